@@ -0,0 +1,224 @@
+use colored::Colorize;
+use std::fs;
+use std::path::Path;
+
+use crate::config::{ConfigManager, DomainConfig, Paths, SiteKind};
+use crate::core::nginx_parser::{self, Directive};
+use crate::error::{Error, Result};
+use crate::utils::{check_root, get_timestamp, log_info, log_success, log_warning};
+
+pub fn run(file: Option<String>, all: bool) -> Result<()> {
+    check_root()?;
+
+    let mut config_manager = ConfigManager::new()?;
+
+    let files = if all {
+        let mut files: Vec<String> = fs::read_dir(Paths::nginx_sites_available())?
+            .flatten()
+            .filter(|entry| entry.path().is_file())
+            .map(|entry| entry.path().to_string_lossy().to_string())
+            .collect();
+        files.sort();
+        files
+    } else {
+        let file = file.ok_or_else(|| Error::Validation("Pass a FILE or --all".to_string()))?;
+        vec![resolve_file(&file)?]
+    };
+
+    let mut adopted = 0;
+    for path in &files {
+        match adopt_file(&mut config_manager, path) {
+            Ok(Some(site)) => {
+                log_success(&format!("Adopted {} from {}", site.domain, path));
+                adopted += 1;
+            }
+            Ok(None) => {}
+            // One odd file shouldn't stop a bulk adopt
+            Err(e) if all => log_warning(&format!("Skipping {}: {}", path, e)),
+            Err(e) => return Err(e),
+        }
+    }
+
+    println!();
+    println!(
+        "{}",
+        format!("Adopted {} site(s). They are left as written; `setup` takes one over.", adopted)
+            .green()
+    );
+    println!();
+
+    Ok(())
+}
+
+/// Accept a path or a file name in sites-available
+fn resolve_file(file: &str) -> Result<String> {
+    let candidates = [
+        file.to_string(),
+        format!("{}/{}", Paths::nginx_sites_available(), file),
+    ];
+
+    candidates
+        .iter()
+        .find(|path| Path::new(path).is_file())
+        .map(|path| {
+            fs::canonicalize(path)
+                .map(|p| p.to_string_lossy().to_string())
+                .unwrap_or_else(|_| path.clone())
+        })
+        .ok_or_else(|| Error::NotFound(format!("nginx config '{}' not found", file)))
+}
+
+fn adopt_file(config_manager: &mut ConfigManager, path: &str) -> Result<Option<DomainConfig>> {
+    if config_manager
+        .list_domains()
+        .iter()
+        .any(|site| site.config_path == path)
+    {
+        log_info(&format!("{} is already managed", path));
+        return Ok(None);
+    }
+
+    let text = fs::read_to_string(path)?;
+    let mut site = site_from_config(path, &text)?
+        .ok_or_else(|| Error::Validation("no server block with a server_name".to_string()))?;
+
+    if config_manager.get_domain(&site.domain).is_some() {
+        return Err(Error::Validation(format!(
+            "{} is already managed by localstacker",
+            site.domain
+        )));
+    }
+
+    site.created_at = get_timestamp();
+    site.enabled = Path::new(&site.enabled_link()).exists();
+    config_manager.upsert_domain(site.clone())?;
+
+    Ok(Some(site))
+}
+
+/// Build a `DomainConfig` from a hand-written site without touching it
+fn site_from_config(path: &str, text: &str) -> Result<Option<DomainConfig>> {
+    let directives = nginx_parser::parse(text)?;
+    let servers = nginx_parser::server_blocks(&directives);
+
+    // Prefer the server that terminates TLS or proxies, not the port 80 redirect
+    let primary = servers
+        .iter()
+        .filter(|server| primary_name(server).is_some())
+        .max_by_key(|server| {
+            (server.find("ssl_certificate").next().is_some() as u8)
+                + (!server.find_all("proxy_pass").is_empty() as u8)
+        });
+
+    let domain = match primary.and_then(|server| primary_name(server)) {
+        Some(domain) => domain.to_string(),
+        None => return Ok(None),
+    };
+
+    let mut site = DomainConfig {
+        domain: domain.clone(),
+        config_path: path.to_string(),
+        kind: SiteKind::Http,
+        adopted: true,
+        ..Default::default()
+    };
+
+    let matching = servers.iter().filter(|server| {
+        server
+            .find("server_name")
+            .any(|names| names.args.contains(&domain))
+    });
+
+    for server in matching {
+        for listen in server.find("listen") {
+            let port = match listen.arg(0).and_then(nginx_parser::listen_port) {
+                Some(port) => port,
+                None => continue,
+            };
+            let ssl = listen.args.iter().skip(1).any(|param| param == "ssl");
+            let slot = if ssl { &mut site.https_port } else { &mut site.http_port };
+            slot.get_or_insert(port);
+        }
+
+        if let Some(cert) = server.find("ssl_certificate").next().and_then(|d| d.arg(0)) {
+            site.ssl_cert_path = cert.to_string();
+        }
+        if let Some(key) = server.find("ssl_certificate_key").next().and_then(|d| d.arg(0)) {
+            site.ssl_key_path = key.to_string();
+        }
+
+        if site.port == 0 {
+            if let Some(port) = server
+                .find_all("proxy_pass")
+                .iter()
+                .find_map(|proxy| proxy.arg(0).and_then(proxy_port))
+            {
+                site.port = port;
+            }
+        }
+    }
+
+    Ok(Some(site))
+}
+
+/// First concrete name of a server block (no `_`, wildcards or regexes)
+fn primary_name(server: &Directive) -> Option<&str> {
+    server
+        .find("server_name")
+        .flat_map(|names| names.args.iter())
+        .map(String::as_str)
+        .find(|name| *name != "_" && !name.starts_with('~') && !name.contains('*') && name.contains('.'))
+}
+
+/// Port of a local `proxy_pass` target such as `http://127.0.0.1:3000/`
+fn proxy_port(target: &str) -> Option<u16> {
+    let rest = target.split_once("://")?.1;
+    let host = rest.split('/').next()?;
+    let (_, port) = host.rsplit_once(':')?;
+    port.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_site_from_config() {
+        let text = r#"
+server {
+    listen 80;
+    server_name app.test;
+    return 301 https://$host$request_uri;
+}
+
+server {
+    listen 8443 ssl;
+    listen [::]:8443 ssl;
+    server_name app.test www.app.test;
+    ssl_certificate /etc/ssl/app.pem;
+    ssl_certificate_key /etc/ssl/app-key.pem;
+
+    location /api/ {
+        proxy_pass http://127.0.0.1:4000/;
+    }
+}
+"#;
+
+        let site = site_from_config("/etc/nginx/sites-available/myapp", text)
+            .unwrap()
+            .unwrap();
+        assert_eq!(site.domain, "app.test");
+        assert_eq!(site.port, 4000);
+        assert_eq!(site.http_port, Some(80));
+        assert_eq!(site.https_port, Some(8443));
+        assert_eq!(site.ssl_cert_path, "/etc/ssl/app.pem");
+        assert!(site.adopted);
+        assert_eq!(site.enabled_link(), "/etc/nginx/sites-enabled/myapp");
+    }
+
+    #[test]
+    fn test_default_server_is_not_adopted() {
+        let text = "server { listen 80 default_server; server_name _; }";
+        assert!(site_from_config("default", text).unwrap().is_none());
+    }
+}
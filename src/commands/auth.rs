@@ -0,0 +1,211 @@
+use clap::Subcommand;
+use dialoguer::Password;
+
+use crate::commands::setup::check_unmodified;
+use crate::config::{ConfigManager, DomainConfig, Paths, SiteKind};
+use crate::core::{web_server_for, FileOperations, FileOps, HtpasswdFile, Transaction};
+use crate::error::{Error, Result};
+use crate::utils::{
+    check_root, content_hash, log_info, log_success, log_warning, validate_username,
+};
+
+#[derive(Subcommand)]
+pub enum AuthCommand {
+    /// Add a basic auth user (or change their password) and enable auth
+    AddUser {
+        /// Managed domain
+        domain: String,
+
+        /// User name
+        user: String,
+
+        /// Overwrite a site config that was edited by hand
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Remove a basic auth user; auth is turned off when no users are left
+    RemoveUser {
+        /// Managed domain
+        domain: String,
+
+        /// User name
+        user: String,
+
+        /// Overwrite a site config that was edited by hand
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+pub fn run(command: AuthCommand) -> Result<()> {
+    check_root()?;
+
+    match command {
+        AuthCommand::AddUser { domain, user, force } => add_user(&domain, &user, force),
+        AuthCommand::RemoveUser { domain, user, force } => remove_user(&domain, &user, force),
+    }
+}
+
+/// Prompt for a new password twice
+pub fn prompt_password(user: &str) -> Result<String> {
+    let password = Password::new()
+        .with_prompt(format!("Password for {}", user))
+        .with_confirmation("Confirm password", "Passwords do not match")
+        .interact()
+        .map_err(|e| Error::Command(format!("Failed to read password: {}", e)))?;
+
+    if password.is_empty() {
+        return Err(Error::Validation("Password cannot be empty".to_string()));
+    }
+
+    Ok(password)
+}
+
+fn load_http_site(config_manager: &ConfigManager, domain: &str) -> Result<DomainConfig> {
+    let site = config_manager
+        .get_domain(domain)
+        .ok_or_else(|| Error::NotFound(format!("Domain '{}' not found", domain)))?
+        .clone();
+
+    if site.kind != SiteKind::Http {
+        return Err(Error::Validation(format!(
+            "Basic auth is only available for proxied HTTP sites, {} is not one",
+            domain
+        )));
+    }
+
+    if site.adopted {
+        return Err(Error::Validation(format!(
+            "{} is an adopted site, run `localstacker setup` for it first",
+            domain
+        )));
+    }
+
+    let with_auth = DomainConfig { basic_auth: true, ..site.clone() };
+    if web_server_for(site.server).unsupported_features(&with_auth).contains(&"basic auth") {
+        return Err(Error::Validation(match site.template {
+            Some(ref template) => format!(
+                "Basic auth is not available for {}, its template {} can't render it (nginx templates need {{{{auth}}}})",
+                domain, template
+            ),
+            None => format!(
+                "Basic auth is not available for {} sites, {} is served by {}",
+                site.server.display_name(),
+                domain,
+                site.server
+            ),
+        }));
+    }
+
+    Ok(site)
+}
+
+fn add_user(domain: &str, user: &str, force: bool) -> Result<()> {
+    validate_username(user)?;
+
+    let mut config_manager = ConfigManager::new()?;
+    let mut site = load_http_site(&config_manager, domain)?;
+
+    // nginx reads the user file on every request, only the first user needs a reload
+    let enable = !site.basic_auth;
+    site.basic_auth = true;
+    let config = enable.then(|| site_config(&site, force)).transpose()?;
+
+    let password = prompt_password(user)?;
+
+    let mut htpasswd = HtpasswdFile::load(&Paths::htpasswd(site.server, domain))?;
+    let existed = htpasswd.set_user(user, &password)?;
+
+    apply(&mut site, &htpasswd, config)?;
+
+    if existed {
+        log_success(&format!("Password updated for {}", user));
+    } else {
+        log_success(&format!("User {} added", user));
+    }
+
+    if enable {
+        config_manager.upsert_domain(site)?;
+        log_success(&format!("Basic auth enabled for {}", domain));
+    }
+
+    Ok(())
+}
+
+fn remove_user(domain: &str, user: &str, force: bool) -> Result<()> {
+    let mut config_manager = ConfigManager::new()?;
+    let mut site = load_http_site(&config_manager, domain)?;
+
+    let mut htpasswd = HtpasswdFile::load(&Paths::htpasswd(site.server, domain))?;
+
+    if !htpasswd.remove_user(user) {
+        return Err(Error::NotFound(format!(
+            "User '{}' not found for {}",
+            user, domain
+        )));
+    }
+
+    let disable = htpasswd.users().is_empty() && site.basic_auth;
+    if disable {
+        site.basic_auth = false;
+    }
+    let config = disable.then(|| site_config(&site, force)).transpose()?;
+    apply(&mut site, &htpasswd, config)?;
+    log_success(&format!("User {} removed", user));
+
+    if disable {
+        config_manager.upsert_domain(site)?;
+        log_warning(&format!("No users left, basic auth disabled for {}", domain));
+    }
+
+    Ok(())
+}
+
+/// The site's new config, refusing to replace hand edits unless forced
+fn site_config(site: &DomainConfig, force: bool) -> Result<String> {
+    let config = web_server_for(site.server).generate_config(site, site.template.as_deref())?;
+    check_unmodified(site, &config, force)?;
+    Ok(config)
+}
+
+/// Write the user file (removing it once it's empty) and, when auth is
+/// switched on or off, the new site config, then reload the server. A
+/// failing config test or reload restores both.
+fn apply(site: &mut DomainConfig, htpasswd: &HtpasswdFile, config: Option<String>) -> Result<()> {
+    let web_server = web_server_for(site.server);
+    let htpasswd_path = Paths::htpasswd(site.server, &site.domain);
+
+    let mut transaction = Transaction::new();
+    transaction.track(&htpasswd_path)?;
+    if config.is_some() {
+        transaction.track_all(web_server.site_paths(site))?;
+    }
+
+    transaction.run(|| {
+        if htpasswd.users().is_empty() {
+            FileOps::new().remove_file(&htpasswd_path)?;
+        } else {
+            htpasswd.save(site.server)?;
+        }
+
+        if let Some(ref config) = config {
+            log_info(&format!(
+                "Updating {} configuration for {}...",
+                site.server.display_name(),
+                site.domain
+            ));
+            web_server.write_config(site, config)?;
+            web_server.test_config()?;
+            web_server.reload()?;
+        }
+        Ok(())
+    })?;
+
+    if let Some(config) = config {
+        site.config_hash = Some(content_hash(&config));
+        log_success(&format!("{} reloaded", site.server.display_name()));
+    }
+
+    Ok(())
+}
@@ -47,7 +47,7 @@ pub fn run(domain: Option<String>) -> Result<()> {
         }
 
         // Check Nginx config
-        let nginx_exists = file_ops.file_exists(&config.nginx_config_path);
+        let nginx_exists = file_ops.file_exists(&config.config_path);
         print!("  Nginx Config: ");
         if nginx_exists {
             println!("{}", "✓ Present".green());
@@ -56,7 +56,7 @@ pub fn run(domain: Option<String>) -> Result<()> {
         }
 
         // Check if site is enabled
-        let enabled_path = format!("/etc/nginx/sites-enabled/{}", config.domain);
+        let enabled_path = config.enabled_link();
         let is_enabled = file_ops.file_exists(&enabled_path);
         print!("  Site Enabled: ");
         if is_enabled {
@@ -99,7 +99,7 @@ pub fn run(domain: Option<String>) -> Result<()> {
 
 fn check_port_listening(port: u16) -> bool {
     Command::new("ss")
-        .args(&["-ln", &format!("sport = :{}", port)])
+        .args(["-ln", &format!("sport = :{}", port)])
         .output()
         .map(|output| {
             let stdout = String::from_utf8_lossy(&output.stdout);
@@ -110,7 +110,7 @@ fn check_port_listening(port: u16) -> bool {
 
 fn check_https_connectivity(domain: &str) -> bool {
     Command::new("curl")
-        .args(&[
+        .args([
             "-k", // ignore cert validation
             "-s",
             "-o",
@@ -1,6 +1,6 @@
 use colored::Colorize;
 
-use crate::config::ConfigManager;
+use crate::config::{ConfigManager, ServerKind, SiteKind};
 use crate::error::Result;
 
 pub fn run(detailed: bool) -> Result<()> {
@@ -27,23 +27,111 @@ pub fn run(detailed: bool) -> Result<()> {
             "✗".red()
         };
 
+        let mut kind_label = match (domain_config.kind, domain_config.listen_port) {
+            (SiteKind::Tcp, Some(listen)) => format!(" [tcp :{}]", listen),
+            (SiteKind::Tcp, None) => " [tcp]".to_string(),
+            (SiteKind::Redirect, _) => " [redirect]".to_string(),
+            (SiteKind::Http, _) if domain_config.adopted => " [adopted]".to_string(),
+            (SiteKind::Http, _) if domain_config.maintenance => " [maintenance]".to_string(),
+            (SiteKind::Http, _) => String::new(),
+        };
+        if domain_config.server != ServerKind::Nginx {
+            kind_label.push_str(&format!(" [{}]", domain_config.server));
+        }
+
+        let target = match domain_config.redirect {
+            Some(ref redirect) => format!("{} ({})", redirect.to, redirect.code),
+            None => format!("localhost:{}", domain_config.port),
+        };
+
         println!(
-            "{} {} {} {}",
+            "{} {} {} {}{}",
             status_icon,
             domain_config.domain.cyan().bold(),
             "→".dimmed(),
-            format!("localhost:{}", domain_config.port).yellow()
+            target.yellow(),
+            kind_label.dimmed()
         );
 
         if detailed {
+            println!("  {} {}", "URL:".dimmed(), domain_config.url());
             println!("  {} {}", "Created:".dimmed(), domain_config.created_at);
             println!("  {} {}", "SSL Cert:".dimmed(), domain_config.ssl_cert_path);
             println!("  {} {}", "SSL Key:".dimmed(), domain_config.ssl_key_path);
-            println!("  {} {}", "Nginx Config:".dimmed(), domain_config.nginx_config_path);
+            if !domain_config.config_path.is_empty() {
+                println!(
+                    "  {} {}",
+                    format!("{} Config:", domain_config.server.display_name()).dimmed(),
+                    domain_config.config_path
+                );
+            }
             
+            if let Some(ref template) = domain_config.template {
+                let version = domain_config
+                    .template_version
+                    .map(|v| format!(" (v{})", v))
+                    .unwrap_or_default();
+                println!("  {} {}{}", "Template:".dimmed(), template, version);
+            }
+
             if let Some(ref service) = domain_config.service {
                 println!("  {} {}", "Service:".dimmed(), service);
             }
+
+            if let Some(ref redirect) = domain_config.redirect {
+                let path = if redirect.preserve_path { "preserved" } else { "dropped" };
+                println!("  {} {}", "Redirect Path:".dimmed(), path);
+            }
+
+            if domain_config.basic_auth {
+                println!("  {} enabled", "Basic Auth:".dimmed());
+            }
+
+            let access = &domain_config.access;
+            if !access.allow.is_empty() || !access.deny.is_empty() {
+                println!(
+                    "  {} allow [{}] deny [{}]",
+                    "Access:".dimmed(),
+                    access.allow.join(", "),
+                    access.deny.join(", ")
+                );
+            }
+
+            if let Some(ref rate) = access.rate_limit {
+                match access.burst {
+                    Some(burst) => println!("  {} {} (burst {})", "Rate Limit:".dimmed(), rate, burst),
+                    None => println!("  {} {}", "Rate Limit:".dimmed(), rate),
+                }
+            }
+
+            if domain_config.kind == SiteKind::Http {
+                let headers = &domain_config.headers;
+                println!("  {} {:?}", "Header Profile:".dimmed(), headers.profile);
+                for header in &headers.custom {
+                    println!("  {} {}", "Header:".dimmed(), header);
+                }
+                if !headers.cors_origins.is_empty() {
+                    println!("  {} {}", "CORS:".dimmed(), headers.cors_origins.join(", "));
+                }
+            }
+
+            let compression: Vec<&str> = [("gzip", domain_config.gzip), ("brotli", domain_config.brotli)]
+                .iter()
+                .filter(|(_, on)| *on)
+                .map(|(name, _)| *name)
+                .collect();
+            if !compression.is_empty() {
+                println!("  {} {}", "Compression:".dimmed(), compression.join(", "));
+            }
+
+            if let Some(ref ttl) = domain_config.cache_ttl {
+                println!("  {} {}", "Cache:".dimmed(), ttl);
+            }
+
+            if domain_config.http3 {
+                let note = if domain_config.quic_reuseport { " (reuseport)" } else { "" };
+                println!("  {} enabled{}", "HTTP/3:".dimmed(), note);
+            }
             
             println!();
         }
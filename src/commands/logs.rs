@@ -0,0 +1,485 @@
+use clap::Args;
+use colored::{ColoredString, Colorize};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::os::unix::fs::MetadataExt;
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::config::{ConfigManager, DomainConfig, Paths, ServerKind, SiteKind};
+use crate::core::access_log::{parse_access_line, parse_error_time, StatusFilter};
+use crate::error::{Error, Result};
+use crate::utils::{duration_secs, log_info, log_verbose};
+
+/// How often a followed log file is checked for new lines
+const FOLLOW_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How much of the log is read at a time when reading backwards from the end
+const TAIL_CHUNK: usize = 64 * 1024;
+
+#[derive(Args)]
+pub struct LogsArgs {
+    /// Managed domain
+    pub domain: String,
+
+    /// Show the error log instead of the access log
+    #[arg(long)]
+    pub error: bool,
+
+    /// Keep printing new lines as they are written
+    #[arg(short, long)]
+    pub follow: bool,
+
+    /// Only requests with these status codes, e.g. 5xx, 404 or 500-504
+    #[arg(long, conflicts_with = "error")]
+    pub status: Option<String>,
+
+    /// Only lines from the last 30s, 10m, 2h, 1d...
+    #[arg(long)]
+    pub since: Option<String>,
+
+    /// Number of lines to show when --since isn't given
+    #[arg(long, default_value_t = 50)]
+    pub lines: usize,
+
+    /// Interleave the backend service's journal (needs --service at setup)
+    #[arg(long)]
+    pub journal: bool,
+}
+
+/// A line ready to print, with its timestamp for interleaving
+struct Line {
+    time: Option<i64>,
+    text: String,
+}
+
+/// Filters and formatting for one log file
+struct LogView {
+    error: bool,
+    status: Option<StatusFilter>,
+    since: Option<i64>,
+    utc_offset: i64,
+}
+
+impl LogView {
+    /// `None` when the line is filtered out
+    fn render(&self, raw: &str) -> Option<Line> {
+        if self.error {
+            let time = parse_error_time(raw, self.utc_offset);
+            if let (Some(since), Some(time)) = (self.since, time) {
+                if time < since {
+                    return None;
+                }
+            }
+            return Some(Line { time, text: color_error_line(raw) });
+        }
+
+        let Some(entry) = parse_access_line(raw) else {
+            // Unparsable lines can't be filtered, show them only when unfiltered
+            return (self.status.is_none() && self.since.is_none())
+                .then(|| Line { time: None, text: raw.dimmed().to_string() });
+        };
+        if self.status.as_ref().is_some_and(|filter| !filter.matches(entry.status)) {
+            return None;
+        }
+        if self.since.is_some_and(|since| entry.time < since) {
+            return None;
+        }
+
+        let user = entry
+            .remote_user
+            .as_deref()
+            .map(|user| format!(" {}", user))
+            .unwrap_or_default();
+        Some(Line {
+            time: Some(entry.time),
+            text: format!(
+                "{} {} {} {} {} {}",
+                entry.time_local.dimmed(),
+                color_status(entry.status),
+                entry.method.bold(),
+                entry.path,
+                format!("{}B", entry.bytes).dimmed(),
+                format!("{}{}", entry.remote_addr, user).dimmed()
+            ),
+        })
+    }
+}
+
+fn color_status(status: u16) -> ColoredString {
+    let text = status.to_string();
+    match status {
+        200..=299 => text.green(),
+        300..=399 => text.cyan(),
+        400..=499 => text.yellow(),
+        500..=599 => text.red().bold(),
+        _ => text.normal(),
+    }
+}
+
+fn color_error_line(line: &str) -> String {
+    let severe = ["[error]", "[crit]", "[alert]", "[emerg]", ":error]", ":crit]", ":alert]", ":emerg]"];
+    if severe.iter().any(|level| line.contains(level)) {
+        line.red().to_string()
+    } else if line.contains("[warn]") || line.contains(":warn]") {
+        line.yellow().to_string()
+    } else {
+        line.to_string()
+    }
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+/// Error logs are written in local time without a zone
+fn local_utc_offset() -> i64 {
+    let output = Command::new("date").arg("+%z").output();
+    let zone = output
+        .ok()
+        .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string())
+        .unwrap_or_default();
+
+    let sign = if zone.starts_with('-') { -1 } else { 1 };
+    let digits = zone.trim_start_matches(['+', '-']);
+    match (digits.get(..2).map(str::parse::<i64>), digits.get(2..4).map(str::parse::<i64>)) {
+        (Some(Ok(hours)), Some(Ok(minutes))) => sign * (hours * 3600 + minutes * 60),
+        _ => 0,
+    }
+}
+
+/// The last `count` lines that pass the view, reading backwards from the end
+/// so large logs aren't read whole. Leaves `file` at the end it read from and
+/// returns an unterminated last line separately, it may still be written.
+fn tail(file: &mut File, view: &LogView, count: usize, chunk: usize) -> Result<(Vec<Line>, Vec<u8>)> {
+    let end = file.seek(SeekFrom::End(0))?;
+    let mut position = end;
+    // Bytes of a line whose start hasn't been read yet
+    let mut head: Vec<u8> = Vec::new();
+    let mut partial: Option<Vec<u8>> = None;
+    let mut lines = Vec::new();
+
+    while position > 0 && (partial.is_none() || lines.len() < count) {
+        let size = chunk.min(position as usize);
+        position -= size as u64;
+        let mut bytes = vec![0; size];
+        file.seek(SeekFrom::Start(position))?;
+        file.read_exact(&mut bytes)?;
+        bytes.append(&mut head);
+
+        if partial.is_none() {
+            match bytes.iter().rposition(|&b| b == b'\n') {
+                Some(newline) => partial = Some(bytes.split_off(newline + 1)),
+                None if position == 0 => partial = Some(std::mem::take(&mut bytes)),
+                None => {
+                    head = bytes;
+                    continue;
+                }
+            }
+        }
+
+        // Everything after the first newline is whole lines, unless this is the start of the file
+        let start = match bytes.iter().position(|&b| b == b'\n') {
+            _ if position == 0 => 0,
+            Some(newline) => newline + 1,
+            None => {
+                head = bytes;
+                continue;
+            }
+        };
+        let complete = bytes.split_off(start);
+        head = bytes;
+
+        for raw in String::from_utf8_lossy(&complete).lines().rev() {
+            if lines.len() == count {
+                break;
+            }
+            if let Some(line) = view.render(raw) {
+                lines.push(line);
+            }
+        }
+    }
+
+    file.seek(SeekFrom::Start(end))?;
+    lines.reverse();
+    Ok((lines, partial.unwrap_or_default()))
+}
+
+/// Every line that passes the view, read one at a time. Like `tail`, an
+/// unterminated last line is returned separately.
+fn scan(file: &mut File, view: &LogView) -> Result<(Vec<Line>, Vec<u8>)> {
+    // Reads up to EOF, so nothing is left buffered and `file` ends up there
+    let mut reader = BufReader::new(file);
+    let mut raw = Vec::new();
+    let mut lines = Vec::new();
+    while reader.read_until(b'\n', &mut raw)? > 0 {
+        if raw.last() != Some(&b'\n') {
+            break;
+        }
+        if let Some(line) = view.render(String::from_utf8_lossy(&raw).trim_end()) {
+            lines.push(line);
+        }
+        raw.clear();
+    }
+    Ok((lines, raw))
+}
+
+fn log_path(site: &DomainConfig, error: bool) -> Result<String> {
+    let log_dir = match (site.server, site.kind) {
+        (_, SiteKind::Tcp) => {
+            return Err(Error::Validation(format!(
+                "{} is a TCP site, the stream module doesn't keep per-domain logs",
+                site.domain
+            )))
+        }
+        (ServerKind::Nginx, _) => Paths::nginx_log_dir(),
+        (ServerKind::Apache, SiteKind::Http) => Paths::apache_log_dir(),
+        (server, _) => {
+            return Err(Error::Validation(format!(
+                "Per-domain logs are only written for nginx and Apache sites, {} is served by {}",
+                site.domain,
+                server.display_name()
+            )))
+        }
+    };
+
+    let suffix = if error { "error" } else { "access" };
+    Ok(format!("{}/{}_{}.log", log_dir, site.domain, suffix))
+}
+
+/// Journal lines of the backend service, `-o short-unix` starts with the timestamp
+fn journal_line(service: &str, raw: &str) -> Line {
+    let time = raw
+        .split_whitespace()
+        .next()
+        .and_then(|stamp| stamp.split('.').next())
+        .and_then(|secs| secs.parse().ok());
+    Line {
+        time,
+        text: format!("{} {}", format!("[{}]", service).magenta(), raw),
+    }
+}
+
+fn journal_history(service: &str, since: Option<i64>, lines: usize) -> Result<Vec<Line>> {
+    let since_arg = since.map(|since| format!("@{}", since));
+    let lines_arg = lines.to_string();
+    let mut args = vec!["-u", service, "-o", "short-unix", "--no-pager", "-q"];
+    match since_arg {
+        Some(ref since) => args.extend(["--since", since.as_str()]),
+        None => args.extend(["-n", lines_arg.as_str()]),
+    }
+
+    let output = Command::new("journalctl")
+        .args(&args)
+        .output()
+        .map_err(|e| Error::Command(format!("Failed to run journalctl: {}", e)))?;
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|raw| journal_line(service, raw))
+        .collect())
+}
+
+/// Stream new journal lines over a channel until the receiver goes away
+fn spawn_journal_follow(service: &str) -> Result<mpsc::Receiver<Line>> {
+    let mut child = Command::new("journalctl")
+        .args(["-u", service, "-o", "short-unix", "--no-pager", "-q", "-f", "-n", "0"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| Error::Command(format!("Failed to run journalctl: {}", e)))?;
+    let stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| Error::Command("journalctl has no output".to_string()))?;
+
+    let (sender, receiver) = mpsc::channel();
+    let service = service.to_string();
+    thread::spawn(move || {
+        for raw in BufReader::new(stdout).lines().map_while(|line| line.ok()) {
+            if sender.send(journal_line(&service, &raw)).is_err() {
+                break;
+            }
+        }
+        let _ = child.kill();
+    });
+    Ok(receiver)
+}
+
+pub fn run(args: LogsArgs) -> Result<()> {
+    let config_manager = ConfigManager::new()?;
+    let site = config_manager
+        .get_domain(&args.domain)
+        .ok_or_else(|| Error::NotFound(format!("Domain '{}' not found", args.domain)))?
+        .clone();
+
+    let service = match (args.journal, site.service.as_deref()) {
+        (true, Some(service)) => Some(service.to_string()),
+        (true, None) => {
+            return Err(Error::Validation(format!(
+                "{} has no backend service, set one with `setup --service`",
+                site.domain
+            )))
+        }
+        (false, _) => None,
+    };
+
+    let since = match args.since {
+        Some(ref since) => Some(now() - duration_secs(since)? as i64),
+        None => None,
+    };
+    let view = LogView {
+        error: args.error,
+        status: args.status.as_deref().map(StatusFilter::parse).transpose()?,
+        since,
+        utc_offset: if args.error { local_utc_offset() } else { 0 },
+    };
+
+    let path = log_path(&site, args.error)?;
+    log_verbose(&format!("Reading {}", path));
+    let mut file = File::open(&path)
+        .map_err(|e| Error::NotFound(format!("Can't read {}: {}", path, e)))?;
+
+    let (mut lines, partial) = match since {
+        Some(_) => scan(&mut file, &view)?,
+        None => tail(&mut file, &view, args.lines, TAIL_CHUNK)?,
+    };
+
+    if let Some(ref service) = service {
+        lines.extend(journal_history(service, since, args.lines)?);
+        // Stable, so lines without a timestamp keep their place
+        let mut last = i64::MIN;
+        let mut keyed: Vec<(i64, Line)> = lines
+            .into_iter()
+            .map(|line| {
+                last = line.time.unwrap_or(last);
+                (last, line)
+            })
+            .collect();
+        keyed.sort_by_key(|(time, _)| *time);
+        lines = keyed.into_iter().map(|(_, line)| line).collect();
+    }
+
+    for line in &lines {
+        println!("{}", line.text);
+    }
+
+    if !args.follow {
+        return Ok(());
+    }
+
+    log_info(&format!("Following {} (Ctrl+C to stop)", path));
+    let journal = service.as_deref().map(spawn_journal_follow).transpose()?;
+    follow(&path, file, partial, &view, journal)
+}
+
+/// Print lines appended to `path`, reopening it after rotation. `pending` is
+/// the start of a line that was still being written when reading stopped.
+fn follow(
+    path: &str,
+    mut file: File,
+    mut pending: Vec<u8>,
+    view: &LogView,
+    journal: Option<mpsc::Receiver<Line>>,
+) -> Result<()> {
+    let mut position = file.stream_position()?;
+    let mut inode = file.metadata()?.ino();
+
+    loop {
+        match journal.as_ref().map(|journal| (journal, journal.recv_timeout(FOLLOW_INTERVAL))) {
+            Some((journal, Ok(line))) => {
+                println!("{}", line.text);
+                while let Ok(line) = journal.try_recv() {
+                    println!("{}", line.text);
+                }
+            }
+            Some((_, Err(RecvTimeoutError::Timeout))) => {}
+            Some((_, Err(RecvTimeoutError::Disconnected))) | None => thread::sleep(FOLLOW_INTERVAL),
+        }
+
+        // logrotate moves the file away or truncates it
+        if let Ok(metadata) = fs::metadata(path) {
+            if metadata.ino() != inode || metadata.len() < position {
+                if let Ok(reopened) = File::open(path) {
+                    file = reopened;
+                    inode = metadata.ino();
+                    position = 0;
+                    pending.clear();
+                }
+            }
+        }
+
+        file.seek(SeekFrom::Start(position))?;
+        let mut bytes = Vec::new();
+        position += file.read_to_end(&mut bytes)? as u64;
+        pending.extend_from_slice(&bytes);
+
+        // Keep a partially written last line for the next round, decoding only
+        // whole lines so a character split across reads isn't mangled
+        while let Some(end) = pending.iter().position(|&b| b == b'\n') {
+            let raw: Vec<u8> = pending.drain(..=end).collect();
+            if let Some(line) = view.render(String::from_utf8_lossy(&raw).trim_end()) {
+                println!("{}", line.text);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tail_reads_backwards() {
+        let path = std::env::temp_dir().join(format!("localstacker-logs-{}", std::process::id()));
+        let contents: String = (1..=20).map(|i| format!("ligne {} é\n", i)).collect();
+        fs::write(&path, &contents).unwrap();
+        let view = LogView { error: true, status: None, since: None, utc_offset: 0 };
+
+        // Small chunks split lines and the two-byte character across reads
+        for chunk in [1, 3, 7, 1024] {
+            let mut file = File::open(&path).unwrap();
+            let (lines, partial) = tail(&mut file, &view, 3, chunk).unwrap();
+            let texts: Vec<&str> = lines.iter().map(|line| line.text.as_str()).collect();
+            assert_eq!(texts, ["ligne 18 é", "ligne 19 é", "ligne 20 é"], "chunk {}", chunk);
+            assert_eq!(file.stream_position().unwrap(), contents.len() as u64);
+            assert!(partial.is_empty());
+
+            let (all, _) = tail(&mut file, &view, 50, chunk).unwrap();
+            assert_eq!(all.len(), 20);
+            assert_eq!(all[0].text, "ligne 1 é");
+        }
+
+        // A last line still being written is left for `follow` to finish
+        fs::write(&path, "one\ntwo é").unwrap();
+        for chunk in [1, 2, 1024] {
+            let mut file = File::open(&path).unwrap();
+            let (lines, partial) = tail(&mut file, &view, 5, chunk).unwrap();
+            let texts: Vec<&str> = lines.iter().map(|line| line.text.as_str()).collect();
+            assert_eq!(texts, ["one"], "chunk {}", chunk);
+            assert_eq!(partial, "two é".as_bytes());
+            assert_eq!(file.stream_position().unwrap(), 10);
+
+            // Even when no lines are asked for
+            let (lines, partial) = tail(&mut file, &view, 0, chunk).unwrap();
+            assert!(lines.is_empty());
+            assert_eq!(partial, "two é".as_bytes());
+        }
+
+        let mut file = File::open(&path).unwrap();
+        let (lines, partial) = scan(&mut file, &view).unwrap();
+        assert_eq!(lines.len(), 1);
+        assert_eq!(partial, "two é".as_bytes());
+        assert_eq!(file.stream_position().unwrap(), 10);
+
+        fs::write(&path, "two").unwrap();
+        let (lines, partial) = tail(&mut File::open(&path).unwrap(), &view, 5, 1).unwrap();
+        assert!(lines.is_empty());
+        assert_eq!(partial, b"two");
+        fs::remove_file(&path).unwrap();
+    }
+}
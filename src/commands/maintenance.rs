@@ -0,0 +1,118 @@
+use clap::Subcommand;
+
+use crate::commands::setup::check_unmodified;
+use crate::config::{ConfigManager, SiteKind};
+use crate::core::{web_server_for, Transaction};
+use crate::error::{Error, Result};
+use crate::utils::{check_root, content_hash, log_info, log_success, log_warning};
+
+#[derive(Subcommand)]
+pub enum MaintenanceCommand {
+    /// Serve a 503 maintenance page instead of proxying
+    On {
+        /// Managed domain
+        domain: String,
+
+        /// Overwrite a site config that was edited by hand
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Resume proxying to the backend
+    Off {
+        /// Managed domain
+        domain: String,
+
+        /// Overwrite a site config that was edited by hand
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+pub fn run(command: MaintenanceCommand) -> Result<()> {
+    check_root()?;
+
+    match command {
+        MaintenanceCommand::On { domain, force } => set(&domain, true, force),
+        MaintenanceCommand::Off { domain, force } => set(&domain, false, force),
+    }
+}
+
+fn set(domain: &str, on: bool, force: bool) -> Result<()> {
+    let mut config_manager = ConfigManager::new()?;
+    let mut site = config_manager
+        .get_domain(domain)
+        .ok_or_else(|| Error::NotFound(format!("Domain '{}' not found", domain)))?
+        .clone();
+
+    if site.kind != SiteKind::Http {
+        return Err(Error::Validation(format!(
+            "Maintenance mode is only available for proxied HTTP sites, {} is not one",
+            domain
+        )));
+    }
+
+    if site.adopted {
+        return Err(Error::Validation(format!(
+            "{} is an adopted site, run `localstacker setup` for it first",
+            domain
+        )));
+    }
+
+    if site.maintenance == on {
+        log_warning(&format!(
+            "Maintenance mode is already {} for {}",
+            if on { "on" } else { "off" },
+            domain
+        ));
+        return Ok(());
+    }
+
+    site.maintenance = on;
+
+    let web_server = web_server_for(site.server);
+    if on && web_server.unsupported_features(&site).contains(&"maintenance mode") {
+        return Err(Error::Validation(match site.template {
+            Some(ref template) => format!(
+                "Maintenance mode is not available for {}, its template {} can't render it (nginx templates need {{{{maintenance}}}})",
+                domain, template
+            ),
+            None => format!(
+                "Maintenance mode is not available for {} sites",
+                site.server.display_name()
+            ),
+        }));
+    }
+    let config = web_server.generate_config(&site, site.template.as_deref())?;
+    check_unmodified(&site, &config, force)?;
+    // HAProxy inlines site configs into its shared config
+    config_manager.domains.insert(domain.to_string(), site.clone());
+
+    let mut transaction = Transaction::new();
+    transaction.track_all(web_server.site_paths(&site))?;
+    transaction.track_all(web_server.shared_paths())?;
+
+    log_info(&format!(
+        "Updating {} configuration for {}...",
+        site.server.display_name(),
+        domain
+    ));
+    transaction.run(|| {
+        web_server.write_config(&site, &config)?;
+        web_server.write_shared_config(&config_manager.list_domains())?;
+        web_server.test_config()?;
+        web_server.reload()
+    })?;
+    site.config_hash = Some(content_hash(&config));
+    log_success(&format!("{} reloaded", site.server.display_name()));
+
+    config_manager.upsert_domain(site)?;
+
+    if on {
+        log_success(&format!("{} is now serving the maintenance page", domain));
+    } else {
+        log_success(&format!("{} is proxying to its backend again", domain));
+    }
+
+    Ok(())
+}
@@ -2,4 +2,12 @@ pub mod setup;
 pub mod list;
 pub mod remove;
 pub mod status;
-pub mod install_mkcert;
\ No newline at end of file
+pub mod install_mkcert;
+pub mod auth;
+pub mod redirect;
+pub mod maintenance;
+pub mod adopt;
+pub mod regenerate;
+pub mod serve;
+pub mod template;
+pub mod logs;
\ No newline at end of file
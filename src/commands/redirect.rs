@@ -0,0 +1,289 @@
+use clap::Args;
+use colored::Colorize;
+use dialoguer::Confirm;
+
+use crate::commands::setup::{check_port_owners, check_unmodified, install_certificate, reassigned_configs};
+use crate::config::{ConfigManager, DomainConfig, Paths, RedirectTarget, ServerKind, SiteKind};
+use crate::core::{web_server_for, FileOperations, FileOps, MkcertProvider, Transaction};
+use crate::error::{Error, Result};
+use crate::utils::{
+    check_root, content_hash, get_timestamp, log_info, log_success, log_warning, validate_domain,
+};
+
+#[derive(Args)]
+pub struct RedirectArgs {
+    /// Domain to redirect from
+    pub from: String,
+
+    /// Domain or URL to redirect to
+    pub to: String,
+
+    /// HTTP status code of the redirect (301, 302, 307 or 308)
+    #[arg(long, default_value_t = 301, value_parser = parse_code)]
+    pub code: u16,
+
+    /// Keep the request path and query string
+    #[arg(long)]
+    pub preserve_path: bool,
+
+    /// Skip confirmation prompts
+    #[arg(short = 'y', long = "yes")]
+    pub skip_confirm: bool,
+
+    /// Web server to configure (remembered per domain, default nginx)
+    #[arg(long, value_enum)]
+    pub server: Option<ServerKind>,
+
+    /// Overwrite configs that were edited by hand
+    #[arg(long)]
+    pub force: bool,
+}
+
+pub fn run(args: RedirectArgs) -> Result<()> {
+    let RedirectArgs {
+        from,
+        to,
+        code,
+        preserve_path,
+        skip_confirm,
+        server,
+        force,
+    } = args;
+
+    check_root()?;
+    validate_domain(&from)?;
+
+    let mut config_manager = ConfigManager::new()?;
+    let existing = config_manager.get_domain(&from).cloned();
+    let target = resolve_target(&to, &config_manager)?;
+
+    if target.trim_start_matches("https://").trim_start_matches("http://") == from {
+        return Err(Error::Validation(format!("{} cannot redirect to itself", from)));
+    }
+
+    let server = match (server, existing.as_ref()) {
+        (Some(server), Some(e)) if server != e.server => {
+            return Err(Error::Validation(format!(
+                "{} is served by {}; run `localstacker remove {}` first to switch to {}",
+                from, e.server, from, server
+            )));
+        }
+        (Some(server), _) => server,
+        (None, Some(e)) => e.server,
+        (None, None) => ServerKind::Nginx,
+    };
+
+    // A proxy or TCP site being replaced; its own files go once the redirect is live
+    let previous = existing.clone().filter(|e| e.kind != SiteKind::Redirect);
+    if let Some(ref previous) = previous {
+        if previous.adopted && previous.kind == SiteKind::Tcp {
+            return Err(Error::Validation(format!(
+                "{} is an adopted TCP site, run `localstacker remove {}` first",
+                from, from
+            )));
+        }
+    }
+
+    log_info(&format!("Redirecting {} -> {} ({})", from, target, code));
+
+    if !skip_confirm {
+        let mut prompt = format!(
+            "This will:\n  \
+            • Generate SSL certificate for {}\n  \
+            • Create a redirect-only {server} site\n  \
+            • Reload {server}",
+            from,
+            server = server.display_name()
+        );
+        if existing.as_ref().is_some_and(|e| e.kind != SiteKind::Redirect) {
+            prompt.push_str(&format!("\n  • Replace the existing proxy for {}", from));
+        }
+        prompt.push_str("\n\n  Continue?");
+
+        let confirm = Confirm::new()
+            .with_prompt(prompt)
+            .default(true)
+            .interact()
+            .unwrap_or(false);
+
+        if !confirm {
+            log_warning("Redirect setup cancelled by user");
+            return Ok(());
+        }
+    }
+
+    let cert_provider = MkcertProvider::new();
+    let web_server = web_server_for(server);
+    let file_ops = FileOps::new();
+
+    let mut site = DomainConfig {
+        domain: from.clone(),
+        ssl_cert_path: Paths::ssl_cert(server, &from),
+        ssl_key_path: Paths::ssl_key(server, &from),
+        config_path: match existing {
+            Some(ref e) if e.adopted && e.kind == SiteKind::Http => e.config_path.clone(),
+            _ => Paths::site_config(server, SiteKind::Redirect, &from),
+        },
+        created_at: get_timestamp(),
+        enabled: true,
+        kind: SiteKind::Redirect,
+        http_port: existing.as_ref().and_then(|e| e.http_port),
+        https_port: existing.as_ref().and_then(|e| e.https_port),
+        redirect: Some(RedirectTarget {
+            to: target.clone(),
+            code,
+            preserve_path,
+        }),
+        server,
+        ..Default::default()
+    };
+    check_port_owners(&config_manager, &site)?;
+    let config = web_server.generate_config(&site, None)?;
+    // An adopted config taken over in place counts as hand-written
+    if let Some(ref e) = existing {
+        if e.config_path == site.config_path {
+            check_unmodified(e, &config, force)?;
+        }
+    }
+    site.config_hash = Some(content_hash(&config));
+
+    // A former proxy site may have held QUIC reuseport or shared zones
+    config_manager.domains.insert(from.clone(), site.clone());
+    let reassigned: Vec<String> = config_manager
+        .rebalance_quic_reuseport()
+        .into_iter()
+        .filter(|d| *d != from)
+        .collect();
+    let reassigned = reassigned_configs(&config_manager, &reassigned, force)?;
+
+    let mut transaction = Transaction::new();
+    transaction.track_all([&site.ssl_cert_path, &site.ssl_key_path])?;
+    transaction.track_all(web_server.site_paths(&site))?;
+    if let Some(ref previous) = previous {
+        transaction.track_all(web_server.site_paths(previous))?;
+        transaction.track(&Paths::htpasswd(server, &from))?;
+    }
+    transaction.track_all(web_server.shared_paths())?;
+    for (other, _) in &reassigned {
+        transaction.track_all(web_server_for(other.server).site_paths(other))?;
+    }
+
+    transaction.run(|| {
+        install_certificate(&cert_provider, &file_ops, &site)?;
+
+        // A TCP site lives in the stream config, take it out of there
+        if let Some(ref previous) = previous {
+            if previous.config_path != site.config_path {
+                web_server.disable_site(previous)?;
+                for path in [
+                    &previous.config_path,
+                    &Paths::disabled_config(&previous.config_path),
+                ] {
+                    file_ops.remove_file(path)?;
+                }
+                log_success(&format!("TCP site for {} removed", from));
+            }
+            file_ops.remove_file(&Paths::htpasswd(server, &from))?;
+        }
+
+        web_server.write_config(&site, &config)?;
+        log_success(&format!("{} redirect configuration created", server.display_name()));
+
+        for (other, config) in &reassigned {
+            log_info(&format!("Moving QUIC reuseport to {}...", other.domain));
+            web_server_for(other.server).write_config(other, config)?;
+        }
+        web_server.write_shared_config(&config_manager.list_domains())?;
+
+        web_server.enable_site(&site)?;
+        log_success("Site enabled");
+
+        web_server.test_config()?;
+        log_success(&format!("{} configuration test passed", server.display_name()));
+
+        web_server.reload()?;
+        log_success(&format!("{} reloaded", server.display_name()));
+
+        Ok(())
+    })?;
+
+    for (other, config) in &reassigned {
+        if let Some(other) = config_manager.domains.get_mut(&other.domain) {
+            other.config_hash = Some(content_hash(config));
+        }
+    }
+    config_manager.upsert_domain(site.clone())?;
+
+    // The server no longer references these, remove them only now
+    if previous.is_some() {
+        file_ops.remove_directory(&Paths::error_pages_dir(&from))?;
+        file_ops.remove_directory(&Paths::nginx_cache_dir(&from))?;
+    }
+
+    println!();
+    println!(
+        "{} {} {} {}",
+        "✓".green().bold(),
+        site.url().cyan(),
+        "⇒".dimmed(),
+        target.yellow()
+    );
+    println!();
+
+    Ok(())
+}
+
+fn parse_code(value: &str) -> std::result::Result<u16, String> {
+    match value.parse::<u16>() {
+        Ok(code @ (301 | 302 | 307 | 308)) => Ok(code),
+        _ => Err("redirect code must be 301, 302, 307 or 308".to_string()),
+    }
+}
+
+/// Turn TO into an absolute URL. Managed domains use their own URL (and
+/// port), bare hostnames get https, explicit URLs are used as given.
+fn resolve_target(to: &str, config_manager: &ConfigManager) -> Result<String> {
+    // nginx would expand `$variables` in the `return` target
+    if to.chars().any(|c| c.is_whitespace() || matches!(c, ';' | '"' | '\'' | '{' | '}' | '$')) {
+        return Err(Error::Validation(format!("Invalid redirect target '{}'", to)));
+    }
+
+    let url = if to.contains("://") {
+        to.to_string()
+    } else if let Some(site) = config_manager.get_domain(to) {
+        site.url()
+    } else {
+        validate_domain(to.split(':').next().unwrap_or(to))?;
+        format!("https://{}", to)
+    };
+
+    Ok(url.trim_end_matches('/').to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_target() {
+        let mut config_manager = ConfigManager::default();
+        config_manager.domains.insert(
+            "app.test".to_string(),
+            DomainConfig {
+                domain: "app.test".to_string(),
+                https_port: Some(8443),
+                ..Default::default()
+            },
+        );
+
+        assert_eq!(resolve_target("app.test", &config_manager).unwrap(), "https://app.test:8443");
+        assert_eq!(resolve_target("other.test", &config_manager).unwrap(), "https://other.test");
+        assert_eq!(
+            resolve_target("http://example.com/docs/", &config_manager).unwrap(),
+            "http://example.com/docs"
+        );
+        assert!(resolve_target("evil.test; return 200", &config_manager).is_err());
+        assert!(resolve_target("https://example.com/$host", &config_manager).is_err());
+        assert!(parse_code("303").is_err());
+    }
+}
@@ -0,0 +1,116 @@
+use colored::Colorize;
+use std::fs;
+
+use crate::commands::setup::is_locally_modified;
+use crate::config::{ConfigManager, DomainConfig, ServerKind};
+use crate::core::templates;
+use crate::core::{web_server_for, Transaction};
+use crate::error::{Error, Result};
+use crate::utils::{check_root, content_hash, log_info, log_success, log_warning};
+
+/// Rewrite managed site configs from their stored settings, e.g. after a
+/// template or TLS policy change
+pub fn run(domain: Option<String>, all: bool, force: bool) -> Result<()> {
+    check_root()?;
+
+    let mut config_manager = ConfigManager::new()?;
+
+    let candidates: Vec<DomainConfig> = if all {
+        let mut sites: Vec<_> = config_manager.list_domains().into_iter().cloned().collect();
+        sites.sort_by(|a, b| a.domain.cmp(&b.domain));
+        sites
+    } else {
+        let domain = domain.ok_or_else(|| Error::Validation("Pass a DOMAIN or --all".to_string()))?;
+        let site = config_manager
+            .get_domain(&domain)
+            .ok_or_else(|| Error::NotFound(format!("Domain '{}' not found", domain)))?;
+        if site.adopted {
+            return Err(Error::Validation(format!(
+                "{} is an adopted site, run `localstacker setup` for it first",
+                domain
+            )));
+        }
+        vec![site.clone()]
+    };
+
+    // Generate everything up front so a bad template fails before any write
+    let mut sites = Vec::new();
+    for mut site in candidates {
+        if site.adopted {
+            log_info(&format!("Skipping adopted site {}", site.domain));
+            continue;
+        }
+
+        let web_server = web_server_for(site.server);
+        let config = web_server.generate_config(&site, site.template.as_deref())?;
+
+        if let Ok(current) = fs::read_to_string(&site.config_path) {
+            if current != config
+                && is_locally_modified(&current, site.config_hash.as_deref(), true)
+                && !force
+            {
+                log_warning(&format!(
+                    "Skipping {}: {} was changed outside localstacker, use --force to overwrite it",
+                    site.domain, site.config_path
+                ));
+                continue;
+            }
+        }
+
+        // Record the template version the config now comes from
+        site.template_version = match site.template {
+            Some(ref template) => Some(templates::resolve(template)?.version),
+            None => None,
+        };
+        sites.push((site, config));
+    }
+
+    if sites.is_empty() {
+        log_warning("Nothing to regenerate");
+        return Ok(());
+    }
+
+    let mut servers: Vec<ServerKind> = sites.iter().map(|(site, _)| site.server).collect();
+    servers.sort_by_key(|server| server.to_string());
+    servers.dedup();
+
+    let mut transaction = Transaction::new();
+    for (site, _) in &sites {
+        transaction.track_all(web_server_for(site.server).site_paths(site))?;
+    }
+    for server in &servers {
+        transaction.track_all(web_server_for(*server).shared_paths())?;
+    }
+
+    transaction.run(|| {
+        for (site, config) in &sites {
+            log_info(&format!(
+                "Regenerating {} configuration for {}...",
+                site.server.display_name(),
+                site.domain
+            ));
+            web_server_for(site.server).write_config(site, config)?;
+        }
+
+        for server in &servers {
+            let web_server = web_server_for(*server);
+            web_server.write_shared_config(&config_manager.list_domains())?;
+            web_server.test_config()?;
+            web_server.reload()?;
+            log_success(&format!("{} reloaded", server.display_name()));
+        }
+        Ok(())
+    })?;
+
+    let count = sites.len();
+    for (mut site, config) in sites {
+        site.config_hash = Some(content_hash(&config));
+        config_manager.upsert_domain(site)?;
+    }
+
+    println!();
+    println!("{}", format!("Regenerated {} site(s).", count).green());
+    println!();
+
+    Ok(())
+}
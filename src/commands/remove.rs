@@ -1,12 +1,13 @@
 use colored::Colorize;
 use dialoguer::Confirm;
 
-use crate::config::ConfigManager;
-use crate::core::{FileOps, FileOperations, NginxConfig, WebServerConfig};
+use crate::commands::setup::reassigned_configs;
+use crate::config::{ConfigManager, DomainConfig, Paths};
+use crate::core::{web_server_for, FileOps, FileOperations, Transaction};
 use crate::error::Result;
-use crate::utils::{check_root, log_info, log_success, log_warning};
+use crate::utils::{check_root, content_hash, log_info, log_success, log_warning};
 
-pub fn run(domain: String, skip_confirm: bool, remove_certs: bool) -> Result<()> {
+pub fn run(domain: String, skip_confirm: bool, remove_certs: bool, force: bool) -> Result<()> {
     check_root()?;
 
     let mut config_manager = ConfigManager::new()?;
@@ -38,36 +39,95 @@ pub fn run(domain: String, skip_confirm: bool, remove_certs: bool) -> Result<()>
         }
     }
 
-    let web_server = NginxConfig::new();
-    let file_ops = FileOps::new();
+    if domain_config.adopted {
+        return forget_adopted(&mut config_manager, &domain_config, remove_certs);
+    }
 
-    // Disable site
-    web_server.disable_site(&domain)?;
-    log_success("Site disabled");
+    let server = domain_config.server;
+    let web_server = web_server_for(server);
+    let file_ops = FileOps::new();
 
-    // Remove nginx config
-    if file_ops.file_exists(&domain_config.nginx_config_path) {
-        file_ops.remove_file(&domain_config.nginx_config_path)?;
-        log_success("Nginx configuration removed");
+    // Hand the QUIC reuseport flag over to another HTTP/3 site
+    let mut reassigned = Vec::new();
+    if domain_config.quic_reuseport {
+        if let Some(site) = config_manager.domains.get_mut(&domain) {
+            site.http3 = false;
+        }
+        reassigned = config_manager.rebalance_quic_reuseport();
+        reassigned.retain(|other| *other != domain);
+    }
+    let reassigned = reassigned_configs(&config_manager, &reassigned, force)?;
+
+    let remaining: Vec<_> = config_manager
+        .list_domains()
+        .into_iter()
+        .filter(|site| site.domain != domain)
+        .collect();
+
+    // Back up everything nginx reads so a failing `nginx -t` leaves it untouched
+    let mut transaction = Transaction::new();
+    transaction.track_all(web_server.site_paths(&domain_config))?;
+    transaction.track_all(web_server.shared_paths())?;
+    for (other, _) in &reassigned {
+        transaction.track_all(web_server_for(other.server).site_paths(other))?;
     }
 
-    // Remove certificates if requested
-    if remove_certs {
-        if file_ops.file_exists(&domain_config.ssl_cert_path) {
-            file_ops.remove_file(&domain_config.ssl_cert_path)?;
+    transaction.run(|| {
+        // Disable site
+        web_server.disable_site(&domain_config)?;
+        log_success("Site disabled");
+
+        // In conf.d mode disabling renamed the file to `*.disabled`
+        let disabled = Paths::disabled_config(&domain_config.config_path);
+        for path in [&domain_config.config_path, &disabled] {
+            if file_ops.file_exists(path) {
+                file_ops.remove_file(path)?;
+                log_success(&format!("{} configuration removed", server.display_name()));
+            }
         }
-        if file_ops.file_exists(&domain_config.ssl_key_path) {
-            file_ops.remove_file(&domain_config.ssl_key_path)?;
+
+        for (other, config) in &reassigned {
+            log_info(&format!("Moving QUIC reuseport to {}...", other.domain));
+            web_server_for(other.server).write_config(other, config)?;
         }
+
+        web_server.write_shared_config(&remaining)?;
+
+        // Test and reload the server
+        web_server.test_config()?;
+        web_server.reload()?;
+        log_success(&format!("{} reloaded", server.display_name()));
+
+        Ok(())
+    })?;
+
+    // Nginx no longer references these, remove them only now
+    file_ops.remove_directory(&Paths::error_pages_dir(&domain))?;
+
+    // Remove basic auth users
+    if domain_config.basic_auth {
+        file_ops.remove_file(&Paths::htpasswd(domain_config.server, &domain))?;
+    }
+
+    // Remove cached responses
+    if domain_config.cache_ttl.is_some() {
+        file_ops.remove_directory(&Paths::nginx_cache_dir(&domain))?;
+        log_success("Proxy cache removed");
+    }
+
+    // Remove the server's certificate copies, and the certificates if requested
+    web_server.remove_certs(&domain_config, remove_certs)?;
+    if remove_certs {
         log_success("SSL certificates removed");
     } else {
         log_info("SSL certificates kept (use --remove-certs to delete them)");
     }
 
-    // Test and reload nginx
-    web_server.test_config()?;
-    web_server.reload()?;
-    log_success("Nginx reloaded");
+    for (other, config) in &reassigned {
+        if let Some(site) = config_manager.domains.get_mut(&other.domain) {
+            site.config_hash = Some(content_hash(config));
+        }
+    }
 
     // Remove from config
     config_manager.remove_domain(&domain)?;
@@ -78,4 +138,32 @@ pub fn run(domain: String, skip_confirm: bool, remove_certs: bool) -> Result<()>
     println!();
 
     Ok(())
-}
\ No newline at end of file
+}
+
+/// An adopted site keeps running from its hand-written config, so only
+/// localstacker's record of it goes away
+fn forget_adopted(config_manager: &mut ConfigManager, site: &DomainConfig, remove_certs: bool) -> Result<()> {
+    log_info(&format!(
+        "Keeping adopted config {}, {} is left running",
+        site.config_path,
+        site.server.display_name()
+    ));
+
+    if remove_certs {
+        web_server_for(site.server).remove_certs(site, true)?;
+    }
+
+    config_manager.remove_domain(&site.domain)?;
+    log_success("Configuration removed");
+
+    println!();
+    println!(
+        "{} {}",
+        "✓".green().bold(),
+        format!("{} is no longer managed by localstacker", site.domain).green()
+    );
+    println!();
+
+    Ok(())
+}
+
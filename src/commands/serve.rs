@@ -0,0 +1,30 @@
+use std::fs;
+
+use crate::config::Paths;
+use crate::core::{proxy_pid, run_proxy};
+use crate::error::{Error, Result};
+use crate::utils::{check_root, log_info, log_warning};
+
+pub fn run() -> Result<()> {
+    check_root()?;
+
+    if let Some(pid) = proxy_pid() {
+        return Err(Error::Validation(format!(
+            "The built-in proxy is already running (pid {})",
+            pid
+        )));
+    }
+
+    log_info("Starting the built-in TLS reverse proxy...");
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?;
+
+    if let Err(e) = fs::write(Paths::serve_pid_file(), format!("{}\n", std::process::id())) {
+        log_warning(&format!("Failed to write {}: {}", Paths::serve_pid_file(), e));
+    }
+    let result = runtime.block_on(run_proxy());
+    let _ = fs::remove_file(Paths::serve_pid_file());
+
+    result
+}
@@ -1,24 +1,160 @@
+use clap::Args;
 use colored::Colorize;
 use dialoguer::Confirm;
 
-use crate::config::{ConfigManager, DomainConfig, Paths};
+use crate::config::{
+    AccessPolicy, ConfigManager, DomainConfig, HeaderPolicy, HeaderProfile, Paths, ServerKind,
+    Settings, SiteKind,
+};
+use crate::commands::auth::prompt_password;
 use crate::core::{
-    CertificateProvider, FileOperations, MkcertProvider, NginxConfig, SystemService,
-    SystemdService, WebServerConfig, FileOps,
+    web_server_for, CertificateProvider, FileOperations, HtpasswdFile, MkcertProvider,
+    NginxCapabilities, SystemService, SystemdService, Transaction, FileOps,
 };
-use crate::error::{Result};
+use crate::core::nginx_conflicts::{self, ConflictKind, ServerInfo};
+use crate::core::templates::{self, TemplateSource};
+use crate::error::{Error, Result};
 use crate::utils::{
-    check_root, get_timestamp, log_info, log_success, log_warning,
-    validate_domain, validate_port,
+    check_root, content_hash, get_timestamp, log_info, log_success, log_warning, print_diff,
+    parse_header, validate_access_source, validate_domain, validate_duration, validate_origin,
+    validate_port, validate_rate, validate_username,
 };
 
-pub fn run(
-    domain: String,
-    port: u16,
-    service: Option<String>,
-    skip_confirm: bool,
-    template: Option<String>,
-) -> Result<()> {
+#[derive(Args)]
+pub struct SetupArgs {
+    /// Domain name to setup
+    #[arg(long)]
+    pub domain: String,
+
+    /// Backend port to proxy to
+    #[arg(long)]
+    pub port: u16,
+
+    /// Systemd service name (optional)
+    #[arg(long)]
+    pub service: Option<String>,
+
+    /// Skip confirmation prompts
+    #[arg(short = 'y', long = "yes")]
+    pub skip_confirm: bool,
+
+    /// Config template: a name from `template list` or a file path
+    #[arg(long)]
+    pub template: Option<String>,
+
+    /// Terminate TLS for a raw TCP service (nginx stream module)
+    #[arg(long, requires = "listen_port", conflicts_with = "http3")]
+    pub tcp: bool,
+
+    /// Public TLS port for --tcp mode
+    #[arg(long, requires = "tcp")]
+    pub listen_port: Option<u16>,
+
+    /// Enable HTTP/3 (QUIC) on the HTTPS listener
+    #[arg(long)]
+    pub http3: bool,
+
+    /// Plain HTTP port (defaults to http_port in /etc/localstacker/config.toml)
+    #[arg(long, conflicts_with = "tcp")]
+    pub http_port: Option<u16>,
+
+    /// HTTPS port (defaults to https_port in /etc/localstacker/config.toml)
+    #[arg(long, conflicts_with = "tcp")]
+    pub https_port: Option<u16>,
+
+    /// Protect the site with HTTP basic auth for this user (prompts for a password)
+    #[arg(long, value_name = "USER", conflicts_with = "tcp")]
+    pub basic_auth: Option<String>,
+
+    /// Allow an address or CIDR range (repeatable, checked before --deny)
+    #[arg(long, value_name = "CIDR", value_delimiter = ',', conflicts_with = "tcp")]
+    pub allow: Vec<String>,
+
+    /// Deny an address, CIDR range or `all` (repeatable)
+    #[arg(long, value_name = "CIDR", value_delimiter = ',', conflicts_with = "tcp")]
+    pub deny: Vec<String>,
+
+    /// Limit requests per client address, e.g. 10r/s
+    #[arg(long, value_name = "RATE", conflicts_with = "tcp")]
+    pub rate_limit: Option<String>,
+
+    /// Requests allowed above the rate limit before rejecting with 429
+    #[arg(long, requires = "rate_limit")]
+    pub burst: Option<u32>,
+
+    /// Security header profile
+    #[arg(long, value_enum, default_value_t = HeaderProfile::Relaxed, conflicts_with = "tcp")]
+    pub header_profile: HeaderProfile,
+
+    /// Extra response header as 'Name: value' (repeatable)
+    #[arg(long = "header", value_name = "HEADER", conflicts_with = "tcp")]
+    pub headers: Vec<String>,
+
+    /// Origins allowed for CORS requests, comma separated (`*` for any)
+    #[arg(long, value_name = "ORIGINS", value_delimiter = ',', conflicts_with = "tcp")]
+    pub cors: Vec<String>,
+
+    /// Compress responses with gzip
+    #[arg(long, conflicts_with = "tcp")]
+    pub gzip: bool,
+
+    /// Compress responses with brotli (needs the ngx_brotli module)
+    #[arg(long, conflicts_with = "tcp")]
+    pub brotli: bool,
+
+    /// Cache proxied responses for this long, e.g. 10m
+    #[arg(long, value_name = "TTL", conflicts_with = "tcp")]
+    pub cache: Option<String>,
+
+    /// Overwrite a hand-edited config and ignore server_name/listen conflicts
+    #[arg(long)]
+    pub force: bool,
+
+    /// Leave a hand-edited config file alone
+    #[arg(long)]
+    pub keep_local: bool,
+
+    /// Web server to configure (remembered per domain, default nginx)
+    #[arg(long, value_enum)]
+    pub server: Option<ServerKind>,
+}
+
+/// What to do with the existing config file
+#[derive(Debug, PartialEq, Eq)]
+enum LocalEdits {
+    Overwrite,
+    Keep,
+    Cancel,
+}
+
+pub fn run(args: SetupArgs) -> Result<()> {
+    let SetupArgs {
+        domain,
+        port,
+        service,
+        skip_confirm,
+        template,
+        tcp,
+        listen_port,
+        http3,
+        http_port,
+        https_port,
+        basic_auth,
+        allow,
+        deny,
+        rate_limit,
+        burst,
+        header_profile,
+        headers,
+        cors,
+        gzip,
+        brotli,
+        cache,
+        force,
+        keep_local,
+        server,
+    } = args;
+
     // Check if running as root
     check_root()?;
 
@@ -26,7 +162,86 @@ pub fn run(
     validate_domain(&domain)?;
     validate_port(port)?;
 
-    log_info(&format!("Setting up SSL for {} -> localhost:{}", domain, port));
+    let kind = if tcp { SiteKind::Tcp } else { SiteKind::Http };
+
+    if kind == SiteKind::Tcp {
+        let listen = listen_port
+            .ok_or_else(|| Error::Validation("--tcp requires --listen-port".to_string()))?;
+        validate_port(listen)?;
+        if listen == port {
+            return Err(Error::Validation(
+                "Listen port must differ from the backend port".to_string(),
+            ));
+        }
+        log_info(&format!(
+            "Setting up TLS for {}:{} -> localhost:{} (TCP)",
+            domain, listen, port
+        ));
+    } else {
+        for listen in [http_port, https_port].into_iter().flatten() {
+            validate_port(listen)?;
+        }
+
+        let settings = Settings::global();
+        if http_port.unwrap_or(settings.http_port) == https_port.unwrap_or(settings.https_port) {
+            return Err(Error::Validation(
+                "HTTP and HTTPS ports must differ".to_string(),
+            ));
+        }
+
+        if let Some(ref user) = basic_auth {
+            validate_username(user)?;
+        }
+
+        for source in allow.iter().chain(deny.iter()) {
+            validate_access_source(source)?;
+        }
+
+        if let Some(ref rate) = rate_limit {
+            validate_rate(rate)?;
+        }
+
+        for header in &headers {
+            parse_header(header)?;
+        }
+
+        for origin in &cors {
+            validate_origin(origin)?;
+        }
+
+        if let Some(ref ttl) = cache {
+            validate_duration(ttl)?;
+        }
+
+        log_info(&format!("Setting up SSL for {} -> localhost:{}", domain, port));
+    }
+
+    let mut config_manager = ConfigManager::new()?;
+    let existing = config_manager.get_domain(&domain).cloned();
+
+    let server = match (server, existing.as_ref()) {
+        (Some(server), Some(e)) if server != e.server => {
+            return Err(Error::Validation(format!(
+                "{} is served by {}; run `localstacker remove {}` first to switch to {}",
+                domain, e.server, domain, server
+            )));
+        }
+        (Some(server), _) => server,
+        (None, Some(e)) => e.server,
+        (None, None) => ServerKind::Nginx,
+    };
+
+    let template = template.map(|t| templates::resolve(&t)).transpose()?;
+    if let Some(ref template) = template {
+        if !matches!(template.source, TemplateSource::File(_)) && server != ServerKind::Nginx {
+            return Err(Error::Validation(format!(
+                "Template '{}' is an nginx template, {} sites take a template file",
+                template.name,
+                server.display_name()
+            )));
+        }
+        log_info(&format!("Using template {} (v{})", template.name, template.version));
+    }
 
     // Show confirmation unless skipped
     if !skip_confirm {
@@ -34,11 +249,13 @@ pub fn run(
             .with_prompt(format!(
                 "This will:\n  \
                 • Generate SSL certificate for {}\n  \
-                • Create Nginx configuration\n  \
+                • Create {server} {kind} configuration\n  \
                 • Enable the site\n  \
-                • Reload Nginx\n\n  \
+                • Reload {server}\n\n  \
                 Continue?",
-                domain
+                domain,
+                server = server.display_name(),
+                kind = if kind == SiteKind::Tcp { "stream" } else { "site" }
             ))
             .default(true)
             .interact()
@@ -52,61 +269,238 @@ pub fn run(
 
     // Initialize services (Dependency Injection following SOLID)
     let cert_provider = MkcertProvider::new();
-    let web_server = NginxConfig::new();
+    let web_server = web_server_for(server);
     let system_service = SystemdService::new();
     let file_ops = FileOps::new();
 
-    // Step 1: Ensure mkcert is installed
-    log_info("Checking mkcert installation...");
-    if !cert_provider.is_installed()? {
-        log_warning("mkcert not found, attempting to install...");
-        cert_provider.install()?;
+    if server == ServerKind::Nginx && (http3 || brotli) {
+        let capabilities = NginxCapabilities::detect();
+
+        if http3 && !capabilities.supports_http3() {
+            return Err(Error::Validation(format!(
+                "nginx {} was built without http_v3_module (HTTP/3 needs nginx >= 1.25.0)",
+                capabilities.version_string()
+            )));
+        }
+
+        if brotli && !capabilities.supports_brotli() {
+            return Err(Error::Validation(
+                "The brotli nginx module is not installed (e.g. libnginx-mod-http-brotli-filter)"
+                    .to_string(),
+            ));
+        }
     }
-    log_success("mkcert is installed");
 
-    // Step 2: Install local CA
-    cert_provider.install_ca()?;
-    log_success("Local CA installed");
+    // Pre-flight: another server block answering for the domain would win silently
+    if kind == SiteKind::Http && server == ServerKind::Nginx {
+        let settings = Settings::global();
+        let ports = [
+            http_port.unwrap_or(settings.http_port),
+            https_port.unwrap_or(settings.https_port),
+        ];
+        let own_files: Vec<String> = match existing {
+            Some(ref e) => vec![e.config_path.clone(), e.enabled_link()],
+            None => vec![
+                Paths::site_config(server, kind, &domain),
+                Paths::site_enabled(server, kind, &domain),
+            ],
+        };
+        check_conflicts(&domain, &ports, &own_files, force)?;
+    }
 
-    // Step 3: Generate certificate
-    cert_provider.generate_cert(&domain)?;
-    log_success(&format!("Certificate generated for {}", domain));
+    let domain_config = DomainConfig {
+        domain: domain.clone(),
+        port,
+        service,
+        ssl_cert_path: Paths::ssl_cert(server, &domain),
+        ssl_key_path: Paths::ssl_key(server, &domain),
+        // Setup takes an adopted config over in place, under its own file name
+        config_path: match existing {
+            Some(ref e) if e.adopted && e.kind == kind => e.config_path.clone(),
+            _ => Paths::site_config(server, kind, &domain),
+        },
+        created_at: get_timestamp(),
+        enabled: true,
+        kind,
+        listen_port: if kind == SiteKind::Tcp { listen_port } else { None },
+        template: template.as_ref().map(|t| t.reference().to_string()),
+        template_version: template.as_ref().map(|t| t.version),
+        http3,
+        quic_reuseport: false,
+        http_port,
+        https_port,
+        // Users are managed with `localstacker auth`, keep auth across re-runs
+        basic_auth: basic_auth.is_some()
+            || existing.as_ref().is_some_and(|e| e.basic_auth && kind == SiteKind::Http),
+        access: AccessPolicy {
+            allow,
+            deny,
+            rate_limit,
+            burst,
+        },
+        headers: HeaderPolicy {
+            profile: header_profile,
+            custom: headers,
+            cors_origins: cors,
+        },
+        gzip,
+        brotli,
+        cache_ttl: cache,
+        redirect: None,
+        // Toggled with `localstacker maintenance`, keep it across re-runs
+        maintenance: existing.as_ref().is_some_and(|e| e.maintenance && kind == SiteKind::Http),
+        config_hash: existing.as_ref().and_then(|e| e.config_hash.clone()),
+        adopted: false,
+        server,
+    };
 
-    // Step 4: Setup SSL directory and copy certificates
-    file_ops.ensure_directory(Paths::ssl_dir())?;
+    let unsupported = web_server.unsupported_features(&domain_config);
+    if !unsupported.is_empty() {
+        let hint = match domain_config.template {
+            Some(ref template) if server == ServerKind::Nginx => format!(
+                "{} has no placeholders for them, see `localstacker template show proxy`",
+                template
+            ),
+            _ => "use --server nginx".to_string(),
+        };
+        return Err(Error::Validation(format!(
+            "{} sites don't support {}; {}",
+            server.display_name(),
+            unsupported.join(", "),
+            hint
+        )));
+    }
+    check_port_owners(&config_manager, &domain_config)?;
+    // Switching between HTTP and TCP moves the config, the old one must go
+    let previous = replaced_site(existing.as_ref(), &domain_config)?;
 
-    let (cert_src, key_src) = cert_provider.get_cert_paths(&domain);
-    let cert_dest = Paths::ssl_cert(&domain);
-    let key_dest = Paths::ssl_key(&domain);
+    // Ask for the password up front so a typo doesn't leave a half-done setup
+    let auth_user = match basic_auth {
+        Some(user) => {
+            let password = prompt_password(&user)?;
+            Some((user, password))
+        }
+        None => None,
+    };
 
-    file_ops.copy_file(&cert_src, &cert_dest)?;
-    file_ops.copy_file(&key_src, &key_dest)?;
+    // Only one QUIC listener on the host may carry `reuseport`
+    let was_update = existing.is_some();
+    config_manager.domains.insert(domain.clone(), domain_config);
+    let reassigned: Vec<String> = config_manager
+        .rebalance_quic_reuseport()
+        .into_iter()
+        .filter(|d| *d != domain)
+        .collect();
+    // Step 1: Generate the server configuration and check for hand edits
+    let config = web_server.generate_config(
+        &config_manager.domains[&domain],
+        config_manager.domains[&domain].template.as_deref(),
+    )?;
+    let local_edits = check_local_edits(
+        &config_manager.domains[&domain],
+        existing.as_ref().is_some_and(|e| !e.adopted),
+        &config,
+        force,
+        keep_local,
+        skip_confirm,
+    )?;
+    match local_edits {
+        LocalEdits::Cancel => {
+            log_warning("Setup cancelled by user");
+            return Ok(());
+        }
+        LocalEdits::Overwrite => {
+            if let Some(site) = config_manager.domains.get_mut(&domain) {
+                site.config_hash = Some(content_hash(&config));
+            }
+        }
+        LocalEdits::Keep => {}
+    }
+    let domain_config = config_manager.domains[&domain].clone();
+    let reassigned = reassigned_configs(&config_manager, &reassigned, force)?;
 
-    // Clean up temporary files
-    file_ops.remove_file(&cert_src)?;
-    file_ops.remove_file(&key_src)?;
+    // Back up everything this setup overwrites so a failing `nginx -t`
+    // leaves the previous state in place
+    let mut transaction = Transaction::new();
+    transaction.track_all([
+        &domain_config.ssl_cert_path,
+        &domain_config.ssl_key_path,
+        &Paths::htpasswd(server, &domain),
+    ])?;
+    transaction.track_all(web_server.site_paths(&domain_config))?;
+    if let Some(ref previous) = previous {
+        transaction.track_all(web_server.site_paths(previous))?;
+    }
+    transaction.track_all(web_server.shared_paths())?;
+    for (other, _) in &reassigned {
+        transaction.track_all(web_server_for(other.server).site_paths(other))?;
+    }
 
-    log_success("SSL certificates installed");
+    transaction.run(|| {
+        // Step 2: Issue the certificate and install it for the web server
+        install_certificate(&cert_provider, &file_ops, &domain_config)?;
+
+        if let Some((user, password)) = auth_user {
+            let mut htpasswd = HtpasswdFile::load(&Paths::htpasswd(server, &domain))?;
+            htpasswd.set_user(&user, &password)?;
+            htpasswd.save(server)?;
+            log_success(&format!("Basic auth user {} saved", user));
+        }
 
-    // Step 5: Generate and write Nginx configuration
-    let config = web_server.generate_config(&domain, port, template.as_deref())?;
-    web_server.write_config(&domain, &config)?;
-    log_success("Nginx configuration created");
+        if let Some(ref previous) = previous {
+            web_server.disable_site(previous)?;
+            for path in [
+                &previous.config_path,
+                &Paths::disabled_config(&previous.config_path),
+            ] {
+                file_ops.remove_file(path)?;
+            }
+            log_success(&format!("Removed the old configuration {}", previous.config_path));
+        }
 
-    // Step 6: Enable site
-    web_server.enable_site(&domain)?;
-    log_success("Site enabled");
+        // Step 3: Write the server configuration
+        if local_edits == LocalEdits::Overwrite {
+            web_server.write_config(&domain_config, &config)?;
+            log_success(&format!("{} configuration created", server.display_name()));
+        }
+
+        for (other, config) in &reassigned {
+            log_info(&format!("Moving QUIC reuseport to {}...", other.domain));
+            web_server_for(other.server).write_config(other, config)?;
+        }
 
-    // Step 7: Test configuration
-    web_server.test_config()?;
-    log_success("Nginx configuration test passed");
+        web_server.write_shared_config(&config_manager.list_domains())?;
 
-    // Step 8: Reload Nginx
-    web_server.reload()?;
-    log_success("Nginx reloaded");
+        // Step 4: Enable site
+        web_server.enable_site(&domain_config)?;
+        log_success("Site enabled");
+
+        // Step 5: Test configuration
+        web_server.test_config()?;
+        log_success(&format!("{} configuration test passed", server.display_name()));
+
+        // Step 6: Reload the server
+        web_server.reload()?;
+        log_success(&format!("{} reloaded", server.display_name()));
+
+        Ok(())
+    })?;
+
+    for (other, config) in &reassigned {
+        if let Some(site) = config_manager.domains.get_mut(&other.domain) {
+            site.config_hash = Some(content_hash(config));
+        }
+    }
 
-    // Step 9: Optionally check and restart service
-    if let Some(service_name) = &service {
+    // Drop cached responses when caching was turned off
+    if domain_config.cache_ttl.is_none()
+        && existing.as_ref().is_some_and(|e| e.cache_ttl.is_some())
+    {
+        file_ops.remove_directory(&Paths::nginx_cache_dir(&domain))?;
+    }
+
+    // Step 7: Optionally check and restart service
+    if let Some(service_name) = &domain_config.service {
         if system_service.exists(service_name)? {
             log_info(&format!("Restarting service {}...", service_name));
             system_service.restart(service_name)?;
@@ -116,20 +510,8 @@ pub fn run(
         }
     }
 
-    // Step 10: Save configuration
-    let mut config_manager = ConfigManager::new()?;
-    let domain_config = DomainConfig {
-        domain: domain.clone(),
-        port,
-        service,
-        ssl_cert_path: cert_dest,
-        ssl_key_path: key_dest,
-        nginx_config_path: Paths::nginx_config(&domain),
-        created_at: get_timestamp(),
-        enabled: true,
-    };
-
-    let was_update = config_manager.upsert_domain(domain_config)?;
+    // Step 8: Save configuration
+    config_manager.upsert_domain(domain_config.clone())?;
     if was_update {
         log_success("Configuration updated");
     } else {
@@ -142,14 +524,346 @@ pub fn run(
     println!("{} {}", "✓".green().bold(), "Setup completed successfully!".green().bold());
     println!("{}", "═══════════════════════════════════════════".green());
     println!();
-    println!("  {} https://{}", "URL:".bold(), domain);
-    println!("  {} localhost:{}", "Backend:".bold(), port);
+    let url = domain_config.url();
+    match kind {
+        SiteKind::Tcp => {
+            println!("  {} {} (TLS)", "Endpoint:".bold(), url);
+            println!("  {} localhost:{}", "Backend:".bold(), port);
+            println!();
+            println!("  {}", "Next steps:".bold());
+            println!("    • Make sure your backend is running on port {}", port);
+            println!("    • Add {} to your /etc/hosts if needed", domain);
+            println!("    • Point your client at {} with TLS enabled", url);
+        }
+        SiteKind::Http | SiteKind::Redirect => {
+            println!("  {} {}", "URL:".bold(), url);
+            println!("  {} localhost:{}", "Backend:".bold(), port);
+            if domain_config.http3 {
+                println!("  {} enabled", "HTTP/3:".bold());
+            }
+            println!();
+            println!("  {}", "Next steps:".bold());
+            println!("    • Make sure your backend is running on port {}", port);
+            println!("    • Add {} to your /etc/hosts if needed", domain);
+            println!("    • Visit {} in your browser", url);
+        }
+    }
+    println!();
+
+    Ok(())
+}
+
+/// Refuse to set up a domain that another server block already answers for
+/// on the same port, or when default servers clash, unless forced
+fn check_conflicts(domain: &str, ports: &[u16], own_files: &[String], force: bool) -> Result<()> {
+    log_info("Checking nginx configs for server_name conflicts...");
+
+    let canonical = |path: &str| {
+        std::fs::canonicalize(path)
+            .map(|p| p.to_string_lossy().to_string())
+            .unwrap_or_else(|_| path.to_string())
+    };
+    let own: Vec<String> = own_files.iter().map(|f| canonical(f)).collect();
+
+    let mut servers: Vec<ServerInfo> = nginx_conflicts::load_servers(Paths::nginx_main_config())
+        .into_iter()
+        .filter(|server| !own.contains(&canonical(&server.file)))
+        .collect();
+
+    // The server block this setup is about to add
+    let planned = ServerInfo {
+        file: "(this setup)".to_string(),
+        line: 0,
+        names: vec![domain.to_string()],
+        ports: ports.to_vec(),
+        default_ports: Vec::new(),
+    };
+    servers.push(planned.clone());
+
+    let conflicts = nginx_conflicts::find_conflicts(&servers);
+    if conflicts.is_empty() {
+        return Ok(());
+    }
+
+    let mut blocking = 0;
+    for conflict in &conflicts {
+        if conflict.kind == ConflictKind::DuplicateDefault || conflict.servers.contains(&planned) {
+            blocking += 1;
+        }
+
+        log_warning(&conflict.description);
+        for server in conflict.servers.iter().filter(|s| **s != planned) {
+            println!("    {}", server.location().yellow());
+        }
+    }
+
+    if blocking == 0 {
+        return Ok(());
+    }
+
+    if force {
+        log_warning("Continuing despite conflicts (--force)");
+        return Ok(());
+    }
+
+    Err(Error::Validation(format!(
+        "{} conflicting nginx server block(s) found, fix them or pass --force",
+        blocking
+    )))
+}
+
+/// Compare the config file on disk with what localstacker last wrote and,
+/// if it was edited by hand, show a diff and decide whether to overwrite it
+fn check_local_edits(
+    site: &DomainConfig,
+    managed: bool,
+    new_config: &str,
+    force: bool,
+    keep_local: bool,
+    skip_confirm: bool,
+) -> Result<LocalEdits> {
+    let path = &site.config_path;
+    let current = match std::fs::read_to_string(path) {
+        Ok(current) => current,
+        Err(_) => return Ok(LocalEdits::Overwrite),
+    };
+
+    if current == new_config || !is_locally_modified(&current, site.config_hash.as_deref(), managed) {
+        return Ok(LocalEdits::Overwrite);
+    }
+
+    if keep_local {
+        log_warning(&format!("Keeping local changes to {}", path));
+        return Ok(LocalEdits::Keep);
+    }
+
+    if force {
+        log_warning(&format!("Overwriting local changes to {}", path));
+        return Ok(LocalEdits::Overwrite);
+    }
+
+    log_warning(&format!("{} was changed outside localstacker:", path));
+    println!();
+    print_diff(path, "new configuration", &current, new_config);
+    println!();
+
+    if skip_confirm {
+        return Err(Error::Validation(format!(
+            "{} has local changes, use --force to overwrite or --keep-local to keep them",
+            path
+        )));
+    }
+
+    let overwrite = Confirm::new()
+        .with_prompt("Overwrite the local changes?")
+        .default(false)
+        .interact()
+        .unwrap_or(false);
+
+    Ok(if overwrite { LocalEdits::Overwrite } else { LocalEdits::Cancel })
+}
+
+/// Another web server already serving one of the site's ports
+fn port_conflict<'a>(sites: &[&'a DomainConfig], site: &DomainConfig) -> Option<(u16, &'a DomainConfig)> {
+    let ports = site.listen_ports();
+    sites
+        .iter()
+        .filter(|other| other.domain != site.domain && other.server != site.server)
+        .find_map(|other| {
+            let port = other.listen_ports().into_iter().find(|port| ports.contains(port))?;
+            Some((port, *other))
+        })
+}
+
+/// Only one server can bind a port, the second one would fail to start or
+/// silently never see the site's requests
+pub(crate) fn check_port_owners(config_manager: &ConfigManager, site: &DomainConfig) -> Result<()> {
+    match port_conflict(&config_manager.list_domains(), site) {
+        Some((port, other)) => Err(Error::Validation(format!(
+            "Port {} is already served by {} for {}; pick other ports with --http-port/--https-port or use --server {}",
+            port,
+            other.server.display_name(),
+            other.domain,
+            other.server
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// The managed site `site` replaces when its config lives elsewhere, as when
+/// switching between HTTP and TCP
+fn replaced_site(existing: Option<&DomainConfig>, site: &DomainConfig) -> Result<Option<DomainConfig>> {
+    let Some(previous) = existing.filter(|e| e.config_path != site.config_path) else {
+        return Ok(None);
+    };
+    // Adopted configs are never deleted, see `remove`
+    if previous.adopted {
+        return Err(Error::Validation(format!(
+            "{} is an adopted site kept at {}, run `localstacker remove {}` first",
+            site.domain, previous.config_path, site.domain
+        )));
+    }
+    Ok(Some(previous.clone()))
+}
+
+/// For commands that rewrite an already managed site as a side effect:
+/// refuse to overwrite a config that was edited by hand unless forced
+pub(crate) fn check_unmodified(site: &DomainConfig, new_config: &str, force: bool) -> Result<()> {
+    let path = &site.config_path;
+    let current = match std::fs::read_to_string(path) {
+        Ok(current) => current,
+        Err(_) => return Ok(()),
+    };
+
+    if current == new_config || !is_locally_modified(&current, site.config_hash.as_deref(), !site.adopted) {
+        return Ok(());
+    }
+
+    if force {
+        log_warning(&format!("Overwriting local changes to {}", path));
+        return Ok(());
+    }
+
+    log_warning(&format!("{} was changed outside localstacker:", path));
     println!();
-    println!("  {}", "Next steps:".bold());
-    println!("    • Make sure your backend is running on port {}", port);
-    println!("    • Add {} to your /etc/hosts if needed", domain);
-    println!("    • Visit https://{} in your browser", domain);
+    print_diff(path, "new configuration", &current, new_config);
     println!();
 
+    Err(Error::Validation(format!(
+        "{} has local changes, use --force to overwrite them",
+        path
+    )))
+}
+
+/// Generate the configs of the sites whose QUIC `reuseport` flag moved,
+/// before anything is written
+pub(crate) fn reassigned_configs(
+    config_manager: &ConfigManager,
+    reassigned: &[String],
+    force: bool,
+) -> Result<Vec<(DomainConfig, String)>> {
+    let mut configs = Vec::new();
+    for other in reassigned {
+        let site = &config_manager.domains[other];
+        let config = web_server_for(site.server).generate_config(site, site.template.as_deref())?;
+        check_unmodified(site, &config, force)?;
+        configs.push((site.clone(), config));
+    }
+    Ok(configs)
+}
+
+/// Files without a stored hash count as hand-written unless localstacker
+/// already manages the domain (entries from before hashes were stored)
+pub(crate) fn is_locally_modified(current: &str, last_hash: Option<&str>, managed: bool) -> bool {
+    match last_hash {
+        Some(hash) => content_hash(current) != hash,
+        None => !managed,
+    }
+}
+
+/// Make sure mkcert and its CA are installed, issue a certificate for the
+/// site's domain and copy it to its `ssl_cert_path` / `ssl_key_path`
+pub fn install_certificate(
+    cert_provider: &dyn CertificateProvider,
+    file_ops: &dyn FileOperations,
+    site: &DomainConfig,
+) -> Result<()> {
+    let domain = site.domain.as_str();
+
+    // Step 1: Ensure mkcert is installed
+    log_info("Checking mkcert installation...");
+    if !cert_provider.is_installed()? {
+        log_warning("mkcert not found, attempting to install...");
+        cert_provider.install()?;
+    }
+    log_success("mkcert is installed");
+
+    // Step 2: Install local CA
+    cert_provider.install_ca()?;
+    log_success("Local CA installed");
+
+    // Step 3: Generate certificate
+    cert_provider.generate_cert(domain)?;
+    log_success(&format!("Certificate generated for {}", domain));
+
+    // Step 4: Setup SSL directory and copy certificates
+    file_ops.ensure_directory(&Paths::ssl_dir(site.server))?;
+
+    let (cert_src, key_src) = cert_provider.get_cert_paths(domain);
+
+    file_ops.copy_file(&cert_src, &site.ssl_cert_path)?;
+    file_ops.copy_file(&key_src, &site.ssl_key_path)?;
+
+    // Clean up temporary files
+    file_ops.remove_file(&cert_src)?;
+    file_ops.remove_file(&key_src)?;
+
+    log_success("SSL certificates installed");
+
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_locally_modified() {
+        let generated = "server {}\n";
+        let hash = content_hash(generated);
+
+        assert!(!is_locally_modified(generated, Some(&hash), true));
+        assert!(is_locally_modified("server { listen 8080; }\n", Some(&hash), true));
+        // Entries written before hashes were stored are trusted
+        assert!(!is_locally_modified("anything", None, true));
+        // A file for a domain localstacker doesn't manage is someone else's
+        assert!(is_locally_modified("anything", None, false));
+    }
+
+    #[test]
+    fn test_port_conflict_across_servers() {
+        let site = |domain: &str, server: ServerKind, http_port: u16, https_port: u16| DomainConfig {
+            domain: domain.to_string(),
+            server,
+            http_port: Some(http_port),
+            https_port: Some(https_port),
+            ..Default::default()
+        };
+        let nginx = site("a.test", ServerKind::Nginx, 8080, 443);
+        let caddy = site("b.test", ServerKind::Caddy, 8081, 8443);
+        let sites = [&nginx, &caddy];
+
+        // Sites of the same server share its listeners
+        assert!(port_conflict(&sites, &site("c.test", ServerKind::Nginx, 8080, 443)).is_none());
+        let (port, other) = port_conflict(&sites, &site("c.test", ServerKind::Builtin, 8080, 9443)).unwrap();
+        assert_eq!((port, other.domain.as_str()), (8080, "a.test"));
+        // Moving a site to another server doesn't conflict with its own entry
+        assert!(port_conflict(&sites, &site("a.test", ServerKind::Apache, 8080, 443)).is_none());
+    }
+
+    #[test]
+    fn test_replaced_site_http_to_tcp() {
+        let site = |kind: SiteKind| DomainConfig {
+            domain: "db.test".to_string(),
+            kind,
+            config_path: Paths::site_config(ServerKind::Nginx, kind, "db.test"),
+            ..Default::default()
+        };
+        let http = site(SiteKind::Http);
+        let tcp = site(SiteKind::Tcp);
+
+        assert!(replaced_site(None, &tcp).unwrap().is_none());
+        assert!(replaced_site(Some(&tcp), &tcp).unwrap().is_none());
+
+        // The HTTP config and its sites-enabled link are taken down with the switch
+        let previous = replaced_site(Some(&http), &tcp).unwrap().unwrap();
+        assert_eq!(previous.config_path, http.config_path);
+        let paths = web_server_for(ServerKind::Nginx).site_paths(&previous);
+        assert!(paths.contains(&http.config_path));
+        assert!(paths.contains(&http.enabled_link()));
+        assert!(!paths.contains(&tcp.config_path));
+
+        let adopted = DomainConfig { adopted: true, ..http };
+        assert!(replaced_site(Some(&adopted), &tcp).is_err());
+    }
+}
@@ -1,8 +1,11 @@
 use colored::Colorize;
-use std::process::Command;
+use std::process::{Command, Stdio};
 
-use crate::config::ConfigManager;
-use crate::core::{FileOps, FileOperations, SystemdService, SystemService};
+use crate::config::{ConfigManager, Paths, ServerKind, SiteKind};
+use crate::core::{
+    proxy_pid, running_pid, FileOps, FileOperations, HtpasswdFile, LayoutMode, NginxCapabilities, NginxConfig,
+    NginxLayout, ReloadStrategy, SystemdService, SystemService,
+};
 use crate::error::Result;
 
 pub fn run(domain: Option<String>) -> Result<()> {
@@ -23,6 +26,9 @@ pub fn run(domain: Option<String>) -> Result<()> {
         config_manager.list_domains().iter().map(|&c| c.clone()).collect()
     };
 
+    print_capabilities(NginxConfig::new().capabilities());
+    print_layout(NginxLayout::global());
+
     if domains_to_check.is_empty() {
         println!("{}", "No domains configured.".yellow());
         return Ok(());
@@ -34,6 +40,8 @@ pub fn run(domain: Option<String>) -> Result<()> {
 
     for config in domains_to_check {
         println!("{} {}", "Domain:".bold(), config.domain.cyan());
+        println!("  URL: {}", config.url());
+        println!("  Server: {}", config.server);
 
         // Check SSL certificates
         let cert_exists = file_ops.file_exists(&config.ssl_cert_path);
@@ -46,31 +54,64 @@ pub fn run(domain: Option<String>) -> Result<()> {
             println!("{}", "✗ Missing".red());
         }
 
-        // Check Nginx config
-        let nginx_exists = file_ops.file_exists(&config.nginx_config_path);
-        print!("  Nginx Config: ");
-        if nginx_exists {
-            println!("{}", "✓ Present".green());
+        if config.server == ServerKind::Builtin {
+            // Built-in proxy sites live only in domains.json
+            print!("  Proxy: ");
+            match proxy_pid() {
+                Some(pid) => println!("{}", format!("✓ Running (pid {})", pid).green()),
+                None => println!("{}", "✗ Not running (start with `localstacker serve`)".red()),
+            }
         } else {
-            println!("{}", "✗ Missing".red());
+            // Check the server config
+            let config_exists = file_ops.file_exists(&config.config_path);
+            print!("  {} Config: ", config.server.display_name());
+            if config_exists {
+                println!("{}", "✓ Present".green());
+            } else {
+                println!("{}", "✗ Missing".red());
+            }
+
+            // Check if site is enabled
+            let enabled_path = config.enabled_link();
+            let is_enabled = file_ops.file_exists(&enabled_path);
+            print!("  Site Enabled: ");
+            if is_enabled {
+                println!("{}", "✓ Yes".green());
+            } else {
+                println!("{}", "✗ No".red());
+            }
         }
 
-        // Check if site is enabled
-        let enabled_path = format!("/etc/nginx/sites-enabled/{}", config.domain);
-        let is_enabled = file_ops.file_exists(&enabled_path);
-        print!("  Site Enabled: ");
-        if is_enabled {
-            println!("{}", "✓ Yes".green());
-        } else {
-            println!("{}", "✗ No".red());
+        if config.adopted {
+            println!("  Adopted: {}", "yes (hand-written config)".dimmed());
+        }
+
+        if config.maintenance {
+            println!("  Maintenance: {}", "on (serving 503)".yellow());
+        }
+
+        if config.basic_auth {
+            print!("  Basic Auth: ");
+            match HtpasswdFile::load(&Paths::htpasswd(config.server, &config.domain)) {
+                Ok(htpasswd) if !htpasswd.users().is_empty() => {
+                    println!("{}", format!("✓ {} user(s)", htpasswd.users().len()).green())
+                }
+                _ => println!("{}", "✗ No users".red()),
+            }
         }
 
-        // Check backend port
-        print!("  Backend Port: ");
-        if check_port_listening(config.port) {
-            println!("{} {}", config.port.to_string().green(), "(listening)".green());
+        if let Some(ref redirect) = config.redirect {
+            println!("  Redirects To: {} ({})", redirect.to.cyan(), redirect.code);
+        } else if config.port == 0 {
+            println!("  Backend Port: {}", "none (no local proxy_pass)".dimmed());
         } else {
-            println!("{} {}", config.port.to_string().yellow(), "(not listening)".yellow());
+            // Check backend port
+            print!("  Backend Port: ");
+            if check_port_listening(config.port) {
+                println!("{} {}", config.port.to_string().green(), "(listening)".green());
+            } else {
+                println!("{} {}", config.port.to_string().yellow(), "(not listening)".yellow());
+            }
         }
 
         // Check service if specified
@@ -83,12 +124,29 @@ pub fn run(domain: Option<String>) -> Result<()> {
             }
         }
 
-        // Check HTTPS connectivity
-        print!("  HTTPS Check: ");
-        if check_https_connectivity(&config.domain) {
-            println!("{}", "✓ Accessible".green());
+        if let (SiteKind::Tcp, Some(listen_port)) = (config.kind, config.listen_port) {
+            // Check TLS listener
+            print!("  TLS Listener: ");
+            if check_port_listening(listen_port) {
+                println!("{} {}", listen_port.to_string().green(), "(listening)".green());
+            } else {
+                println!("{} {}", listen_port.to_string().yellow(), "(not listening)".yellow());
+            }
+
+            print!("  TLS Check: ");
+            if check_tls_handshake(&config.domain, listen_port) {
+                println!("{}", "✓ Handshake OK".green());
+            } else {
+                println!("{}", "✗ Handshake failed".yellow());
+            }
         } else {
-            println!("{}", "✗ Not accessible".yellow());
+            // Check HTTPS connectivity
+            print!("  HTTPS Check: ");
+            if check_https_connectivity(&config.url()) {
+                println!("{}", "✓ Accessible".green());
+            } else {
+                println!("{}", "✗ Not accessible".yellow());
+            }
         }
 
         println!();
@@ -97,9 +155,81 @@ pub fn run(domain: Option<String>) -> Result<()> {
     Ok(())
 }
 
+fn print_capabilities(caps: &NginxCapabilities) {
+    println!();
+    println!("{}", "Nginx Capabilities".bold().underline());
+    println!();
+
+    if !caps.detected {
+        println!("  {}", "✗ Could not run `nginx -V`, assuming defaults".yellow());
+        return;
+    }
+
+    let flag = |supported: bool| {
+        if supported {
+            "✓ Yes".green()
+        } else {
+            "✗ No".yellow()
+        }
+    };
+
+    println!("  Version: {}", caps.version_string().cyan());
+    if let Some((ref name, (major, minor, patch))) = caps.tls_library {
+        println!("  TLS Library: {} {}.{}.{}", name, major, minor, patch);
+    }
+    if let Some(conf_path) = caps.configure_arg("--conf-path") {
+        println!("  Config Path: {}", conf_path);
+    }
+    println!("  TLSv1.3: {}", flag(caps.supports_tls13()));
+    print!("  HTTP/2: {}", flag(caps.supports_http2()));
+    if caps.supports_http2() {
+        if caps.uses_http2_directive() {
+            print!(" {}", "(http2 on;)".dimmed());
+        } else {
+            print!(" {}", "(listen ... http2)".dimmed());
+        }
+    }
+    println!();
+    println!("  HTTP/3 (QUIC): {}", flag(caps.supports_http3()));
+    println!("  IPv6: {}", flag(caps.ipv6));
+    println!("  Brotli: {}", flag(caps.supports_brotli()));
+    println!("  Stream (TCP): {}", flag(caps.supports_stream()));
+}
+
+fn print_layout(layout: &NginxLayout) {
+    println!();
+    println!("{}", "Nginx Layout".bold().underline());
+    println!();
+
+    let mode = match layout.mode {
+        LayoutMode::SitesEnabled => "sites-enabled (symlinks)",
+        LayoutMode::ConfD => "conf.d (*.conf, disabled as *.conf.disabled)",
+    };
+    println!("  Mode: {}", mode.cyan());
+    println!("  Main Config: {}", layout.main_config);
+    println!("  Sites: {}", layout.sites_available);
+    if layout.mode == LayoutMode::SitesEnabled {
+        println!("  Enabled: {}", layout.sites_enabled);
+    }
+    println!("  Logs: {}", layout.log_dir);
+
+    let strategy = ReloadStrategy::global();
+    let source = if ReloadStrategy::is_configured() { "configured" } else { "detected" };
+    println!("  Reload: {} {}", strategy.to_string().cyan(), format!("({})", source).dimmed());
+    print!("  Running: ");
+    if strategy.is_running() {
+        match running_pid() {
+            Some(pid) => println!("{} {}", "✓ Yes".green(), format!("(pid {})", pid).dimmed()),
+            None => println!("{}", "✓ Yes".green()),
+        }
+    } else {
+        println!("{}", "✗ No".yellow());
+    }
+}
+
 fn check_port_listening(port: u16) -> bool {
     Command::new("ss")
-        .args(&["-ln", &format!("sport = :{}", port)])
+        .args(["-ln", &format!("sport = :{}", port)])
         .output()
         .map(|output| {
             let stdout = String::from_utf8_lossy(&output.stdout);
@@ -108,16 +238,16 @@ fn check_port_listening(port: u16) -> bool {
         .unwrap_or(false)
 }
 
-fn check_https_connectivity(domain: &str) -> bool {
+fn check_https_connectivity(url: &str) -> bool {
     Command::new("curl")
-        .args(&[
+        .args([
             "-k", // ignore cert validation
             "-s",
             "-o",
             "/dev/null",
             "-w",
             "%{http_code}",
-            &format!("https://{}", domain),
+            url,
             "--max-time",
             "5",
         ])
@@ -127,4 +257,20 @@ fn check_https_connectivity(domain: &str) -> bool {
             code.starts_with('2') || code.starts_with('3')
         })
         .unwrap_or(false)
-}
\ No newline at end of file
+}
+fn check_tls_handshake(domain: &str, port: u16) -> bool {
+    Command::new("openssl")
+        .args([
+            "s_client",
+            "-connect",
+            &format!("127.0.0.1:{}", port),
+            "-servername",
+            domain,
+        ])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false)
+}
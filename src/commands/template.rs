@@ -0,0 +1,178 @@
+use clap::Subcommand;
+use colored::Colorize;
+
+use std::fs;
+use std::path::Path;
+
+use crate::config::{DomainConfig, Paths, SiteKind};
+use crate::core::nginx_lint::{self, LintContext, Severity};
+use crate::core::NginxConfig;
+use crate::core::templates::{self, TemplateSource};
+use crate::error::{Error, Result};
+use crate::utils::{check_root, log_success};
+
+#[derive(Subcommand)]
+pub enum TemplateCommand {
+    /// List built-in and user templates
+    List,
+
+    /// Print a template
+    Show {
+        /// Template name
+        name: String,
+    },
+
+    /// Create a user template in /etc/localstacker/templates from another one
+    New {
+        /// Name of the new template
+        name: String,
+
+        /// Template to start from
+        #[arg(long, default_value = "proxy")]
+        from: String,
+
+        /// Replace an existing user template
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Check an nginx template for structural errors without touching nginx
+    Lint {
+        /// Template file or name
+        file: String,
+
+        /// Domain to fill in for `{{domain}}` and to check `server_name` against
+        #[arg(long, default_value = "example.test")]
+        domain: String,
+
+        /// Backend port to fill in for `{{port}}`
+        #[arg(long, default_value_t = 3000)]
+        port: u16,
+
+        /// The template is a TCP site, included in the `stream {}` block
+        #[arg(long)]
+        stream: bool,
+    },
+}
+
+pub fn run(command: TemplateCommand) -> Result<()> {
+    match command {
+        TemplateCommand::List => list(),
+        TemplateCommand::Show { name } => show(&name),
+        TemplateCommand::New { name, from, force } => new(&name, &from, force),
+        TemplateCommand::Lint { file, domain, port, stream } => lint(&file, &domain, port, stream),
+    }
+}
+
+fn list() -> Result<()> {
+    let templates = templates::list()?;
+
+    println!();
+    println!("{}", "Templates".bold().underline());
+    println!();
+    for template in &templates {
+        let source = match template.source {
+            TemplateSource::Builtin => "built-in".to_string(),
+            TemplateSource::User(ref path) | TemplateSource::File(ref path) => path.clone(),
+        };
+        println!(
+            "{} {} {}",
+            template.name.cyan().bold(),
+            format!("v{}", template.version).dimmed(),
+            format!("[{}]", source).dimmed()
+        );
+        if !template.description.is_empty() {
+            println!("  {}", template.description);
+        }
+    }
+    println!();
+    println!(
+        "{}",
+        format!("Use one with `setup --template NAME`; add your own to {}", Paths::templates_dir()).dimmed()
+    );
+
+    Ok(())
+}
+
+fn show(name: &str) -> Result<()> {
+    let template = templates::find(name)?;
+    print!("{}", template.content);
+    Ok(())
+}
+
+fn new(name: &str, from: &str, force: bool) -> Result<()> {
+    check_root()?;
+    templates::validate_name(name)?;
+
+    if templates::is_builtin(name) {
+        return Err(Error::Validation(format!(
+            "'{}' is a built-in template, pick another name",
+            name
+        )));
+    }
+
+    let path = templates::user_template_path(name);
+    if Path::new(&path).exists() && !force {
+        return Err(Error::Validation(format!(
+            "{} already exists, use --force to replace it",
+            path
+        )));
+    }
+
+    let source = templates::resolve(from)?;
+    // The copy starts its own version history
+    let body: String = source
+        .content
+        .lines()
+        .skip_while(|line| line.starts_with('#'))
+        .map(|line| format!("{}\n", line))
+        .collect();
+    let content = format!(
+        "# description: Based on {}\n# version: 1\n{}",
+        source.name, body
+    );
+
+    fs::create_dir_all(Paths::templates_dir())?;
+    fs::write(&path, content)?;
+
+    log_success(&format!("Created {} from {}", path, source.name));
+    println!("Edit it, bump `# version:` when you change it, then run `localstacker regenerate --all`.");
+    Ok(())
+}
+
+fn lint(file: &str, domain: &str, port: u16, stream: bool) -> Result<()> {
+    let site = DomainConfig {
+        domain: domain.to_string(),
+        port,
+        kind: if stream { SiteKind::Tcp } else { SiteKind::Http },
+        listen_port: stream.then_some(port.saturating_add(1)),
+        ..Default::default()
+    };
+    let config = NginxConfig::render_template(file, &site)?;
+    let context = if stream { LintContext::Stream } else { LintContext::detect(&config) };
+    let findings = nginx_lint::lint(&config, context, Some(domain))
+        .map_err(|e| Error::Validation(format!("{}: {}", file, e)))?;
+
+    let mut errors = 0;
+    for finding in &findings {
+        match finding.severity {
+            Severity::Error => {
+                errors += 1;
+                println!("{} {}: {}", "✗".red().bold(), file, finding.to_string().red());
+            }
+            Severity::Warning => println!("{} {}: {}", "⚠".yellow().bold(), file, finding.to_string().yellow()),
+        }
+    }
+
+    if errors > 0 {
+        return Err(Error::Validation(format!("{} error(s) in {}", errors, file)));
+    }
+
+    let warnings = findings.len();
+    if warnings > 0 {
+        log_success(&format!("{} has no errors ({} warning(s))", file, warnings));
+    } else {
+        log_success(&format!("{} looks good", file));
+    }
+    Ok(())
+}
@@ -1,23 +1,352 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
-use std::path::{PathBuf};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 
+use crate::core::{ApacheLayout, LayoutMode, NginxLayout, NginxPathSettings, TraefikSettings};
 use crate::error::{Error, Result};
 
 const CONFIG_DIR: &str = "/etc/localstacker";
 const CONFIG_FILE: &str = "domains.json";
+const SETTINGS_FILE: &str = "config.toml";
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+static SETTINGS: OnceLock<Settings> = OnceLock::new();
+
+/// Global settings from /etc/localstacker/config.toml
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct Settings {
+    /// Default plain HTTP port for new sites
+    pub http_port: u16,
+    /// Default HTTPS port for new sites
+    pub https_port: u16,
+    /// Overrides for detected nginx paths and the reload strategy
+    pub nginx: NginxPathSettings,
+    /// Where Traefik's file provider looks and which entry points to use
+    pub traefik: TraefikSettings,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            http_port: 80,
+            https_port: 443,
+            nginx: NginxPathSettings::default(),
+            traefik: TraefikSettings::default(),
+        }
+    }
+}
+
+impl Settings {
+    /// Load the settings file once at startup
+    pub fn init() -> Result<()> {
+        let path = PathBuf::from(CONFIG_DIR).join(SETTINGS_FILE);
+
+        let settings = if path.exists() {
+            let contents = fs::read_to_string(&path)?;
+            toml::from_str(&contents).map_err(|e| {
+                Error::Config(format!("Failed to parse {}: {}", path.display(), e))
+            })?
+        } else {
+            Settings::default()
+        };
+
+        let _ = SETTINGS.set(settings);
+        Ok(())
+    }
+
+    /// Loaded settings, or the defaults if `init` was never called
+    pub fn global() -> &'static Settings {
+        SETTINGS.get_or_init(Settings::default)
+    }
+}
+
+/// Web server that serves a domain
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ServerKind {
+    #[default]
+    Nginx,
+    Caddy,
+    Apache,
+    Haproxy,
+    Traefik,
+    /// `localstacker serve`, no web server needed
+    Builtin,
+}
+
+impl ServerKind {
+    pub fn display_name(self) -> &'static str {
+        match self {
+            ServerKind::Nginx => "Nginx",
+            ServerKind::Caddy => "Caddy",
+            ServerKind::Apache => "Apache",
+            ServerKind::Haproxy => "HAProxy",
+            ServerKind::Traefik => "Traefik",
+            ServerKind::Builtin => "Built-in proxy",
+        }
+    }
+
+    /// Groups the server's workers may run as, most likely first. The
+    /// built-in proxy runs as root.
+    pub fn worker_groups(self) -> &'static [&'static str] {
+        match self {
+            ServerKind::Nginx => &["www-data", "nginx", "http"],
+            ServerKind::Apache => &["www-data", "apache", "http"],
+            ServerKind::Caddy => &["caddy"],
+            ServerKind::Haproxy => &["haproxy"],
+            ServerKind::Traefik => &["traefik"],
+            ServerKind::Builtin => &[],
+        }
+    }
+}
+
+impl std::fmt::Display for ServerKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ServerKind::Nginx => "nginx",
+            ServerKind::Caddy => "caddy",
+            ServerKind::Apache => "apache",
+            ServerKind::Haproxy => "haproxy",
+            ServerKind::Traefik => "traefik",
+            ServerKind::Builtin => "builtin",
+        })
+    }
+}
+
+/// Kind of site managed for a domain
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SiteKind {
+    /// HTTPS reverse proxy (nginx `http` server block)
+    #[default]
+    Http,
+    /// TLS termination for a raw TCP service (nginx `stream` server block)
+    Tcp,
+    /// Redirect-only alias of another URL
+    Redirect,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct DomainConfig {
     pub domain: String,
     pub port: u16,
     pub service: Option<String>,
     pub ssl_cert_path: String,
     pub ssl_key_path: String,
-    pub nginx_config_path: String,
+    /// Site config file, whichever server it's written for
+    #[serde(alias = "nginx_config_path")]
+    pub config_path: String,
     pub created_at: String,
     pub enabled: bool,
+    #[serde(default)]
+    pub kind: SiteKind,
+    /// Public TLS port for TCP sites
+    #[serde(default)]
+    pub listen_port: Option<u16>,
+    /// Template the config was generated from: a registry name or a file path
+    #[serde(default)]
+    pub template: Option<String>,
+    /// Version of the template at the time the config was generated
+    #[serde(default)]
+    pub template_version: Option<u32>,
+    /// Serve HTTP/3 (QUIC) next to HTTP/1.1 and HTTP/2
+    #[serde(default)]
+    pub http3: bool,
+    /// Whether this site's QUIC listener carries `reuseport`
+    #[serde(default)]
+    pub quic_reuseport: bool,
+    /// Per-domain override of the global HTTP port
+    #[serde(default)]
+    pub http_port: Option<u16>,
+    /// Per-domain override of the global HTTPS port
+    #[serde(default)]
+    pub https_port: Option<u16>,
+    /// Require HTTP basic auth (users in `Paths::htpasswd`)
+    #[serde(default)]
+    pub basic_auth: bool,
+    /// IP allow/deny rules and rate limiting
+    #[serde(default)]
+    pub access: AccessPolicy,
+    /// Response headers and CORS
+    #[serde(default)]
+    pub headers: HeaderPolicy,
+    /// Compress responses with gzip
+    #[serde(default)]
+    pub gzip: bool,
+    /// Compress responses with brotli (needs the ngx_brotli module)
+    #[serde(default)]
+    pub brotli: bool,
+    /// Cache proxied responses for this long, e.g. `10m`
+    #[serde(default)]
+    pub cache_ttl: Option<String>,
+    /// Target of a redirect-only site
+    #[serde(default)]
+    pub redirect: Option<RedirectTarget>,
+    /// Serve the maintenance page instead of proxying
+    #[serde(default)]
+    pub maintenance: bool,
+    /// Hash of the config file localstacker last wrote
+    #[serde(default)]
+    pub config_hash: Option<String>,
+    /// Hand-written config taken over with `adopt`; never regenerated implicitly
+    #[serde(default)]
+    pub adopted: bool,
+    /// Web server the site is configured in
+    #[serde(default)]
+    pub server: ServerKind,
+}
+
+/// Where a redirect-only site sends its visitors
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct RedirectTarget {
+    /// Target URL without trailing slash, e.g. `https://app.test`
+    pub to: String,
+    /// 301, 302, 307 or 308
+    pub code: u16,
+    /// Append the original path and query string to the target
+    pub preserve_path: bool,
+}
+
+/// Named set of security response headers
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum HeaderProfile {
+    /// HSTS (without includeSubDomains), CSP, frame denial and a strict referrer policy
+    Strict,
+    /// Basic hardening that doesn't pin browsers to HTTPS
+    #[default]
+    Relaxed,
+    /// No security headers
+    None,
+}
+
+impl HeaderProfile {
+    /// Response headers sent for the profile
+    pub fn headers(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            HeaderProfile::Strict => &[
+                ("Strict-Transport-Security", "max-age=31536000"),
+                ("X-Frame-Options", "DENY"),
+                ("X-Content-Type-Options", "nosniff"),
+                ("Referrer-Policy", "no-referrer"),
+                ("Content-Security-Policy", "default-src 'self'; frame-ancestors 'none'; base-uri 'self'"),
+                ("Cross-Origin-Opener-Policy", "same-origin"),
+                ("Permissions-Policy", "camera=(), microphone=(), geolocation=()"),
+            ],
+            HeaderProfile::Relaxed => &[
+                ("X-Frame-Options", "SAMEORIGIN"),
+                ("X-Content-Type-Options", "nosniff"),
+                ("Referrer-Policy", "strict-origin-when-cross-origin"),
+            ],
+            HeaderProfile::None => &[],
+        }
+    }
+}
+
+/// Response headers for a site
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct HeaderPolicy {
+    #[serde(default)]
+    pub profile: HeaderProfile,
+    /// Extra headers as `Name: value`
+    #[serde(default)]
+    pub custom: Vec<String>,
+    /// Origins allowed for cross-origin requests (`*` for any)
+    #[serde(default)]
+    pub cors_origins: Vec<String>,
+}
+
+/// Edge access policy for a site. Like nginx, allow rules win and deny
+/// rules apply to everyone else
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct AccessPolicy {
+    /// Addresses or CIDR ranges allowed, checked before `deny`
+    #[serde(default)]
+    pub allow: Vec<String>,
+    /// Addresses, CIDR ranges or `all` to deny
+    #[serde(default)]
+    pub deny: Vec<String>,
+    /// Request rate per client address, e.g. `10r/s`
+    #[serde(default)]
+    pub rate_limit: Option<String>,
+    /// Requests allowed to exceed the rate before rejecting
+    #[serde(default)]
+    pub burst: Option<u32>,
+}
+
+impl AccessPolicy {
+    /// Sources as a space separated list of ranges, `all` spelled out for
+    /// servers that match on addresses
+    pub fn ranges(sources: &[String]) -> String {
+        sources
+            .iter()
+            .map(|source| if source == "all" { "0.0.0.0/0 ::/0" } else { source.as_str() })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+impl DomainConfig {
+    pub fn effective_http_port(&self) -> u16 {
+        self.http_port.unwrap_or(Settings::global().http_port)
+    }
+
+    pub fn effective_https_port(&self) -> u16 {
+        self.https_port.unwrap_or(Settings::global().https_port)
+    }
+
+    /// Ports the web server listens on for this site
+    pub fn listen_ports(&self) -> Vec<u16> {
+        match self.kind {
+            SiteKind::Tcp => self.listen_port.into_iter().collect(),
+            SiteKind::Http | SiteKind::Redirect => {
+                vec![self.effective_http_port(), self.effective_https_port()]
+            }
+        }
+    }
+
+    /// Symlink that enables the site. Adopted configs keep their own file name.
+    /// In conf.d layouts and for HAProxy the config file itself is the enabled site.
+    pub fn enabled_link(&self) -> String {
+        let conf_d = match self.server {
+            ServerKind::Nginx => {
+                self.kind != SiteKind::Tcp && NginxLayout::global().mode == LayoutMode::ConfD
+            }
+            ServerKind::Apache => !Paths::apache_uses_a2ensite(),
+            ServerKind::Haproxy | ServerKind::Traefik | ServerKind::Builtin => true,
+            ServerKind::Caddy => false,
+        };
+        if conf_d {
+            return self.config_path.clone();
+        }
+
+        let name = Path::new(&self.config_path)
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| self.domain.clone());
+        Paths::site_enabled(self.server, self.kind, &name)
+    }
+
+    /// Only nginx shares one `reuseport` QUIC listener between sites
+    fn uses_quic_reuseport(&self) -> bool {
+        self.http3 && self.server == ServerKind::Nginx
+    }
+
+    /// Public URL of the site, with the port when it isn't the default
+    pub fn url(&self) -> String {
+        match self.kind {
+            SiteKind::Tcp => match self.listen_port {
+                Some(port) => format!("{}:{}", self.domain, port),
+                None => self.domain.clone(),
+            },
+            SiteKind::Http | SiteKind::Redirect => match self.effective_https_port() {
+                443 => format!("https://{}", self.domain),
+                port => format!("https://{}:{}", self.domain, port),
+            },
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Default)]
@@ -93,7 +422,47 @@ impl ConfigManager {
         self.domains.values().collect()
     }
 
-    fn config_path() -> PathBuf {
+    /// Make sure exactly one HTTP/3 domain per HTTPS port holds `reuseport`
+    /// on its QUIC listener; nginx refuses duplicate `reuseport` on the same
+    /// address. Returns the domains whose flag changed so their configs can
+    /// be rewritten.
+    pub fn rebalance_quic_reuseport(&mut self) -> Vec<String> {
+        let mut names: Vec<String> = self.domains.keys().cloned().collect();
+        names.sort();
+
+        let mut holders: HashMap<u16, String> = HashMap::new();
+        for name in &names {
+            let config = &self.domains[name];
+            if config.uses_quic_reuseport() && config.quic_reuseport {
+                holders
+                    .entry(config.effective_https_port())
+                    .or_insert_with(|| name.clone());
+            }
+        }
+        for name in &names {
+            let config = &self.domains[name];
+            if config.uses_quic_reuseport() {
+                holders
+                    .entry(config.effective_https_port())
+                    .or_insert_with(|| name.clone());
+            }
+        }
+
+        let mut changed = Vec::new();
+        for name in names {
+            let config = self.domains.get_mut(&name).expect("domain listed above");
+            let should_hold = config.uses_quic_reuseport()
+                && holders.get(&config.effective_https_port()) == Some(&name);
+            if config.quic_reuseport != should_hold {
+                config.quic_reuseport = should_hold;
+                changed.push(name);
+            }
+        }
+
+        changed
+    }
+
+    pub fn config_path() -> PathBuf {
         PathBuf::from(CONFIG_DIR).join(CONFIG_FILE)
     }
 }
@@ -101,31 +470,295 @@ impl ConfigManager {
 pub struct Paths;
 
 impl Paths {
-    pub fn ssl_dir() -> &'static str {
-        "/etc/nginx/ssl"
+    fn layout() -> &'static NginxLayout {
+        NginxLayout::global()
+    }
+
+    /// Certificates are copied next to the server's config so it can read them
+    pub fn ssl_dir(server: ServerKind) -> String {
+        match server {
+            ServerKind::Nginx => format!("{}/ssl", Self::layout().conf_dir),
+            ServerKind::Caddy => "/etc/caddy/ssl".to_string(),
+            ServerKind::Apache => format!("{}/ssl", Self::apache_conf_dir()),
+            ServerKind::Haproxy => "/etc/haproxy/ssl".to_string(),
+            ServerKind::Traefik => "/etc/traefik/ssl".to_string(),
+            ServerKind::Builtin => format!("{}/ssl", CONFIG_DIR),
+        }
     }
 
     pub fn nginx_sites_available() -> &'static str {
-        "/etc/nginx/sites-available"
+        &Self::layout().sites_available
     }
 
     pub fn nginx_sites_enabled() -> &'static str {
-        "/etc/nginx/sites-enabled"
+        &Self::layout().sites_enabled
     }
 
-    pub fn ssl_cert(domain: &str) -> String {
-        format!("{}/{}.pem", Self::ssl_dir(), domain)
+    pub fn ssl_cert(server: ServerKind, domain: &str) -> String {
+        format!("{}/{}.pem", Self::ssl_dir(server), domain)
     }
 
-    pub fn ssl_key(domain: &str) -> String {
-        format!("{}/{}-key.pem", Self::ssl_dir(), domain)
+    pub fn ssl_key(server: ServerKind, domain: &str) -> String {
+        format!("{}/{}-key.pem", Self::ssl_dir(server), domain)
     }
 
+    /// Site config file; conf.d layouts only include `*.conf`
     pub fn nginx_config(domain: &str) -> String {
-        format!("{}/{}", Self::nginx_sites_available(), domain)
+        match Self::layout().mode {
+            LayoutMode::SitesEnabled => format!("{}/{}", Self::nginx_sites_available(), domain),
+            LayoutMode::ConfD => format!("{}/{}.conf", Self::nginx_sites_available(), domain),
+        }
     }
 
     pub fn nginx_enabled(domain: &str) -> String {
-        format!("{}/{}", Self::nginx_sites_enabled(), domain)
+        match Self::layout().mode {
+            LayoutMode::SitesEnabled => format!("{}/{}", Self::nginx_sites_enabled(), domain),
+            LayoutMode::ConfD => Self::nginx_config(domain),
+        }
+    }
+
+    /// Name a conf.d site is renamed to while disabled
+    pub fn disabled_config(config_path: &str) -> String {
+        format!("{}.disabled", config_path)
+    }
+
+    pub fn nginx_main_config() -> &'static str {
+        &Self::layout().main_config
+    }
+
+    pub fn nginx_log_dir() -> &'static str {
+        &Self::layout().log_dir
+    }
+
+    pub fn nginx_streams_available() -> String {
+        format!("{}/streams-available", Self::layout().conf_dir)
+    }
+
+    pub fn nginx_streams_enabled() -> String {
+        format!("{}/streams-enabled", Self::layout().conf_dir)
     }
-}
\ No newline at end of file
+
+    /// Managed `stream {}` block that includes every enabled TCP site
+    pub fn nginx_stream_include() -> String {
+        format!("{}/localstacker-stream.conf", Self::layout().conf_dir)
+    }
+
+    pub fn nginx_stream_config(domain: &str) -> String {
+        format!("{}/{}", Self::nginx_streams_available(), domain)
+    }
+
+    pub fn nginx_stream_enabled(domain: &str) -> String {
+        format!("{}/{}", Self::nginx_streams_enabled(), domain)
+    }
+
+    pub fn nginx_snippets_dir() -> String {
+        format!("{}/snippets", Self::layout().conf_dir)
+    }
+
+    pub fn nginx_ssl_snippet() -> String {
+        format!("{}/localstacker-ssl.conf", Self::nginx_snippets_dir())
+    }
+
+    /// The stream module can't use the http snippet's session cache zone
+    pub fn nginx_stream_ssl_snippet() -> String {
+        format!("{}/localstacker-stream-ssl.conf", Self::nginx_snippets_dir())
+    }
+
+    pub fn nginx_proxy_snippet() -> String {
+        format!("{}/localstacker-proxy.conf", Self::nginx_snippets_dir())
+    }
+
+    /// Managed http-level include shared by all sites (zones, maps)
+    pub fn nginx_shared_include() -> String {
+        format!("{}/localstacker.conf", Self::layout().shared_dir())
+    }
+
+    pub fn nginx_cache_root() -> &'static str {
+        "/var/cache/nginx/localstacker"
+    }
+
+    pub fn nginx_cache_dir(domain: &str) -> String {
+        format!("{}/{}", Self::nginx_cache_root(), domain)
+    }
+
+    pub fn htpasswd_dir(server: ServerKind) -> String {
+        match server {
+            ServerKind::Nginx => format!("{}/htpasswd", Self::layout().conf_dir),
+            ServerKind::Caddy => "/etc/caddy/htpasswd".to_string(),
+            ServerKind::Apache => format!("{}/htpasswd", Self::apache_conf_dir()),
+            ServerKind::Haproxy => "/etc/haproxy/htpasswd".to_string(),
+            ServerKind::Traefik => "/etc/traefik/htpasswd".to_string(),
+            ServerKind::Builtin => format!("{}/htpasswd", CONFIG_DIR),
+        }
+    }
+
+    pub fn htpasswd(server: ServerKind, domain: &str) -> String {
+        format!("{}/{}", Self::htpasswd_dir(server), domain)
+    }
+
+    pub fn error_pages_root() -> &'static str {
+        "/var/www/localstacker"
+    }
+
+    /// Static error and maintenance pages served for a domain
+    pub fn error_pages_dir(domain: &str) -> String {
+        format!("{}/{}", Self::error_pages_root(), domain)
+    }
+
+    pub fn caddy_main_config() -> &'static str {
+        "/etc/caddy/Caddyfile"
+    }
+
+    pub fn caddy_sites_available() -> &'static str {
+        "/etc/caddy/sites-available"
+    }
+
+    pub fn caddy_sites_enabled() -> &'static str {
+        "/etc/caddy/sites-enabled"
+    }
+
+    pub fn apache_uses_a2ensite() -> bool {
+        ApacheLayout::global().a2ensite
+    }
+
+    pub fn apache_conf_dir() -> &'static str {
+        &ApacheLayout::global().conf_dir
+    }
+
+    pub fn apache_sites_available() -> String {
+        ApacheLayout::global().sites_available()
+    }
+
+    pub fn apache_sites_enabled() -> String {
+        ApacheLayout::global().sites_enabled()
+    }
+
+    /// Managed server-level include with the extra `Listen` ports
+    pub fn apache_shared_include() -> String {
+        ApacheLayout::global().shared_include()
+    }
+
+    pub fn apache_log_dir() -> &'static str {
+        &ApacheLayout::global().log_dir
+    }
+
+    pub fn haproxy_main_config() -> &'static str {
+        "/etc/haproxy/haproxy.cfg"
+    }
+
+    /// Per-site backend sections, inlined into the managed part of haproxy.cfg
+    pub fn haproxy_sites_dir() -> &'static str {
+        "/etc/haproxy/sites"
+    }
+
+    /// SNI entries for the managed frontend's certificates
+    pub fn haproxy_crt_list() -> &'static str {
+        "/etc/haproxy/localstacker.crt-list"
+    }
+
+    /// Certificate and key in the single PEM HAProxy loads
+    pub fn haproxy_bundle(domain: &str) -> String {
+        format!("{}/{}.bundle.pem", Self::ssl_dir(ServerKind::Haproxy), domain)
+    }
+
+    /// Directory watched by Traefik's file provider
+    pub fn traefik_dynamic_dir() -> String {
+        Settings::global().traefik.directory.clone()
+    }
+
+    /// User templates, `<name>.conf`, listed next to the built-in ones
+    pub fn templates_dir() -> String {
+        format!("{}/templates", CONFIG_DIR)
+    }
+
+    /// Pid of the running `localstacker serve`
+    pub fn serve_pid_file() -> &'static str {
+        "/run/localstacker-serve.pid"
+    }
+
+    pub fn serve_unit() -> &'static str {
+        "/etc/systemd/system/localstacker-serve.service"
+    }
+
+    /// Built-in proxy sites have no config file, the proxy reads domains.json
+    pub fn site_config(server: ServerKind, kind: SiteKind, domain: &str) -> String {
+        match (server, kind) {
+            (ServerKind::Caddy, _) => format!("{}/{}", Self::caddy_sites_available(), domain),
+            // a2ensite and conf.d only pick up `*.conf`
+            (ServerKind::Apache, _) => format!("{}/{}.conf", Self::apache_sites_available(), domain),
+            (ServerKind::Haproxy, _) => format!("{}/{}.cfg", Self::haproxy_sites_dir(), domain),
+            (ServerKind::Traefik, _) => format!("{}/{}.yml", Self::traefik_dynamic_dir(), domain),
+            (ServerKind::Builtin, _) => String::new(),
+            (ServerKind::Nginx, SiteKind::Http | SiteKind::Redirect) => Self::nginx_config(domain),
+            (ServerKind::Nginx, SiteKind::Tcp) => Self::nginx_stream_config(domain),
+        }
+    }
+
+    /// Enabled symlink for a config file name (the domain for generated configs)
+    pub fn site_enabled(server: ServerKind, kind: SiteKind, domain: &str) -> String {
+        match (server, kind) {
+            (ServerKind::Caddy, _) => format!("{}/{}", Self::caddy_sites_enabled(), domain),
+            (ServerKind::Apache, _) => format!("{}/{}", Self::apache_sites_enabled(), domain),
+            (ServerKind::Haproxy, _) => format!("{}/{}", Self::haproxy_sites_dir(), domain),
+            (ServerKind::Traefik, _) => format!("{}/{}", Self::traefik_dynamic_dir(), domain),
+            (ServerKind::Builtin, _) => String::new(),
+            (ServerKind::Nginx, SiteKind::Http | SiteKind::Redirect) => Self::nginx_enabled(domain),
+            (ServerKind::Nginx, SiteKind::Tcp) => Self::nginx_stream_enabled(domain),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn http3_site(domain: &str, reuseport: bool) -> DomainConfig {
+        DomainConfig {
+            domain: domain.to_string(),
+            http3: true,
+            quic_reuseport: reuseport,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_rebalance_quic_reuseport_keeps_single_holder() {
+        let mut manager = ConfigManager::default();
+        manager.domains.insert("b.test".into(), http3_site("b.test", true));
+        manager.domains.insert("a.test".into(), http3_site("a.test", true));
+
+        let changed = manager.rebalance_quic_reuseport();
+        assert_eq!(changed, vec!["b.test".to_string()]);
+        assert!(manager.domains["a.test"].quic_reuseport);
+
+        manager.domains.get_mut("a.test").unwrap().http3 = false;
+        let changed = manager.rebalance_quic_reuseport();
+        assert_eq!(changed, vec!["a.test".to_string(), "b.test".to_string()]);
+        assert!(manager.domains["b.test"].quic_reuseport);
+    }
+
+    #[test]
+    fn test_rebalance_quic_reuseport_per_https_port() {
+        let mut manager = ConfigManager::default();
+        let mut other_port = http3_site("b.test", false);
+        other_port.https_port = Some(8443);
+        manager.domains.insert("a.test".into(), http3_site("a.test", false));
+        manager.domains.insert("b.test".into(), other_port);
+
+        manager.rebalance_quic_reuseport();
+        assert!(manager.domains["a.test"].quic_reuseport);
+        assert!(manager.domains["b.test"].quic_reuseport);
+    }
+
+    #[test]
+    fn test_url_includes_non_default_port() {
+        let mut site = DomainConfig {
+            domain: "app.test".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(site.url(), "https://app.test");
+
+        site.https_port = Some(8443);
+        assert_eq!(site.url(), "https://app.test:8443");
+    }
+}
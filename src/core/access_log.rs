@@ -0,0 +1,238 @@
+use crate::error::{Error, Result};
+
+/// One request from a log in the `combined` format:
+/// `$remote_addr - $remote_user [$time_local] "$request" $status $body_bytes_sent "$http_referer" "$http_user_agent"`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccessEntry {
+    pub remote_addr: String,
+    pub remote_user: Option<String>,
+    /// Unix timestamp
+    pub time: i64,
+    /// The timestamp as logged, e.g. `10/Oct/2026:13:55:36 +0200`
+    pub time_local: String,
+    pub method: String,
+    pub path: String,
+    pub protocol: String,
+    pub status: u16,
+    pub bytes: u64,
+    pub referer: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+/// `-` means "not set" in nginx and Apache logs
+fn optional(value: &str) -> Option<String> {
+    (value != "-" && !value.is_empty()).then(|| value.to_string())
+}
+
+/// Take a `"quoted"` field, honouring `\"` escapes
+fn quoted(rest: &str) -> Option<(String, &str)> {
+    let rest = rest.trim_start().strip_prefix('"')?;
+    let mut value = String::new();
+    let mut chars = rest.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => {
+                if let Some((_, escaped)) = chars.next() {
+                    value.push(escaped);
+                }
+            }
+            '"' => return Some((value, &rest[i + 1..])),
+            c => value.push(c),
+        }
+    }
+    None
+}
+
+fn word(rest: &str) -> Option<(&str, &str)> {
+    let rest = rest.trim_start();
+    let end = rest.find(' ').unwrap_or(rest.len());
+    (end > 0).then(|| (&rest[..end], &rest[end..]))
+}
+
+pub fn parse_access_line(line: &str) -> Option<AccessEntry> {
+    let (remote_addr, rest) = word(line)?;
+    let (_ident, rest) = word(rest)?;
+    let (remote_user, rest) = word(rest)?;
+
+    let rest = rest.trim_start().strip_prefix('[')?;
+    let (time_local, rest) = rest.split_once(']')?;
+    let time = parse_time_local(time_local)?;
+
+    let (request, rest) = quoted(rest)?;
+    let mut parts = request.splitn(3, ' ');
+    let method = parts.next().unwrap_or_default().to_string();
+    let path = parts.next().unwrap_or_default().to_string();
+    let protocol = parts.next().unwrap_or_default().to_string();
+
+    let (status, rest) = word(rest)?;
+    let (bytes, rest) = word(rest)?;
+    let (referer, user_agent) = match quoted(rest) {
+        Some((referer, rest)) => (optional(&referer), quoted(rest).and_then(|(ua, _)| optional(&ua))),
+        None => (None, None),
+    };
+
+    Some(AccessEntry {
+        remote_addr: remote_addr.to_string(),
+        remote_user: optional(remote_user),
+        time,
+        time_local: time_local.to_string(),
+        method,
+        path,
+        protocol,
+        status: status.parse().ok()?,
+        bytes: bytes.parse().unwrap_or(0),
+        referer,
+        user_agent,
+    })
+}
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+fn month(name: &str) -> Option<i64> {
+    MONTHS.iter().position(|m| *m == name).map(|i| i as i64 + 1)
+}
+
+/// Days since 1970-01-01 of a proleptic Gregorian date
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+fn timestamp(year: i64, month: i64, day: i64, clock: &str) -> Option<i64> {
+    let mut fields = clock.split(':');
+    let hour: i64 = fields.next()?.parse().ok()?;
+    let minute: i64 = fields.next()?.parse().ok()?;
+    // Apache error logs carry microseconds
+    let second: i64 = fields.next()?.split('.').next()?.parse().ok()?;
+    Some(days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60 + second)
+}
+
+/// `10/Oct/2026:13:55:36 +0200`
+fn parse_time_local(value: &str) -> Option<i64> {
+    let (datetime, zone) = value.split_once(' ')?;
+    let (date, clock) = datetime.split_once(':')?;
+    let mut date = date.split('/');
+    let day = date.next()?.parse().ok()?;
+    let month = month(date.next()?)?;
+    let year = date.next()?.parse().ok()?;
+
+    let sign = if zone.starts_with('-') { -1 } else { 1 };
+    let zone = zone.trim_start_matches(['+', '-']);
+    let offset = zone.get(..2)?.parse::<i64>().ok()? * 3600 + zone.get(2..4)?.parse::<i64>().ok()? * 60;
+
+    Some(timestamp(year, month, day, clock)? - sign * offset)
+}
+
+/// Timestamp of an error log line. nginx writes `2026/10/10 13:55:36 [error] ...`
+/// and Apache `[Sat Oct 10 13:55:36.123456 2026] [core:error] ...`, both in
+/// local time, so the caller passes the local UTC offset in seconds.
+pub fn parse_error_time(line: &str, utc_offset: i64) -> Option<i64> {
+    let local = if let Some(rest) = line.strip_prefix('[') {
+        let (stamp, _) = rest.split_once(']')?;
+        let fields: Vec<&str> = stamp.split_whitespace().collect();
+        let [_, month_name, day, clock, year] = fields.as_slice() else {
+            return None;
+        };
+        timestamp(year.parse().ok()?, month(month_name)?, day.parse().ok()?, clock)?
+    } else {
+        let (date, rest) = line.split_once(' ')?;
+        let clock = rest.split(' ').next()?;
+        let mut date = date.split('/');
+        let year = date.next()?.parse().ok()?;
+        let month = date.next()?.parse().ok()?;
+        let day = date.next()?.parse().ok()?;
+        timestamp(year, month, day, clock)?
+    };
+    Some(local - utc_offset)
+}
+
+/// `--status` values: `5xx`, `404`, `500-504`, comma separated
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatusFilter(Vec<(u16, u16)>);
+
+impl StatusFilter {
+    pub fn parse(value: &str) -> Result<Self> {
+        let invalid = || {
+            Error::Validation(format!(
+                "Invalid status filter '{}' (expected e.g. 5xx, 404 or 500-504)",
+                value
+            ))
+        };
+
+        let mut ranges = Vec::new();
+        for part in value.split(',').map(str::trim) {
+            let range = if let Some(class) = part.strip_suffix("xx").or_else(|| part.strip_suffix("XX")) {
+                let class: u16 = class.parse().map_err(|_| invalid())?;
+                if !(1..=5).contains(&class) {
+                    return Err(invalid());
+                }
+                (class * 100, class * 100 + 99)
+            } else if let Some((from, to)) = part.split_once('-') {
+                let from = from.parse().map_err(|_| invalid())?;
+                let to = to.parse().map_err(|_| invalid())?;
+                if from > to {
+                    return Err(invalid());
+                }
+                (from, to)
+            } else {
+                let status = part.parse().map_err(|_| invalid())?;
+                (status, status)
+            };
+            ranges.push(range);
+        }
+        Ok(StatusFilter(ranges))
+    }
+
+    pub fn matches(&self, status: u16) -> bool {
+        self.0.iter().any(|(from, to)| (*from..=*to).contains(&status))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_combined_line() {
+        let line = r#"10.0.0.7 - alice [18/Oct/2026:13:55:36 +0200] "GET /api/items?page=2 HTTP/2.0" 502 157 "https://app.test/" "curl/8.5 \"beta\"""#;
+        let entry = parse_access_line(line).unwrap();
+        assert_eq!(entry.remote_addr, "10.0.0.7");
+        assert_eq!(entry.remote_user.as_deref(), Some("alice"));
+        assert_eq!(entry.time, 1792324536);
+        assert_eq!(entry.method, "GET");
+        assert_eq!(entry.path, "/api/items?page=2");
+        assert_eq!(entry.protocol, "HTTP/2.0");
+        assert_eq!(entry.status, 502);
+        assert_eq!(entry.bytes, 157);
+        assert_eq!(entry.referer.as_deref(), Some("https://app.test/"));
+        assert_eq!(entry.user_agent.as_deref(), Some("curl/8.5 \"beta\""));
+
+        let bare = parse_access_line(r#"::1 - - [18/Oct/2026:11:55:36 +0000] "-" 400 0 "-" "-""#).unwrap();
+        assert_eq!(bare.time, entry.time);
+        assert_eq!(bare.remote_user, None);
+        assert_eq!(bare.user_agent, None);
+        assert!(parse_access_line("not a log line").is_none());
+    }
+
+    #[test]
+    fn test_error_times_and_status_filter() {
+        let nginx = "2026/10/18 13:55:36 [error] 12#12: *5 connect() failed (111: Connection refused)";
+        assert_eq!(parse_error_time(nginx, 7200), Some(1792324536));
+        let apache = "[Sun Oct 18 13:55:36.123456 2026] [proxy:error] [pid 42] AH00957: failed";
+        assert_eq!(parse_error_time(apache, 7200), Some(1792324536));
+
+        let filter = StatusFilter::parse("5xx,404").unwrap();
+        assert!(filter.matches(502));
+        assert!(filter.matches(404));
+        assert!(!filter.matches(403));
+        assert!(StatusFilter::parse("500-504").unwrap().matches(503));
+        assert!(StatusFilter::parse("6xx").is_err());
+        assert!(StatusFilter::parse("504-500").is_err());
+    }
+}
@@ -0,0 +1,611 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::sync::OnceLock;
+
+use crate::config::{DomainConfig, Paths, RedirectTarget, ServerKind, SiteKind};
+use crate::core::{disable_renamed, enable_renamed, error_pages, render_template, templates, WebServerConfig};
+use crate::error::{Error, Result};
+use crate::utils::{execute_command, is_dry_run, log_info, log_verbose, parse_header};
+
+/// Modules the generated virtual hosts use
+const MODULES: &[&str] = &["ssl", "proxy", "proxy_http", "headers", "rewrite", "alias"];
+
+/// MIME types compressed by mod_deflate
+const COMPRESSIBLE_TYPES: &str = "text/html text/plain text/css text/xml text/javascript application/json application/javascript application/xml application/rss+xml application/wasm image/svg+xml";
+
+static LAYOUT: OnceLock<ApacheLayout> = OnceLock::new();
+
+/// Where this host's Apache keeps its configuration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApacheLayout {
+    /// Debian's sites-available + a2ensite, otherwise RHEL's conf.d
+    pub a2ensite: bool,
+    pub conf_dir: String,
+    pub log_dir: String,
+}
+
+impl ApacheLayout {
+    pub fn debian() -> Self {
+        ApacheLayout {
+            a2ensite: true,
+            conf_dir: "/etc/apache2".to_string(),
+            log_dir: "/var/log/apache2".to_string(),
+        }
+    }
+
+    pub fn rhel() -> Self {
+        ApacheLayout {
+            a2ensite: false,
+            conf_dir: "/etc/httpd".to_string(),
+            log_dir: "/var/log/httpd".to_string(),
+        }
+    }
+
+    /// Debian's apache2 package lives in /etc/apache2, RHEL's httpd in /etc/httpd
+    pub fn detect() -> Self {
+        if Path::new("/etc/apache2").is_dir() {
+            Self::debian()
+        } else {
+            Self::rhel()
+        }
+    }
+
+    /// Layout of this host, detected on first use unless one was set with `init`
+    pub fn global() -> &'static ApacheLayout {
+        LAYOUT.get_or_init(|| {
+            let layout = Self::detect();
+            log_verbose(&format!("Using Apache layout: {:?}", layout));
+            layout
+        })
+    }
+
+    /// Use `layout` instead of detecting one; has no effect after the first `global()`
+    #[cfg(test)]
+    pub fn init(layout: ApacheLayout) {
+        let _ = LAYOUT.set(layout);
+    }
+
+    pub fn sites_available(&self) -> String {
+        if self.a2ensite {
+            format!("{}/sites-available", self.conf_dir)
+        } else {
+            format!("{}/conf.d", self.conf_dir)
+        }
+    }
+
+    pub fn sites_enabled(&self) -> String {
+        if self.a2ensite {
+            format!("{}/sites-enabled", self.conf_dir)
+        } else {
+            self.sites_available()
+        }
+    }
+
+    pub fn shared_include(&self) -> String {
+        if self.a2ensite {
+            format!("{}/conf-enabled/localstacker.conf", self.conf_dir)
+        } else {
+            format!("{}/conf.d/localstacker.conf", self.conf_dir)
+        }
+    }
+}
+
+/// Modules from `MODULES` that `httpd -M` output doesn't list as loaded
+fn missing_modules(loaded: &str) -> Vec<&'static str> {
+    let loaded: BTreeSet<&str> = loaded
+        .lines()
+        .filter_map(|line| line.split_whitespace().next())
+        .filter_map(|name| name.strip_suffix("_module"))
+        .collect();
+    MODULES.iter().copied().filter(|module| !loaded.contains(module)).collect()
+}
+
+/// RHEL has no a2enmod; modules are loaded from conf.modules.d and mod_ssl
+/// is a separate package, so only check that they are there
+fn check_modules() -> Result<()> {
+    if is_dry_run() {
+        return Ok(());
+    }
+
+    let output = Command::new("httpd")
+        .arg("-M")
+        .output()
+        .map_err(|e| Error::Command(format!("Failed to execute httpd -M: {}", e)))?;
+    let missing = missing_modules(&String::from_utf8_lossy(&output.stdout));
+    if !missing.is_empty() {
+        return Err(Error::Validation(format!(
+            "Apache doesn't load the {} module(s) localstacker sites need. Install mod_ssl if ssl is missing and load the others in {}/conf.modules.d",
+            missing.join(", "),
+            ApacheLayout::global().conf_dir
+        )));
+    }
+    Ok(())
+}
+
+/// Apache httpd backend: Debian's sites-available + a2ensite, or RHEL's
+/// conf.d where disabled sites are renamed to `*.conf.disabled`
+pub struct ApacheConfig;
+
+impl ApacheConfig {
+    pub fn new() -> Self {
+        ApacheConfig
+    }
+
+    fn quote(value: &str) -> String {
+        format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+
+    fn header_directives(site: &DomainConfig) -> String {
+        let mut headers: Vec<(String, String)> = site
+            .headers
+            .profile
+            .headers()
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        headers.extend(site.headers.custom.iter().filter_map(|h| parse_header(h).ok()));
+
+        if headers.is_empty() {
+            return String::new();
+        }
+
+        let mut out = String::from("\n\n    # Security Headers");
+        for (name, value) in headers {
+            out.push_str(&format!("\n    Header always set {} {}", name, Self::quote(&value)));
+        }
+        out
+    }
+
+    /// `Require` rules for `<Location />`: allow rules win, then deny rules
+    /// apply (nginx semantics), optionally combined with basic auth
+    fn require_rules(site: &DomainConfig, with_auth: bool) -> Vec<String> {
+        let access = &site.access;
+        let mut rules = Vec::new();
+
+        if !access.deny.is_empty() {
+            let denied = if access.deny.iter().any(|source| source == "all") {
+                "Require all denied".to_string()
+            } else {
+                format!(
+                    "<RequireAll>\n    Require all granted\n    Require not ip {}\n</RequireAll>",
+                    access.deny.join(" ")
+                )
+            };
+
+            if access.allow.is_empty() {
+                rules.push(denied);
+            } else {
+                rules.push(format!(
+                    "<RequireAny>\n    Require ip {}\n{}\n</RequireAny>",
+                    access.allow.join(" "),
+                    indent(&denied, "    ")
+                ));
+            }
+        }
+
+        if with_auth {
+            rules.push("Require valid-user".to_string());
+        }
+
+        rules
+    }
+
+    fn location_directives(site: &DomainConfig) -> String {
+        let rules = Self::require_rules(site, site.basic_auth);
+        if rules.is_empty() {
+            return String::new();
+        }
+
+        let mut out = String::from("\n\n    # Access Control\n    <Location />");
+        if site.basic_auth {
+            out.push_str(&format!(
+                "\n        AuthType Basic\n        AuthName \"Restricted\"\n        AuthUserFile {}",
+                Paths::htpasswd(ServerKind::Apache, &site.domain)
+            ));
+        }
+        out.push_str("\n        <RequireAll>");
+        for rule in &rules {
+            out.push_str(&format!("\n{}", indent(rule, "            ")));
+        }
+        out.push_str("\n        </RequireAll>\n    </Location>");
+
+        // Basic auth covers everything except /.well-known (ACME, etc.)
+        if site.basic_auth {
+            let rule = Self::require_rules(site, false)
+                .into_iter()
+                .next()
+                .unwrap_or_else(|| "Require all granted".to_string());
+            out.push_str(&format!(
+                "\n    <Location /.well-known/>\n{}\n    </Location>",
+                indent(&rule, "        ")
+            ));
+        }
+
+        out
+    }
+
+    fn compression_directives(site: &DomainConfig) -> String {
+        if site.gzip {
+            format!(
+                "\n\n    # Compression\n    AddOutputFilterByType DEFLATE {}",
+                COMPRESSIBLE_TYPES
+            )
+        } else {
+            String::new()
+        }
+    }
+
+    /// Static error pages and the maintenance switch
+    fn error_page_directives(site: &DomainConfig) -> String {
+        let dir = Paths::error_pages_dir(&site.domain);
+        let location = error_pages::LOCATION;
+
+        let mut out = format!(
+            "\n\n    # Error Pages\n    ProxyPass {loc} !\n    Alias {loc} {dir}/\n    <Directory {dir}>\n        Require all granted\n    </Directory>\n    ErrorDocument 502 {loc}{down}\n    ErrorDocument 504 {loc}{down}",
+            loc = location,
+            dir = dir,
+            down = error_pages::BACKEND_DOWN_PAGE,
+        );
+
+        if site.maintenance {
+            out.push_str(&format!(
+                "\n    ErrorDocument 503 {loc}{page}\n\n    # Maintenance mode\n    RewriteEngine On\n    RewriteCond %{{REQUEST_URI}} !^/\\.well-known/\n    RewriteCond %{{REQUEST_URI}} !^{escaped}\n    RewriteRule ^ - [R=503,L]",
+                loc = location,
+                page = error_pages::MAINTENANCE_PAGE,
+                escaped = location.replace('.', "\\."),
+            ));
+        } else {
+            // mod_proxy answers 503 when the backend refuses connections
+            out.push_str(&format!(
+                "\n    ErrorDocument 503 {}{}",
+                location,
+                error_pages::BACKEND_DOWN_PAGE
+            ));
+        }
+
+        out
+    }
+
+    fn redirect_vhost(site: &DomainConfig) -> String {
+        format!(
+            r#"<VirtualHost *:{http_port}>
+    ServerName {domain}
+
+    # Redirect all HTTP to HTTPS
+    RewriteEngine On
+    RewriteRule ^ {url}%{{REQUEST_URI}} [R=301,L]
+</VirtualHost>"#,
+            http_port = site.effective_http_port(),
+            domain = site.domain,
+            url = site.url(),
+        )
+    }
+
+    fn ssl_directives(site: &DomainConfig) -> String {
+        format!(
+            "    SSLEngine on\n    SSLCertificateFile {}\n    SSLCertificateKeyFile {}\n    SSLProtocol -all +TLSv1.2 +TLSv1.3",
+            site.ssl_cert_path, site.ssl_key_path
+        )
+    }
+
+    fn default_template(site: &DomainConfig) -> String {
+        format!(
+            r#"# Generated by localstacker
+# Domain: {domain}
+# Backend: 127.0.0.1:{port}
+
+{redirect}
+
+<VirtualHost *:{https_port}>
+    ServerName {domain}
+
+    # SSL Configuration
+{ssl}{headers}{compression}{location}{errors}
+
+    # Reverse Proxy
+    ProxyPreserveHost On
+    ProxyPass / http://127.0.0.1:{port}/
+    ProxyPassReverse / http://127.0.0.1:{port}/
+    RequestHeader set X-Forwarded-Proto "https"
+    RequestHeader set X-Forwarded-Port "{https_port}"
+
+    # Logging
+    ErrorLog {log_dir}/{domain}_error.log
+    CustomLog {log_dir}/{domain}_access.log combined
+</VirtualHost>
+"#,
+            domain = site.domain,
+            port = site.port,
+            redirect = Self::redirect_vhost(site),
+            https_port = site.effective_https_port(),
+            ssl = Self::ssl_directives(site),
+            headers = Self::header_directives(site),
+            compression = Self::compression_directives(site),
+            location = Self::location_directives(site),
+            errors = Self::error_page_directives(site),
+            log_dir = Paths::apache_log_dir(),
+        )
+    }
+
+    fn redirect_template(site: &DomainConfig, target: &RedirectTarget) -> String {
+        let path = if target.preserve_path { "%{REQUEST_URI}" } else { "" };
+        let rule = format!(
+            "    RewriteEngine On\n    RewriteRule ^ {}{} [R={},L]",
+            target.to, path, target.code
+        );
+
+        format!(
+            r#"# Generated by localstacker
+# Domain: {domain} (redirect)
+
+<VirtualHost *:{http_port}>
+    ServerName {domain}
+{rule}
+</VirtualHost>
+
+<VirtualHost *:{https_port}>
+    ServerName {domain}
+{ssl}
+{rule}
+</VirtualHost>
+"#,
+            domain = site.domain,
+            http_port = site.effective_http_port(),
+            https_port = site.effective_https_port(),
+            ssl = Self::ssl_directives(site),
+            rule = rule,
+        )
+    }
+
+    /// `Listen` lines for ports the distro config doesn't already open
+    fn shared_config(sites: &[&DomainConfig]) -> String {
+        let mut http = BTreeSet::new();
+        let mut https = BTreeSet::new();
+        for site in sites.iter().filter(|s| s.server == ServerKind::Apache) {
+            http.insert(site.effective_http_port());
+            https.insert(site.effective_https_port());
+        }
+
+        let mut out = String::from("# Managed by localstacker - do not edit\n");
+        for port in http.into_iter().filter(|port| *port != 80) {
+            out.push_str(&format!("Listen {}\n", port));
+        }
+        for port in https.into_iter().filter(|port| *port != 443) {
+            out.push_str(&format!("Listen {} https\n", port));
+        }
+        out
+    }
+
+    fn file_name(site: &DomainConfig) -> String {
+        Path::new(&site.config_path)
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| format!("{}.conf", site.domain))
+    }
+}
+
+impl WebServerConfig for ApacheConfig {
+    fn generate_config(&self, site: &DomainConfig, template: Option<&str>) -> Result<String> {
+        log_verbose(&format!("Generating Apache config for {}", site.domain));
+
+        let unsupported = self.unsupported_features(site);
+        if !unsupported.is_empty() {
+            return Err(Error::Validation(format!(
+                "Apache sites don't support {}; use --server nginx",
+                unsupported.join(", ")
+            )));
+        }
+
+        if let Some(template_path) = template {
+            return render_template(template_path, site);
+        }
+
+        match site.kind {
+            SiteKind::Redirect => {
+                let target = site.redirect.as_ref().ok_or_else(|| {
+                    Error::Config(format!("Redirect site {} has no target", site.domain))
+                })?;
+                Ok(Self::redirect_template(site, target))
+            }
+            _ => Ok(Self::default_template(site)),
+        }
+    }
+
+    fn unsupported_features(&self, site: &DomainConfig) -> Vec<&'static str> {
+        let mut unsupported = Vec::new();
+        if site.kind == SiteKind::Tcp {
+            unsupported.push("TCP sites");
+        }
+        if site.http3 {
+            unsupported.push("HTTP/3");
+        }
+        if site.access.rate_limit.is_some() {
+            unsupported.push("rate limiting");
+        }
+        if !site.headers.cors_origins.is_empty() {
+            unsupported.push("CORS");
+        }
+        if site.brotli {
+            unsupported.push("brotli");
+        }
+        if site.cache_ttl.is_some() {
+            unsupported.push("proxy caching");
+        }
+        templates::add_missing_options(&mut unsupported, site);
+        unsupported
+    }
+
+    fn write_shared_config(&self, sites: &[&DomainConfig]) -> Result<()> {
+        let path = Paths::apache_shared_include();
+        log_verbose(&format!("Writing shared Apache config {}", path));
+
+        if let Some(parent) = Path::new(&path).parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| Error::Config(format!("Failed to create {}: {}", parent.display(), e)))?;
+        }
+
+        fs::write(&path, Self::shared_config(sites))
+            .map_err(|e| Error::Config(format!("Failed to write {}: {}", path, e)))?;
+
+        Ok(())
+    }
+
+    fn write_config(&self, site: &DomainConfig, config: &str) -> Result<()> {
+        log_info(&format!("Writing Apache configuration for {}...", site.domain));
+
+        fs::create_dir_all(Paths::apache_sites_available())
+            .map_err(|e| Error::Config(format!("Failed to create sites directory: {}", e)))?;
+        if site.kind == SiteKind::Http {
+            error_pages::write(site)?;
+        }
+
+        fs::write(&site.config_path, config)
+            .map_err(|e| Error::Config(format!("Failed to write Apache config: {}", e)))?;
+
+        Ok(())
+    }
+
+    fn enable_site(&self, site: &DomainConfig) -> Result<()> {
+        log_info(&format!("Enabling site {}...", site.domain));
+
+        if Paths::apache_uses_a2ensite() {
+            let mut args = vec!["-q"];
+            args.extend_from_slice(MODULES);
+            execute_command("a2enmod", &args, "Enable Apache modules")?;
+            execute_command("a2ensite", &["-q", &Self::file_name(site)], "Enable site")?;
+            return Ok(());
+        }
+        check_modules()?;
+
+        enable_renamed(&site.config_path)
+    }
+
+    fn disable_site(&self, site: &DomainConfig) -> Result<()> {
+        log_info(&format!("Disabling site {}...", site.domain));
+
+        if !Paths::apache_uses_a2ensite() {
+            return disable_renamed(&site.config_path);
+        }
+
+        if !Path::new(&site.enabled_link()).exists() {
+            log_verbose("Site already disabled");
+            return Ok(());
+        }
+
+        execute_command("a2dissite", &["-q", &Self::file_name(site)], "Disable site")?;
+        Ok(())
+    }
+
+    fn site_paths(&self, site: &DomainConfig) -> Vec<String> {
+        let mut paths = vec![site.config_path.clone(), site.enabled_link()];
+        if paths[0] == paths[1] {
+            paths[1] = Paths::disabled_config(&site.config_path);
+        }
+        if site.kind == SiteKind::Http {
+            paths.push(Paths::error_pages_dir(&site.domain));
+        }
+        paths
+    }
+
+    fn shared_paths(&self) -> Vec<String> {
+        vec![Paths::apache_shared_include()]
+    }
+
+    fn test_config(&self) -> Result<()> {
+        log_info("Testing Apache configuration...");
+        execute_command("apachectl", &["configtest"], "Test Apache config")?;
+        Ok(())
+    }
+
+    fn reload(&self) -> Result<()> {
+        log_info("Reloading Apache gracefully...");
+        execute_command("apachectl", &["graceful"], "Reload Apache")?;
+        Ok(())
+    }
+}
+
+fn indent(text: &str, prefix: &str) -> String {
+    text.lines()
+        .map(|line| format!("{}{}", prefix, line))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AccessPolicy;
+
+    fn site(domain: &str, port: u16) -> DomainConfig {
+        ApacheLayout::init(ApacheLayout::debian());
+        DomainConfig {
+            domain: domain.to_string(),
+            port,
+            server: ServerKind::Apache,
+            ssl_cert_path: Paths::ssl_cert(ServerKind::Apache, domain),
+            ssl_key_path: Paths::ssl_key(ServerKind::Apache, domain),
+            config_path: Paths::site_config(ServerKind::Apache, SiteKind::Http, domain),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_default_template() {
+        let mut app = site("app.test", 3000);
+        app.https_port = Some(8443);
+        app.basic_auth = true;
+        app.access = AccessPolicy {
+            allow: vec!["10.0.0.0/8".to_string()],
+            deny: vec!["all".to_string()],
+            ..Default::default()
+        };
+
+        let config = ApacheConfig::new().generate_config(&app, None).unwrap();
+        assert!(config.contains("<VirtualHost *:8443>"));
+        assert!(config.contains("RewriteRule ^ https://app.test:8443%{REQUEST_URI} [R=301,L]"));
+        assert!(config.contains("SSLCertificateFile /etc/apache2/ssl/app.test.pem"));
+        assert!(config.contains("ProxyPass / http://127.0.0.1:3000/"));
+        assert!(config.contains("AuthUserFile /etc/apache2/htpasswd/app.test"));
+        assert!(config.contains("<RequireAny>\n                Require ip 10.0.0.0/8\n                Require all denied"));
+        assert!(config.contains("Require valid-user"));
+        assert!(config.contains("<Location /.well-known/>"));
+        assert!(config.contains("ErrorDocument 503 /.localstacker/502.html"));
+
+        app.maintenance = true;
+        let config = ApacheConfig::new().generate_config(&app, None).unwrap();
+        assert!(config.contains("RewriteRule ^ - [R=503,L]"));
+        assert!(config.contains("ErrorDocument 503 /.localstacker/maintenance.html"));
+
+        assert_eq!(app.enabled_link(), "/etc/apache2/sites-enabled/app.test.conf");
+    }
+
+    #[test]
+    fn test_rhel_layout_and_modules() {
+        let rhel = ApacheLayout::rhel();
+        assert_eq!(rhel.sites_available(), "/etc/httpd/conf.d");
+        assert_eq!(rhel.sites_enabled(), rhel.sites_available());
+        assert_eq!(rhel.shared_include(), "/etc/httpd/conf.d/localstacker.conf");
+
+        let loaded = "Loaded Modules:\n core_module (static)\n ssl_module (shared)\n proxy_module (shared)\n headers_module (shared)\n alias_module (shared)\n";
+        assert_eq!(missing_modules(loaded), vec!["proxy_http", "rewrite"]);
+    }
+
+    #[test]
+    fn test_shared_config_opens_extra_ports() {
+        let mut a = site("a.test", 3000);
+        a.http_port = Some(8080);
+        a.https_port = Some(8443);
+        let b = site("b.test", 3001);
+        let mut nginx = site("n.test", 3002);
+        nginx.server = ServerKind::Nginx;
+        nginx.https_port = Some(9443);
+
+        let shared = ApacheConfig::shared_config(&[&a, &b, &nginx]);
+        assert!(shared.contains("Listen 8080\n"));
+        assert!(shared.contains("Listen 8443 https\n"));
+        assert!(!shared.contains("Listen 443"));
+        assert!(!shared.contains("9443"));
+    }
+}
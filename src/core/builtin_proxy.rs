@@ -0,0 +1,252 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::ErrorKind;
+use std::net::TcpListener;
+use std::path::Path;
+
+use crate::config::{DomainConfig, Paths, ServerKind, SiteKind};
+use crate::core::proxy::load_certified_key;
+use crate::core::WebServerConfig;
+use crate::error::{Error, Result};
+use crate::utils::{execute_command, log_info, log_verbose, log_warning};
+
+const SERVICE: &str = "localstacker-serve.service";
+
+/// Backend for `localstacker serve`: the proxy reads its routes straight
+/// from domains.json and reloads them itself, so there are no per-site
+/// files. Applying a change makes sure the daemon is running.
+pub struct BuiltinProxyConfig;
+
+impl BuiltinProxyConfig {
+    pub fn new() -> Self {
+        BuiltinProxyConfig
+    }
+
+    fn has_systemd() -> bool {
+        Path::new("/run/systemd/system").is_dir()
+    }
+
+    fn unit() -> Result<String> {
+        let exe = std::env::current_exe()?;
+        Ok(format!(
+            r#"# Managed by localstacker - do not edit
+[Unit]
+Description=localstacker built-in TLS reverse proxy
+After=network.target
+
+[Service]
+ExecStart={} serve
+Restart=on-failure
+
+[Install]
+WantedBy=multi-user.target
+"#,
+            exe.display()
+        ))
+    }
+}
+
+/// Inodes of the sockets listening on `port`, from /proc/net/tcp{,6}
+fn listening_sockets(port: u16) -> BTreeSet<String> {
+    let mut inodes = BTreeSet::new();
+    for table in ["/proc/net/tcp", "/proc/net/tcp6"] {
+        let contents = fs::read_to_string(table).unwrap_or_default();
+        inodes.extend(parse_listening(&contents, port));
+    }
+    inodes
+}
+
+/// `local_address` is `HEXIP:HEXPORT`, state `0A` is LISTEN
+fn parse_listening(table: &str, port: u16) -> Vec<String> {
+    table
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (_, local_port) = fields.get(1)?.rsplit_once(':')?;
+            let listening = *fields.get(3)? == "0A" && u16::from_str_radix(local_port, 16).ok()? == port;
+            listening.then(|| fields.get(9).map(|inode| inode.to_string()))?
+        })
+        .collect()
+}
+
+/// Whether process `pid` has one of `sockets` open
+fn holds_socket(pid: u32, sockets: &BTreeSet<String>) -> bool {
+    let Ok(fds) = fs::read_dir(format!("/proc/{}/fd", pid)) else {
+        return false;
+    };
+    fds.flatten().any(|fd| {
+        fs::read_link(fd.path()).ok().is_some_and(|target| {
+            target
+                .to_str()
+                .and_then(|target| target.strip_prefix("socket:["))
+                .and_then(|inode| inode.strip_suffix(']'))
+                .is_some_and(|inode| sockets.contains(inode))
+        })
+    })
+}
+
+/// The proxy only logs ports it can't bind, so make sure every port the
+/// sites need is free or already held by the running proxy
+fn check_ports(sites: &[&DomainConfig]) -> Result<()> {
+    let ports: BTreeSet<u16> = sites
+        .iter()
+        .filter(|site| site.server == ServerKind::Builtin)
+        .flat_map(|site| site.listen_ports())
+        .collect();
+
+    for port in ports {
+        match TcpListener::bind(("0.0.0.0", port)) {
+            Err(e) if e.kind() == ErrorKind::AddrInUse => {}
+            _ => continue,
+        }
+        if proxy_pid().is_some_and(|pid| holds_socket(pid, &listening_sockets(port))) {
+            continue;
+        }
+        return Err(Error::Validation(format!(
+            "Port {} is already in use by another program, the built-in proxy can't listen on it",
+            port
+        )));
+    }
+    Ok(())
+}
+
+/// Pid of the running `localstacker serve`
+pub fn proxy_pid() -> Option<u32> {
+    let contents = fs::read_to_string(Paths::serve_pid_file()).ok()?;
+    let pid: u32 = contents.trim().parse().ok().filter(|pid| *pid > 0)?;
+    Path::new(&format!("/proc/{}", pid)).exists().then_some(pid)
+}
+
+impl WebServerConfig for BuiltinProxyConfig {
+    /// A summary of the route, stored as the site's config hash
+    fn generate_config(&self, site: &DomainConfig, _template: Option<&str>) -> Result<String> {
+        log_verbose(&format!("Generating built-in proxy route for {}", site.domain));
+
+        let unsupported = self.unsupported_features(site);
+        if !unsupported.is_empty() {
+            return Err(Error::Validation(format!(
+                "Built-in proxy sites don't support {}; use --server nginx",
+                unsupported.join(", ")
+            )));
+        }
+
+        let target = match site.redirect {
+            Some(ref redirect) => format!("redirect {} {}", redirect.code, redirect.to),
+            None => format!("proxy 127.0.0.1:{}", site.port),
+        };
+        Ok(format!("{} -> {}\n", site.url(), target))
+    }
+
+    fn unsupported_features(&self, site: &DomainConfig) -> Vec<&'static str> {
+        let mut unsupported = Vec::new();
+        if site.kind == SiteKind::Tcp {
+            unsupported.push("TCP sites");
+        }
+        if site.template.is_some() {
+            unsupported.push("config templates");
+        }
+        if site.http3 {
+            unsupported.push("HTTP/3");
+        }
+        if site.access.rate_limit.is_some() {
+            unsupported.push("rate limiting");
+        }
+        if !site.headers.cors_origins.is_empty() {
+            unsupported.push("CORS");
+        }
+        if site.gzip || site.brotli {
+            unsupported.push("compression");
+        }
+        if site.cache_ttl.is_some() {
+            unsupported.push("proxy caching");
+        }
+        unsupported
+    }
+
+    fn write_config(&self, site: &DomainConfig, _config: &str) -> Result<()> {
+        log_verbose(&format!("The built-in proxy reads {} from domains.json", site.domain));
+        Ok(())
+    }
+
+    fn enable_site(&self, _site: &DomainConfig) -> Result<()> {
+        Ok(())
+    }
+
+    fn disable_site(&self, _site: &DomainConfig) -> Result<()> {
+        Ok(())
+    }
+
+    fn site_paths(&self, _site: &DomainConfig) -> Vec<String> {
+        Vec::new()
+    }
+
+    fn shared_paths(&self) -> Vec<String> {
+        if Self::has_systemd() {
+            vec![Paths::serve_unit().to_string()]
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Check the sites' ports and install the systemd unit that runs the
+    /// proxy. The unit is written whenever this backend is used.
+    fn write_shared_config(&self, sites: &[&DomainConfig]) -> Result<()> {
+        check_ports(sites)?;
+        if !Self::has_systemd() {
+            return Ok(());
+        }
+
+        log_verbose(&format!("Writing {}", Paths::serve_unit()));
+        fs::write(Paths::serve_unit(), Self::unit()?)
+            .map_err(|e| Error::Config(format!("Failed to write {}: {}", Paths::serve_unit(), e)))?;
+        Ok(())
+    }
+
+    /// Every certificate in the proxy's store must load
+    fn test_config(&self) -> Result<()> {
+        let dir = Paths::ssl_dir(ServerKind::Builtin);
+        log_info(&format!("Checking certificates in {}...", dir));
+
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(_) => return Ok(()),
+        };
+        for entry in entries.flatten() {
+            let key_path = entry.path().to_string_lossy().to_string();
+            if let Some(base) = key_path.strip_suffix("-key.pem") {
+                load_certified_key(&format!("{}.pem", base), &key_path)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The proxy picks up domains.json changes itself; only make sure it runs
+    fn reload(&self) -> Result<()> {
+        if Self::has_systemd() {
+            log_info("Starting the built-in proxy...");
+            execute_command("systemctl", &["daemon-reload"], "Reload systemd units")?;
+            execute_command("systemctl", &["enable", "--now", SERVICE], "Start built-in proxy")?;
+        } else if proxy_pid().is_none() {
+            log_warning("The built-in proxy is not running, start it with `localstacker serve`");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_listening() {
+        let table = "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode
+   0: 00000000:01BB 00000000:0000 0A 00000000:00000000 00:00000000 00000000     0        0 41234 1 0000000000000000 100 0 0 10 0
+   1: 0100007F:1F90 00000000:0000 0A 00000000:00000000 00:00000000 00000000     0        0 41235 1 0000000000000000 100 0 0 10 0
+   2: 0100007F:01BB 0100007F:D2F0 01 00000000:00000000 00:00000000 00000000     0        0 41236 1 0000000000000000 20 4 30 10 -1
+";
+        assert_eq!(parse_listening(table, 443), ["41234"]);
+        assert_eq!(parse_listening(table, 8080), ["41235"]);
+        assert!(parse_listening(table, 80).is_empty());
+    }
+}
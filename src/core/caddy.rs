@@ -0,0 +1,400 @@
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use crate::config::{AccessPolicy, DomainConfig, Paths, RedirectTarget, SiteKind};
+use crate::core::{error_pages, render_template, templates, WebServerConfig};
+use crate::error::{Error, Result};
+use crate::utils::{execute_command, log_info, log_verbose, parse_header};
+
+/// Caddy backend: one Caddyfile snippet per site in sites-available,
+/// symlinked into sites-enabled, which the main Caddyfile imports
+pub struct CaddyConfig;
+
+impl CaddyConfig {
+    pub fn new() -> Self {
+        CaddyConfig
+    }
+
+    fn import_line() -> String {
+        format!("import {}/*", Paths::caddy_sites_enabled())
+    }
+
+    fn quote(value: &str) -> String {
+        format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+
+    fn header_block(site: &DomainConfig) -> String {
+        let mut headers: Vec<(String, String)> = site
+            .headers
+            .profile
+            .headers()
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        headers.extend(site.headers.custom.iter().filter_map(|h| parse_header(h).ok()));
+
+        if headers.is_empty() {
+            return String::new();
+        }
+
+        let mut out = String::from("\n\theader {");
+        for (name, value) in headers {
+            out.push_str(&format!("\n\t\t{} {}", name, Self::quote(&value)));
+        }
+        out.push_str("\n\t}\n");
+        out
+    }
+
+    /// Answer 403 to the clients the `AccessPolicy` denies
+    fn access_block(site: &DomainConfig) -> String {
+        let access = &site.access;
+        if access.deny.is_empty() {
+            return String::new();
+        }
+
+        let mut out = String::from("\n\t@blocked {");
+        if !access.allow.is_empty() {
+            out.push_str(&format!("\n\t\tnot remote_ip {}", AccessPolicy::ranges(&access.allow)));
+        }
+        out.push_str(&format!("\n\t\tremote_ip {}", AccessPolicy::ranges(&access.deny)));
+        out.push_str("\n\t}\n\trespond @blocked 403\n");
+        out
+    }
+
+    /// Serve the static error pages for backend errors, and the
+    /// maintenance page for the 503 raised in maintenance mode
+    fn error_block(site: &DomainConfig) -> String {
+        let serve = |matcher: &str, page: &str| {
+            format!(
+                "\n\t\thandle {matcher} {{\n\t\t\troot * {dir}\n\t\t\trewrite * /{page}\n\t\t\tfile_server\n\t\t}}",
+                matcher = matcher,
+                dir = Paths::error_pages_dir(&site.domain),
+                page = page,
+            )
+        };
+
+        let mut out = String::from("\n\thandle_errors {");
+        if site.maintenance {
+            out.push_str("\n\t\t@maintenance expression {err.status_code} == 503");
+            out.push_str(&serve("@maintenance", error_pages::MAINTENANCE_PAGE));
+            out.push_str("\n\t\t@backend expression {err.status_code} in [502, 504]");
+        } else {
+            out.push_str("\n\t\t@backend expression {err.status_code} in [502, 503, 504]");
+        }
+        out.push_str(&serve("@backend", error_pages::BACKEND_DOWN_PAGE));
+        out.push_str("\n\t}\n");
+        out
+    }
+
+    fn default_template(site: &DomainConfig) -> String {
+        let maintenance = if site.maintenance {
+            "\n\t# Maintenance mode (localstacker maintenance off to disable)\n\t@maintenance not path /.well-known/*\n\terror @maintenance 503\n"
+        } else {
+            ""
+        };
+        let encode = if site.gzip { "\n\tencode gzip\n" } else { "" };
+
+        format!(
+            r#"# Generated by localstacker for {domain}
+
+http://{domain}:{http_port} {{
+	redir {url}{{uri}} permanent
+}}
+
+https://{domain}:{https_port} {{
+	tls {cert} {key}
+{headers}{encode}{access}{maintenance}
+	reverse_proxy 127.0.0.1:{port}
+{errors}}}
+"#,
+            domain = site.domain,
+            http_port = site.effective_http_port(),
+            https_port = site.effective_https_port(),
+            url = site.url(),
+            cert = site.ssl_cert_path,
+            key = site.ssl_key_path,
+            headers = Self::header_block(site),
+            encode = encode,
+            access = Self::access_block(site),
+            maintenance = maintenance,
+            port = site.port,
+            errors = Self::error_block(site),
+        )
+    }
+
+    fn redirect_template(site: &DomainConfig, target: &RedirectTarget) -> String {
+        let to = if target.preserve_path {
+            format!("{}{{uri}}", target.to)
+        } else {
+            target.to.clone()
+        };
+
+        format!(
+            r#"# Generated by localstacker for {domain} (redirect)
+
+http://{domain}:{http_port} {{
+	redir {to} {code}
+}}
+
+https://{domain}:{https_port} {{
+	tls {cert} {key}
+	redir {to} {code}
+}}
+"#,
+            domain = site.domain,
+            http_port = site.effective_http_port(),
+            https_port = site.effective_https_port(),
+            cert = site.ssl_cert_path,
+            key = site.ssl_key_path,
+            to = to,
+            code = target.code,
+        )
+    }
+
+    /// Make sure the main Caddyfile imports the enabled sites
+    fn ensure_import(&self) -> Result<()> {
+        let main_config = Paths::caddy_main_config();
+        let line = Self::import_line();
+        let current = fs::read_to_string(main_config).unwrap_or_default();
+
+        if current.lines().any(|l| l.trim() == line) {
+            return Ok(());
+        }
+
+        log_info(&format!("Adding localstacker import to {}...", main_config));
+        let mut updated = current;
+        if !updated.is_empty() && !updated.ends_with('\n') {
+            updated.push('\n');
+        }
+        updated.push_str(&format!("\n# Sites managed by localstacker\n{}\n", line));
+
+        fs::write(main_config, updated)
+            .map_err(|e| Error::Config(format!("Failed to update {}: {}", main_config, e)))?;
+
+        Ok(())
+    }
+
+    /// Caddy runs as its own user; let it read the certificate and key
+    fn share_certificate(site: &DomainConfig) -> Result<()> {
+        let has_caddy_user = Command::new("id")
+            .arg("caddy")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .map(|status| status.success())
+            .unwrap_or(false);
+
+        if !has_caddy_user || !Path::new(&site.ssl_key_path).exists() {
+            return Ok(());
+        }
+
+        execute_command(
+            "chown",
+            &["root:caddy", &site.ssl_cert_path, &site.ssl_key_path],
+            "Share certificate with caddy",
+        )?;
+        execute_command("chmod", &["640", &site.ssl_key_path], "Share certificate with caddy")?;
+        Ok(())
+    }
+}
+
+impl WebServerConfig for CaddyConfig {
+    fn generate_config(&self, site: &DomainConfig, template: Option<&str>) -> Result<String> {
+        log_verbose(&format!("Generating Caddy config for {}", site.domain));
+
+        let unsupported = self.unsupported_features(site);
+        if !unsupported.is_empty() {
+            return Err(Error::Validation(format!(
+                "Caddy sites don't support {}; use --server nginx",
+                unsupported.join(", ")
+            )));
+        }
+
+        if let Some(template_path) = template {
+            return render_template(template_path, site);
+        }
+
+        match site.kind {
+            SiteKind::Redirect => {
+                let target = site.redirect.as_ref().ok_or_else(|| {
+                    Error::Config(format!("Redirect site {} has no target", site.domain))
+                })?;
+                Ok(Self::redirect_template(site, target))
+            }
+            _ => Ok(Self::default_template(site)),
+        }
+    }
+
+    /// Options the Caddyfile output has no equivalent for without plugins
+    fn unsupported_features(&self, site: &DomainConfig) -> Vec<&'static str> {
+        let mut unsupported = Vec::new();
+        if site.kind == SiteKind::Tcp {
+            unsupported.push("TCP sites");
+        }
+        if site.basic_auth {
+            unsupported.push("basic auth");
+        }
+        if site.access.rate_limit.is_some() {
+            unsupported.push("rate limiting");
+        }
+        if !site.headers.cors_origins.is_empty() {
+            unsupported.push("CORS");
+        }
+        if site.brotli {
+            unsupported.push("brotli");
+        }
+        if site.cache_ttl.is_some() {
+            unsupported.push("proxy caching");
+        }
+        templates::add_missing_options(&mut unsupported, site);
+        unsupported
+    }
+
+    fn write_config(&self, site: &DomainConfig, config: &str) -> Result<()> {
+        log_info(&format!("Writing Caddy configuration for {}...", site.domain));
+
+        fs::create_dir_all(Paths::caddy_sites_available())
+            .map_err(|e| Error::Config(format!("Failed to create sites directory: {}", e)))?;
+        if site.kind == SiteKind::Http {
+            error_pages::write(site)?;
+        }
+        Self::share_certificate(site)?;
+
+        fs::write(&site.config_path, config)
+            .map_err(|e| Error::Config(format!("Failed to write Caddy config: {}", e)))?;
+
+        Ok(())
+    }
+
+    fn enable_site(&self, site: &DomainConfig) -> Result<()> {
+        log_info(&format!("Enabling site {}...", site.domain));
+
+        self.ensure_import()?;
+        fs::create_dir_all(Paths::caddy_sites_enabled())
+            .map_err(|e| Error::Config(format!("Failed to create sites directory: {}", e)))?;
+
+        let enabled = site.enabled_link();
+        if Path::new(&enabled).exists() {
+            log_verbose("Site already enabled");
+            return Ok(());
+        }
+
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&site.config_path, &enabled)
+            .map_err(|e| Error::Config(format!("Failed to enable site: {}", e)))?;
+
+        Ok(())
+    }
+
+    fn disable_site(&self, site: &DomainConfig) -> Result<()> {
+        log_info(&format!("Disabling site {}...", site.domain));
+
+        let enabled = site.enabled_link();
+        if !Path::new(&enabled).exists() {
+            log_verbose("Site already disabled");
+            return Ok(());
+        }
+
+        fs::remove_file(&enabled)
+            .map_err(|e| Error::Config(format!("Failed to disable site: {}", e)))?;
+
+        Ok(())
+    }
+
+    fn site_paths(&self, site: &DomainConfig) -> Vec<String> {
+        let mut paths = vec![site.config_path.clone(), site.enabled_link()];
+        if site.kind == SiteKind::Http {
+            paths.push(Paths::error_pages_dir(&site.domain));
+        }
+        paths
+    }
+
+    fn shared_paths(&self) -> Vec<String> {
+        vec![Paths::caddy_main_config().to_string()]
+    }
+
+    fn test_config(&self) -> Result<()> {
+        log_info("Validating Caddy configuration...");
+        execute_command(
+            "caddy",
+            &["validate", "--config", Paths::caddy_main_config(), "--adapter", "caddyfile"],
+            "Validate Caddy config",
+        )?;
+        Ok(())
+    }
+
+    fn reload(&self) -> Result<()> {
+        log_info("Reloading Caddy...");
+        execute_command(
+            "caddy",
+            &["reload", "--config", Paths::caddy_main_config(), "--adapter", "caddyfile"],
+            "Reload Caddy",
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{AccessPolicy, ServerKind};
+
+    fn site(domain: &str, port: u16) -> DomainConfig {
+        DomainConfig {
+            domain: domain.to_string(),
+            port,
+            server: ServerKind::Caddy,
+            ssl_cert_path: Paths::ssl_cert(ServerKind::Caddy, domain),
+            ssl_key_path: Paths::ssl_key(ServerKind::Caddy, domain),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_default_template() {
+        let mut app = site("app.test", 3000);
+        app.https_port = Some(8443);
+        app.gzip = true;
+        app.access = AccessPolicy {
+            allow: vec!["10.0.0.0/8".to_string()],
+            deny: vec!["all".to_string()],
+            ..Default::default()
+        };
+
+        let config = CaddyConfig::new().generate_config(&app, None).unwrap();
+        assert!(config.contains("https://app.test:8443 {"));
+        assert!(config.contains("redir https://app.test:8443{uri} permanent"));
+        assert!(config.contains("tls /etc/caddy/ssl/app.test.pem /etc/caddy/ssl/app.test-key.pem"));
+        assert!(config.contains("X-Frame-Options \"SAMEORIGIN\""));
+        assert!(config.contains("encode gzip"));
+        assert!(config.contains("not remote_ip 10.0.0.0/8\n\t\tremote_ip 0.0.0.0/0 ::/0"));
+        assert!(config.contains("reverse_proxy 127.0.0.1:3000"));
+        assert!(config.contains("rewrite * /502.html"));
+        assert!(!config.contains("error @maintenance"));
+
+        app.maintenance = true;
+        let config = CaddyConfig::new().generate_config(&app, None).unwrap();
+        assert!(config.contains("error @maintenance 503"));
+        assert!(config.contains("rewrite * /maintenance.html"));
+    }
+
+    #[test]
+    fn test_redirect_and_unsupported_options() {
+        let mut old = site("old.test", 0);
+        old.kind = SiteKind::Redirect;
+        old.redirect = Some(RedirectTarget {
+            to: "https://new.test".to_string(),
+            code: 308,
+            preserve_path: true,
+        });
+        let config = CaddyConfig::new().generate_config(&old, None).unwrap();
+        assert!(config.contains("redir https://new.test{uri} 308"));
+
+        let mut app = site("app.test", 3000);
+        app.basic_auth = true;
+        app.brotli = true;
+        let err = CaddyConfig::new().generate_config(&app, None).unwrap_err();
+        assert!(err.to_string().contains("basic auth, brotli"));
+    }
+}
@@ -0,0 +1,119 @@
+use std::fs;
+
+use crate::config::{DomainConfig, Paths};
+use crate::error::{Error, Result};
+use crate::utils::log_verbose;
+
+/// Page for 502/503/504 when the backend is down
+pub const BACKEND_DOWN_PAGE: &str = "502.html";
+
+/// Page served while maintenance mode is on
+pub const MAINTENANCE_PAGE: &str = "maintenance.html";
+
+/// URI prefix the pages are served under (internal only)
+pub const LOCATION: &str = "/.localstacker/";
+
+/// Write both pages for a site into its error pages directory
+pub fn write(site: &DomainConfig) -> Result<()> {
+    let dir = Paths::error_pages_dir(&site.domain);
+    log_verbose(&format!("Writing error pages to {}", dir));
+
+    fs::create_dir_all(&dir)
+        .map_err(|e| Error::Config(format!("Failed to create {}: {}", dir, e)))?;
+
+    for (name, contents) in [
+        (BACKEND_DOWN_PAGE, backend_down_page(site)),
+        (MAINTENANCE_PAGE, maintenance_page(site)),
+    ] {
+        let path = format!("{}/{}", dir, name);
+        fs::write(&path, contents)
+            .map_err(|e| Error::Config(format!("Failed to write {}: {}", path, e)))?;
+    }
+
+    Ok(())
+}
+
+pub fn backend_down_page(site: &DomainConfig) -> String {
+    let mut details = format!(
+        "<p>Nothing is answering on <code>localhost:{}</code>.</p>\n",
+        site.port
+    );
+
+    match site.service {
+        Some(ref service) => details.push_str(&format!(
+            "<p>Check the service with <code>systemctl status {}</code>.</p>\n",
+            escape(service)
+        )),
+        None => details.push_str("<p>Make sure your backend is running.</p>\n"),
+    }
+
+    details.push_str(&format!(
+        "<p>Run <code>sudo localstacker status {}</code> for details.</p>\n",
+        escape(&site.domain)
+    ));
+
+    page(&site.domain, "Backend unavailable", &details)
+}
+
+pub fn maintenance_page(site: &DomainConfig) -> String {
+    let details = format!(
+        "<p>This site is in maintenance mode.</p>\n\
+         <p>Turn it off with <code>sudo localstacker maintenance off {}</code>.</p>\n",
+        escape(&site.domain)
+    );
+
+    page(&site.domain, "Under maintenance", &details)
+}
+
+fn page(domain: &str, title: &str, details: &str) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>{title} - {domain}</title>
+<style>
+body {{ font-family: system-ui, sans-serif; max-width: 40rem; margin: 4rem auto; padding: 0 1rem; color: #222; }}
+code {{ background: #f2f2f2; padding: 0.1rem 0.3rem; border-radius: 3px; }}
+</style>
+</head>
+<body>
+<h1>{title}</h1>
+<h2>{domain}</h2>
+{details}<hr>
+<small>localstacker</small>
+</body>
+</html>
+"#,
+        title = title,
+        domain = escape(domain),
+        details = details,
+    )
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backend_down_page_names_backend() {
+        let site = DomainConfig {
+            domain: "app.test".to_string(),
+            port: 3000,
+            service: Some("my<app>".to_string()),
+            ..Default::default()
+        };
+
+        let html = backend_down_page(&site);
+        assert!(html.contains("localhost:3000"));
+        assert!(html.contains("systemctl status my&lt;app&gt;"));
+        assert!(html.contains("localstacker status app.test"));
+    }
+}
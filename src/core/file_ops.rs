@@ -19,7 +19,7 @@ impl FileOperations for FileOps {
 
         if !Path::new(path).exists() {
             fs::create_dir_all(path)
-                .map_err(|e| Error::Io(e))?;
+                .map_err(Error::Io)?;
         }
 
         Ok(())
@@ -29,7 +29,7 @@ impl FileOperations for FileOps {
         log_verbose(&format!("Copying {} -> {}", from, to));
 
         fs::copy(from, to)
-            .map_err(|e| Error::Io(e))?;
+            .map_err(Error::Io)?;
 
         Ok(())
     }
@@ -39,7 +39,18 @@ impl FileOperations for FileOps {
 
         if Path::new(path).exists() {
             fs::remove_file(path)
-                .map_err(|e| Error::Io(e))?;
+                .map_err(Error::Io)?;
+        }
+
+        Ok(())
+    }
+
+    fn remove_directory(&self, path: &str) -> Result<()> {
+        log_verbose(&format!("Removing directory: {}", path));
+
+        if Path::new(path).exists() {
+            fs::remove_dir_all(path)
+                .map_err(Error::Io)?;
         }
 
         Ok(())
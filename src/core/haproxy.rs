@@ -0,0 +1,490 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+use crate::config::{AccessPolicy, DomainConfig, Paths, RedirectTarget, ServerKind, SiteKind};
+use crate::core::{
+    disable_renamed, enable_renamed, error_pages, remove_installed_certs, render_template, templates,
+    FileOperations, FileOps, WebServerConfig,
+};
+use crate::error::{Error, Result};
+use crate::utils::{execute_command, log_info, log_verbose, parse_header};
+
+/// Marks the part of haproxy.cfg localstacker owns
+const BEGIN_MARKER: &str = "# BEGIN localstacker - managed section, do not edit";
+const END_MARKER: &str = "# END localstacker";
+
+/// MIME types compressed with `compression algo gzip`
+const COMPRESSIBLE_TYPES: &str = "text/html text/plain text/css text/xml text/javascript application/json application/javascript application/xml application/rss+xml application/wasm image/svg+xml";
+
+/// HAProxy backend: one `backend` section per site in /etc/haproxy/sites,
+/// inlined with a single SNI-routing frontend into a managed section of
+/// haproxy.cfg. Disabled sites are renamed to `*.cfg.disabled`.
+pub struct HaproxyConfig;
+
+impl HaproxyConfig {
+    pub fn new() -> Self {
+        HaproxyConfig
+    }
+
+    fn quote(value: &str) -> String {
+        format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+
+    fn backend_name(domain: &str) -> String {
+        format!("localstacker_{}", domain)
+    }
+
+    fn header_rules(site: &DomainConfig) -> String {
+        let mut headers: Vec<(String, String)> = site
+            .headers
+            .profile
+            .headers()
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        headers.extend(site.headers.custom.iter().filter_map(|h| parse_header(h).ok()));
+
+        headers
+            .into_iter()
+            .map(|(name, value)| format!("\n    http-response set-header {} {}", name, Self::quote(&value)))
+            .collect()
+    }
+
+    /// Deny the clients the `AccessPolicy` denies with a 403
+    fn access_rule(site: &DomainConfig) -> String {
+        let access = &site.access;
+        if access.deny.is_empty() {
+            return String::new();
+        }
+
+        let allowed = if access.allow.is_empty() {
+            String::new()
+        } else {
+            format!("!{{ src {} }} ", AccessPolicy::ranges(&access.allow))
+        };
+        format!(
+            "\n    http-request deny deny_status 403 if {}{{ src {} }}",
+            allowed,
+            AccessPolicy::ranges(&access.deny)
+        )
+    }
+
+    /// Serve the static error pages for backend errors; HAProxy answers 503
+    /// itself once the health check marks the backend down
+    fn error_rules(site: &DomainConfig) -> String {
+        [502, 503, 504]
+            .iter()
+            .map(|status| {
+                format!(
+                    "\n    http-error status {} content-type \"text/html; charset=utf-8\" file {}/{}",
+                    status,
+                    Paths::error_pages_dir(&site.domain),
+                    error_pages::BACKEND_DOWN_PAGE
+                )
+            })
+            .collect()
+    }
+
+    fn default_template(site: &DomainConfig) -> String {
+        let maintenance = if site.maintenance {
+            format!(
+                "\n    # Maintenance mode (localstacker maintenance off to disable)\n    http-request return status 503 content-type \"text/html; charset=utf-8\" file {}/{} unless {{ path_beg /.well-known/ }}",
+                Paths::error_pages_dir(&site.domain),
+                error_pages::MAINTENANCE_PAGE
+            )
+        } else {
+            String::new()
+        };
+        let compression = if site.gzip {
+            format!("\n    compression algo gzip\n    compression type {}", COMPRESSIBLE_TYPES)
+        } else {
+            String::new()
+        };
+
+        format!(
+            r#"# Generated by localstacker for {domain}
+
+backend {backend}
+    mode http
+    http-request redirect prefix {url} code 301 unless {{ ssl_fc }}{access}{maintenance}
+    http-request set-header X-Forwarded-Proto https{headers}{compression}{errors}
+    server local 127.0.0.1:{port} check
+"#,
+            domain = site.domain,
+            backend = Self::backend_name(&site.domain),
+            url = site.url(),
+            access = Self::access_rule(site),
+            maintenance = maintenance,
+            headers = Self::header_rules(site),
+            compression = compression,
+            errors = Self::error_rules(site),
+            port = site.port,
+        )
+    }
+
+    fn redirect_template(site: &DomainConfig, target: &RedirectTarget) -> String {
+        // `prefix` keeps the request path, `location` drops it
+        let mode = if target.preserve_path { "prefix" } else { "location" };
+
+        format!(
+            r#"# Generated by localstacker for {domain} (redirect)
+
+backend {backend}
+    mode http
+    http-request redirect {mode} {to} code {code}
+"#,
+            domain = site.domain,
+            backend = Self::backend_name(&site.domain),
+            mode = mode,
+            to = target.to,
+            code = target.code,
+        )
+    }
+
+    /// One frontend for every site: TLS is terminated with the crt-list and
+    /// requests are routed by SNI, plain HTTP by Host
+    fn frontend(sites: &[&DomainConfig]) -> String {
+        let http: BTreeSet<u16> = sites.iter().map(|site| site.effective_http_port()).collect();
+        let https: BTreeSet<u16> = sites.iter().map(|site| site.effective_https_port()).collect();
+
+        let mut out = String::from("frontend localstacker\n    mode http");
+        for port in http {
+            out.push_str(&format!("\n    bind :{}", port));
+        }
+        for port in https {
+            out.push_str(&format!(
+                "\n    bind :{} ssl crt-list {} alpn h2,http/1.1",
+                port,
+                Paths::haproxy_crt_list()
+            ));
+        }
+        out.push_str("\n    option forwardfor");
+
+        for site in sites {
+            let backend = Self::backend_name(&site.domain);
+            out.push_str(&format!(
+                "\n    use_backend {} if {{ ssl_fc_sni -i {} }} {{ dst_port {} }}",
+                backend,
+                site.domain,
+                site.effective_https_port()
+            ));
+            out.push_str(&format!(
+                "\n    use_backend {} if !{{ ssl_fc }} {{ hdr(host),field(1,:) -i {} }} {{ dst_port {} }}",
+                backend,
+                site.domain,
+                site.effective_http_port()
+            ));
+        }
+        out.push('\n');
+        out
+    }
+
+    fn crt_list(sites: &[&DomainConfig]) -> String {
+        sites
+            .iter()
+            .map(|site| format!("{} {}\n", Paths::haproxy_bundle(&site.domain), site.domain))
+            .collect()
+    }
+
+    /// The frontend followed by each site's backend section
+    fn managed_section(sites: &[&DomainConfig], backends: &[String]) -> String {
+        let mut out = format!("{}\n{}", BEGIN_MARKER, Self::frontend(sites));
+        for backend in backends {
+            out.push('\n');
+            out.push_str(backend.trim_end());
+            out.push('\n');
+        }
+        out.push_str(END_MARKER);
+        out.push('\n');
+        out
+    }
+
+    /// Replace the managed section of haproxy.cfg, appending it the first
+    /// time; `None` removes it
+    fn splice(main_config: &str, section: Option<&str>) -> String {
+        let mut out = String::new();
+        let mut in_section = false;
+        for line in main_config.lines() {
+            match line.trim() {
+                BEGIN_MARKER => in_section = true,
+                END_MARKER if in_section => in_section = false,
+                _ if !in_section => {
+                    out.push_str(line);
+                    out.push('\n');
+                }
+                _ => {}
+            }
+        }
+
+        let mut out = out.trim_end().to_string();
+        if let Some(section) = section {
+            if !out.is_empty() {
+                out.push_str("\n\n");
+            }
+            out.push_str(section);
+        } else if !out.is_empty() {
+            out.push('\n');
+        }
+        out
+    }
+
+    /// HAProxy wants the certificate and its key in one file
+    fn write_bundle(site: &DomainConfig) -> Result<()> {
+        let read = |path: &str| {
+            fs::read_to_string(path)
+                .map_err(|e| Error::Config(format!("Failed to read {}: {}", path, e)))
+        };
+        let mut bundle = read(&site.ssl_cert_path)?;
+        if !bundle.ends_with('\n') {
+            bundle.push('\n');
+        }
+        bundle.push_str(&read(&site.ssl_key_path)?);
+
+        let path = Paths::haproxy_bundle(&site.domain);
+        log_verbose(&format!("Writing certificate bundle {}", path));
+        fs::write(&path, bundle)
+            .and_then(|_| fs::set_permissions(&path, fs::Permissions::from_mode(0o600)))
+            .map_err(|e| Error::Config(format!("Failed to write {}: {}", path, e)))?;
+
+        Ok(())
+    }
+}
+
+impl WebServerConfig for HaproxyConfig {
+    fn generate_config(&self, site: &DomainConfig, template: Option<&str>) -> Result<String> {
+        log_verbose(&format!("Generating HAProxy config for {}", site.domain));
+
+        let unsupported = self.unsupported_features(site);
+        if !unsupported.is_empty() {
+            return Err(Error::Validation(format!(
+                "HAProxy sites don't support {}; use --server nginx",
+                unsupported.join(", ")
+            )));
+        }
+
+        if let Some(template_path) = template {
+            return render_template(template_path, site);
+        }
+
+        match site.kind {
+            SiteKind::Redirect => {
+                let target = site.redirect.as_ref().ok_or_else(|| {
+                    Error::Config(format!("Redirect site {} has no target", site.domain))
+                })?;
+                Ok(Self::redirect_template(site, target))
+            }
+            _ => Ok(Self::default_template(site)),
+        }
+    }
+
+    /// Options the generated sections have no equivalent for
+    fn unsupported_features(&self, site: &DomainConfig) -> Vec<&'static str> {
+        let mut unsupported = Vec::new();
+        if site.kind == SiteKind::Tcp {
+            unsupported.push("TCP sites");
+        }
+        if site.http3 {
+            unsupported.push("HTTP/3");
+        }
+        // HAProxy userlists can't check apr1 hashes
+        if site.basic_auth {
+            unsupported.push("basic auth");
+        }
+        if site.access.rate_limit.is_some() {
+            unsupported.push("rate limiting");
+        }
+        if !site.headers.cors_origins.is_empty() {
+            unsupported.push("CORS");
+        }
+        if site.brotli {
+            unsupported.push("brotli");
+        }
+        if site.cache_ttl.is_some() {
+            unsupported.push("proxy caching");
+        }
+        templates::add_missing_options(&mut unsupported, site);
+        unsupported
+    }
+
+    /// Rebuild the crt-list and the managed section of haproxy.cfg from the
+    /// enabled sites' backend sections
+    fn write_shared_config(&self, sites: &[&DomainConfig]) -> Result<()> {
+        let sites: Vec<&DomainConfig> = sites
+            .iter()
+            .copied()
+            .filter(|site| site.server == ServerKind::Haproxy && site.kind != SiteKind::Tcp)
+            .filter(|site| Path::new(&site.config_path).exists())
+            .collect();
+
+        let mut backends = Vec::new();
+        for site in &sites {
+            backends.push(fs::read_to_string(&site.config_path).map_err(|e| {
+                Error::Config(format!("Failed to read {}: {}", site.config_path, e))
+            })?);
+        }
+
+        let crt_list = Paths::haproxy_crt_list();
+        log_verbose(&format!("Writing {}", crt_list));
+        fs::write(crt_list, Self::crt_list(&sites))
+            .map_err(|e| Error::Config(format!("Failed to write {}: {}", crt_list, e)))?;
+
+        let main_config = Paths::haproxy_main_config();
+        log_verbose(&format!("Updating managed section of {}", main_config));
+        let section = (!sites.is_empty()).then(|| Self::managed_section(&sites, &backends));
+        let current = fs::read_to_string(main_config).unwrap_or_default();
+        fs::write(main_config, Self::splice(&current, section.as_deref()))
+            .map_err(|e| Error::Config(format!("Failed to update {}: {}", main_config, e)))?;
+
+        Ok(())
+    }
+
+    fn write_config(&self, site: &DomainConfig, config: &str) -> Result<()> {
+        log_info(&format!("Writing HAProxy configuration for {}...", site.domain));
+
+        fs::create_dir_all(Paths::haproxy_sites_dir())
+            .map_err(|e| Error::Config(format!("Failed to create sites directory: {}", e)))?;
+        if site.kind == SiteKind::Http {
+            error_pages::write(site)?;
+        }
+        Self::write_bundle(site)?;
+
+        fs::write(&site.config_path, config)
+            .map_err(|e| Error::Config(format!("Failed to write HAProxy config: {}", e)))?;
+
+        Ok(())
+    }
+
+    fn enable_site(&self, site: &DomainConfig) -> Result<()> {
+        log_info(&format!("Enabling site {}...", site.domain));
+        enable_renamed(&site.config_path)
+    }
+
+    fn disable_site(&self, site: &DomainConfig) -> Result<()> {
+        log_info(&format!("Disabling site {}...", site.domain));
+        disable_renamed(&site.config_path)
+    }
+
+    fn site_paths(&self, site: &DomainConfig) -> Vec<String> {
+        let mut paths = vec![
+            site.config_path.clone(),
+            Paths::disabled_config(&site.config_path),
+            Paths::haproxy_bundle(&site.domain),
+        ];
+        if site.kind == SiteKind::Http {
+            paths.push(Paths::error_pages_dir(&site.domain));
+        }
+        paths
+    }
+
+    /// The bundle holds a copy of the key, it goes with the site
+    fn remove_certs(&self, site: &DomainConfig, installed: bool) -> Result<()> {
+        FileOps::new().remove_file(&Paths::haproxy_bundle(&site.domain))?;
+        if installed {
+            remove_installed_certs(site)?;
+        }
+        Ok(())
+    }
+
+    fn shared_paths(&self) -> Vec<String> {
+        vec![
+            Paths::haproxy_main_config().to_string(),
+            Paths::haproxy_crt_list().to_string(),
+        ]
+    }
+
+    fn test_config(&self) -> Result<()> {
+        log_info("Checking HAProxy configuration...");
+        execute_command(
+            "haproxy",
+            &["-c", "-q", "-f", Paths::haproxy_main_config()],
+            "Check HAProxy config",
+        )?;
+        Ok(())
+    }
+
+    /// `reload-or-restart` also starts a stopped HAProxy
+    fn reload(&self) -> Result<()> {
+        log_info("Reloading HAProxy...");
+        if Path::new("/run/systemd/system").is_dir() {
+            execute_command("systemctl", &["reload-or-restart", "haproxy"], "Reload HAProxy")?;
+        } else {
+            execute_command("service", &["haproxy", "reload"], "Reload HAProxy")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn site(domain: &str, port: u16) -> DomainConfig {
+        DomainConfig {
+            domain: domain.to_string(),
+            port,
+            server: ServerKind::Haproxy,
+            ssl_cert_path: Paths::ssl_cert(ServerKind::Haproxy, domain),
+            ssl_key_path: Paths::ssl_key(ServerKind::Haproxy, domain),
+            config_path: Paths::site_config(ServerKind::Haproxy, SiteKind::Http, domain),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_default_template() {
+        let mut app = site("app.test", 3000);
+        app.https_port = Some(8443);
+        app.gzip = true;
+        app.access = AccessPolicy {
+            allow: vec!["10.0.0.0/8".to_string()],
+            deny: vec!["all".to_string()],
+            ..Default::default()
+        };
+
+        let config = HaproxyConfig::new().generate_config(&app, None).unwrap();
+        assert!(config.contains("backend localstacker_app.test\n"));
+        assert!(config.contains("redirect prefix https://app.test:8443 code 301 unless { ssl_fc }"));
+        assert!(config.contains("deny deny_status 403 if !{ src 10.0.0.0/8 } { src 0.0.0.0/0 ::/0 }"));
+        assert!(config.contains("http-response set-header X-Frame-Options \"SAMEORIGIN\""));
+        assert!(config.contains("compression algo gzip"));
+        assert!(config.contains("http-error status 504 content-type \"text/html; charset=utf-8\" file /var/www/localstacker/app.test/502.html"));
+        assert!(config.contains("server local 127.0.0.1:3000 check"));
+        assert!(!config.contains("maintenance.html"));
+
+        app.maintenance = true;
+        let config = HaproxyConfig::new().generate_config(&app, None).unwrap();
+        assert!(config.contains("return status 503 content-type \"text/html; charset=utf-8\" file /var/www/localstacker/app.test/maintenance.html unless { path_beg /.well-known/ }"));
+
+        app.basic_auth = true;
+        let err = HaproxyConfig::new().generate_config(&app, None).unwrap_err();
+        assert!(err.to_string().contains("basic auth"));
+    }
+
+    #[test]
+    fn test_managed_section_routes_by_sni() {
+        let a = site("a.test", 3000);
+        let mut b = site("b.test", 3001);
+        b.https_port = Some(8443);
+        let sites = [&a, &b];
+
+        let section = HaproxyConfig::managed_section(&sites, &["backend localstacker_a.test\n".to_string()]);
+        assert!(section.contains("    bind :80\n"));
+        assert!(section.contains("bind :443 ssl crt-list /etc/haproxy/localstacker.crt-list alpn h2,http/1.1"));
+        assert!(section.contains("bind :8443 ssl crt-list"));
+        assert!(section.contains("use_backend localstacker_b.test if { ssl_fc_sni -i b.test } { dst_port 8443 }"));
+        assert!(section.contains("use_backend localstacker_a.test if !{ ssl_fc } { hdr(host),field(1,:) -i a.test } { dst_port 80 }"));
+        assert_eq!(
+            HaproxyConfig::crt_list(&sites),
+            "/etc/haproxy/ssl/a.test.bundle.pem a.test\n/etc/haproxy/ssl/b.test.bundle.pem b.test\n"
+        );
+
+        // The section is replaced in place and the rest of haproxy.cfg kept
+        let main = "global\n    daemon\n";
+        let once = HaproxyConfig::splice(main, Some(&section));
+        assert!(once.starts_with("global\n    daemon\n\n# BEGIN localstacker"));
+        assert_eq!(HaproxyConfig::splice(&once, Some(&section)), once);
+        assert_eq!(HaproxyConfig::splice(&once, None), main);
+    }
+}
@@ -0,0 +1,252 @@
+use md5::{Digest, Md5};
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+use crate::config::ServerKind;
+use crate::error::Result;
+use crate::utils::{log_verbose, log_warning, validate_username};
+
+const APR1_MAGIC: &str = "$apr1$";
+const ITOA64: &[u8] = b"./0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+/// An htpasswd file with apr1 (Apache MD5) password hashes, which nginx
+/// verifies itself regardless of the system crypt() implementation
+pub struct HtpasswdFile {
+    path: String,
+    entries: Vec<(String, String)>,
+}
+
+impl HtpasswdFile {
+    /// Load an htpasswd file, or start an empty one if it doesn't exist
+    pub fn load(path: &str) -> Result<Self> {
+        let mut entries = Vec::new();
+
+        if Path::new(path).exists() {
+            let contents = fs::read_to_string(path)?;
+            for line in contents.lines() {
+                let line = line.trim();
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                if let Some((user, hash)) = line.split_once(':') {
+                    entries.push((user.to_string(), hash.to_string()));
+                }
+            }
+        }
+
+        Ok(HtpasswdFile {
+            path: path.to_string(),
+            entries,
+        })
+    }
+
+    /// Write the file readable by `server`'s workers but not by other users
+    pub fn save(&self, server: ServerKind) -> Result<()> {
+        log_verbose(&format!("Writing {}", self.path));
+
+        if let Some(parent) = Path::new(&self.path).parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let contents: String = self
+            .entries
+            .iter()
+            .map(|(user, hash)| format!("{}:{}\n", user, hash))
+            .collect();
+        fs::write(&self.path, contents)?;
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            let group = server.worker_groups().iter().find_map(|name| {
+                nix::unistd::Group::from_name(name).ok().flatten()
+            });
+            let mode = match group {
+                Some(group) => {
+                    std::os::unix::fs::chown(&self.path, None, Some(group.gid.as_raw()))?;
+                    0o640
+                }
+                // The built-in proxy reads it as root
+                None if server.worker_groups().is_empty() => 0o640,
+                None => {
+                    log_warning(&format!(
+                        "No {} group found, {} stays world-readable so its workers can check passwords",
+                        server.display_name(),
+                        self.path
+                    ));
+                    0o644
+                }
+            };
+            fs::set_permissions(&self.path, fs::Permissions::from_mode(mode))?;
+        }
+
+        Ok(())
+    }
+
+    pub fn users(&self) -> Vec<&str> {
+        self.entries.iter().map(|(user, _)| user.as_str()).collect()
+    }
+
+    /// Add a user or replace their password. Returns true if the user existed.
+    pub fn set_user(&mut self, user: &str, password: &str) -> Result<bool> {
+        validate_username(user)?;
+
+        let hash = apr1_hash(password, &random_salt()?);
+        if let Some(entry) = self.entries.iter_mut().find(|(u, _)| u == user) {
+            entry.1 = hash;
+            Ok(true)
+        } else {
+            self.entries.push((user.to_string(), hash));
+            Ok(false)
+        }
+    }
+
+    /// Check a password against the user's apr1 hash
+    pub fn verify(&self, user: &str, password: &str) -> bool {
+        self.entries
+            .iter()
+            .find(|(u, _)| u == user)
+            .and_then(|(_, hash)| {
+                let salt = hash.strip_prefix(APR1_MAGIC)?.split('$').next()?;
+                Some(apr1_hash(password, salt) == *hash)
+            })
+            .unwrap_or(false)
+    }
+
+    /// Remove a user. Returns false if the user wasn't present.
+    pub fn remove_user(&mut self, user: &str) -> bool {
+        let before = self.entries.len();
+        self.entries.retain(|(u, _)| u != user);
+        self.entries.len() != before
+    }
+}
+
+fn random_salt() -> Result<String> {
+    let mut bytes = [0u8; 8];
+    fs::File::open("/dev/urandom")?.read_exact(&mut bytes)?;
+    Ok(bytes
+        .iter()
+        .map(|b| ITOA64[(*b & 0x3f) as usize] as char)
+        .collect())
+}
+
+/// Apache's MD5-crypt variant, as produced by `htpasswd -m` / `openssl passwd -apr1`
+pub fn apr1_hash(password: &str, salt: &str) -> String {
+    let password = password.as_bytes();
+    let salt = &salt.as_bytes()[..salt.len().min(8)];
+
+    let mut alternate = Md5::new();
+    alternate.update(password);
+    alternate.update(salt);
+    alternate.update(password);
+    let alternate = alternate.finalize();
+
+    let mut ctx = Md5::new();
+    ctx.update(password);
+    ctx.update(APR1_MAGIC.as_bytes());
+    ctx.update(salt);
+
+    let mut remaining = password.len();
+    while remaining > 0 {
+        let n = remaining.min(16);
+        ctx.update(&alternate[..n]);
+        remaining -= n;
+    }
+
+    let mut i = password.len();
+    while i > 0 {
+        if i & 1 == 1 {
+            ctx.update([0u8]);
+        } else {
+            ctx.update(&password[..1]);
+        }
+        i >>= 1;
+    }
+
+    let mut digest = ctx.finalize();
+
+    for round in 0..1000 {
+        let mut ctx = Md5::new();
+        if round & 1 == 1 {
+            ctx.update(password);
+        } else {
+            ctx.update(digest);
+        }
+        if round % 3 != 0 {
+            ctx.update(salt);
+        }
+        if round % 7 != 0 {
+            ctx.update(password);
+        }
+        if round & 1 == 1 {
+            ctx.update(digest);
+        } else {
+            ctx.update(password);
+        }
+        digest = ctx.finalize();
+    }
+
+    let mut encoded = String::new();
+    let mut to64 = |mut value: u32, count: usize| {
+        for _ in 0..count {
+            encoded.push(ITOA64[(value & 0x3f) as usize] as char);
+            value >>= 6;
+        }
+    };
+
+    for (a, b, c) in [(0, 6, 12), (1, 7, 13), (2, 8, 14), (3, 9, 15), (4, 10, 5)] {
+        to64(
+            ((digest[a] as u32) << 16) | ((digest[b] as u32) << 8) | digest[c] as u32,
+            4,
+        );
+    }
+    to64(digest[11] as u32, 2);
+
+    format!("{}{}${}", APR1_MAGIC, String::from_utf8_lossy(salt), encoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apr1_matches_openssl() {
+        assert_eq!(
+            apr1_hash("password", "abcdefgh"),
+            "$apr1$abcdefgh$FBwExRW4dCc8aL.OvjpIE1"
+        );
+        assert_eq!(
+            apr1_hash("a longer password than sixteen chars", "xy"),
+            "$apr1$xy$.y0Xs8C.8jzszG1FZeZbm."
+        );
+    }
+
+    #[test]
+    fn test_verify() {
+        let htpasswd = HtpasswdFile {
+            path: String::new(),
+            entries: vec![("alice".to_string(), apr1_hash("secret", "abcdefgh"))],
+        };
+        assert!(htpasswd.verify("alice", "secret"));
+        assert!(!htpasswd.verify("alice", "wrong"));
+        assert!(!htpasswd.verify("bob", "secret"));
+    }
+
+    #[test]
+    fn test_save_is_not_world_readable() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = std::env::temp_dir().join(format!("localstacker-htpasswd-{}", std::process::id()));
+        let mut htpasswd = HtpasswdFile::load(path.to_str().unwrap()).unwrap();
+        htpasswd.set_user("alice", "secret").unwrap();
+        htpasswd.save(ServerKind::Builtin).unwrap();
+
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o640);
+        assert!(HtpasswdFile::load(path.to_str().unwrap()).unwrap().verify("alice", "secret"));
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,9 +1,11 @@
 // Core traits following SOLID principles
 
-use crate::error::Result;
+use crate::config::{DomainConfig, Paths, ServerKind, SiteKind};
+use crate::error::{Error, Result};
+use crate::utils::{log_verbose, log_warning};
 
-/// Single Responsibility: Each service handles one specific aspect
-/// Interface Segregation: Small, focused interfaces
+// Single Responsibility: Each service handles one specific aspect
+// Interface Segregation: Small, focused interfaces
 
 /// Certificate provider interface
 pub trait CertificateProvider {
@@ -16,12 +18,182 @@ pub trait CertificateProvider {
 
 /// Web server configuration interface
 pub trait WebServerConfig {
-    fn generate_config(&self, domain: &str, port: u16, template: Option<&str>) -> Result<String>;
-    fn write_config(&self, domain: &str, config: &str) -> Result<()>;
-    fn enable_site(&self, domain: &str) -> Result<()>;
-    fn disable_site(&self, domain: &str) -> Result<()>;
+    fn generate_config(&self, site: &DomainConfig, template: Option<&str>) -> Result<String>;
+    fn write_config(&self, site: &DomainConfig, config: &str) -> Result<()>;
+    fn enable_site(&self, site: &DomainConfig) -> Result<()>;
+    fn disable_site(&self, site: &DomainConfig) -> Result<()>;
     fn test_config(&self) -> Result<()>;
     fn reload(&self) -> Result<()>;
+
+    /// Files, links and directories written for a site, backed up before changes
+    fn site_paths(&self, site: &DomainConfig) -> Vec<String>;
+
+    /// Site options this server can't express; setup refuses them
+    fn unsupported_features(&self, _site: &DomainConfig) -> Vec<&'static str> {
+        Vec::new()
+    }
+
+    /// Files shared by all sites that a change may rewrite
+    fn shared_paths(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Rewrite configuration shared by all managed sites. Called with the
+    /// full set of sites whenever one is added, changed or removed.
+    fn write_shared_config(&self, _sites: &[&DomainConfig]) -> Result<()> {
+        Ok(())
+    }
+
+    /// Clean up after a removed site: copies of its certificate the server
+    /// keeps, plus the installed certificate and key when `installed` is set
+    fn remove_certs(&self, site: &DomainConfig, installed: bool) -> Result<()> {
+        if installed {
+            remove_installed_certs(site)?;
+        }
+        Ok(())
+    }
+}
+
+/// Backend for the web server a site is configured in
+pub fn web_server_for(server: ServerKind) -> Box<dyn WebServerConfig> {
+    match server {
+        ServerKind::Nginx => Box::new(NginxConfig::new()),
+        ServerKind::Caddy => Box::new(CaddyConfig::new()),
+        ServerKind::Apache => Box::new(ApacheConfig::new()),
+        ServerKind::Haproxy => Box::new(HaproxyConfig::new()),
+        ServerKind::Traefik => Box::new(TraefikConfig::new()),
+        ServerKind::Builtin => Box::new(BuiltinProxyConfig::new()),
+    }
+}
+
+/// Delete a site's certificate and key, but only where localstacker
+/// installs them; certificates referenced from elsewhere aren't ours
+pub(crate) fn remove_installed_certs(site: &DomainConfig) -> Result<()> {
+    let file_ops = FileOps::new();
+    let ssl_dir = Paths::ssl_dir(site.server);
+    for path in [&site.ssl_cert_path, &site.ssl_key_path] {
+        if !std::path::Path::new(path).starts_with(&ssl_dir) {
+            log_warning(&format!("Keeping {}, it is outside {}", path, ssl_dir));
+        } else if file_ops.file_exists(path) {
+            file_ops.remove_file(path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Enable a site whose config file is the enabled site (conf.d layouts) by
+/// dropping the `.disabled` suffix `disable_renamed` added
+pub(crate) fn enable_renamed(config_path: &str) -> Result<()> {
+    let disabled = Paths::disabled_config(config_path);
+    if !std::path::Path::new(&disabled).exists() {
+        log_verbose("Site already enabled");
+    } else if std::path::Path::new(config_path).exists() {
+        // A freshly written config supersedes the disabled copy
+        std::fs::remove_file(&disabled)
+            .map_err(|e| Error::Config(format!("Failed to enable site: {}", e)))?;
+    } else {
+        std::fs::rename(&disabled, config_path)
+            .map_err(|e| Error::Config(format!("Failed to enable site: {}", e)))?;
+    }
+    Ok(())
+}
+
+/// Disable such a site by renaming it out of the server's include pattern
+pub(crate) fn disable_renamed(config_path: &str) -> Result<()> {
+    if !std::path::Path::new(config_path).exists() {
+        log_verbose("Site already disabled");
+        return Ok(());
+    }
+    std::fs::rename(config_path, Paths::disabled_config(config_path))
+        .map_err(|e| Error::Config(format!("Failed to disable site: {}", e)))
+}
+
+/// Fill a `--template` (registry name or file path) for a site
+pub(crate) fn render_template(template: &str, site: &DomainConfig) -> Result<String> {
+    let template = templates::resolve(template)?;
+    Ok(render_content(&template.content, site))
+}
+
+/// Replace a template's `{{placeholders}}`: `domain`, `port`,
+/// `listen_port`, `http_port`, `https_port`, `https_port_suffix` (empty on
+/// 443), `ssl_cert`, `ssl_key`, `log_dir`, `ssl_snippet` (the stream one
+/// for TCP sites) and `proxy_snippet`
+pub(crate) fn render_content(content: &str, site: &DomainConfig) -> String {
+    let https_port = site.effective_https_port();
+    let https_port_suffix = match https_port {
+        443 => String::new(),
+        other => format!(":{}", other),
+    };
+
+    let ssl_snippet = match site.kind {
+        SiteKind::Tcp => Paths::nginx_stream_ssl_snippet(),
+        SiteKind::Http | SiteKind::Redirect => Paths::nginx_ssl_snippet(),
+    };
+
+    let mut config = content
+        .replace("{{domain}}", &site.domain)
+        .replace("{{port}}", &site.port.to_string())
+        .replace("{{http_port}}", &site.effective_http_port().to_string())
+        .replace("{{https_port}}", &https_port.to_string())
+        .replace("{{https_port_suffix}}", &https_port_suffix)
+        .replace("{{ssl_cert}}", &site.ssl_cert_path)
+        .replace("{{ssl_key}}", &site.ssl_key_path)
+        .replace("{{log_dir}}", Paths::nginx_log_dir())
+        .replace("{{ssl_snippet}}", &ssl_snippet)
+        .replace("{{proxy_snippet}}", &Paths::nginx_proxy_snippet());
+
+    if let Some(listen_port) = site.listen_port {
+        config = config.replace("{{listen_port}}", &listen_port.to_string());
+    }
+
+    config
+}
+
+/// Replace placeholders that stand on a line of their own with a block of
+/// directives, indented like the placeholder. Empty blocks drop the line,
+/// consecutive ones are separated by a blank line.
+pub(crate) fn fill_blocks(content: &str, blocks: &[(&str, String)]) -> String {
+    let mut out = String::new();
+    let mut after_block = false;
+    for line in content.lines() {
+        let block = blocks
+            .iter()
+            .find(|(name, _)| line.trim() == format!("{{{{{}}}}}", name))
+            .map(|(_, block)| block);
+        let Some(block) = block else {
+            out.push_str(line);
+            out.push('\n');
+            after_block = false;
+            continue;
+        };
+
+        let indent = &line[..line.len() - line.trim_start().len()];
+        let lines: Vec<&str> = block.lines().skip_while(|l| l.trim().is_empty()).collect();
+        if lines.is_empty() {
+            continue;
+        }
+        if after_block {
+            out.push('\n');
+        }
+        after_block = true;
+        let base = lines
+            .iter()
+            .filter(|l| !l.trim().is_empty())
+            .map(|l| l.len() - l.trim_start().len())
+            .min()
+            .unwrap_or(0);
+        for l in lines {
+            if !l.trim().is_empty() {
+                out.push_str(indent);
+                out.push_str(&l[base..]);
+            }
+            out.push('\n');
+        }
+    }
+    if !content.ends_with('\n') {
+        out.pop();
+    }
+    out
 }
 
 /// System service interface
@@ -39,16 +211,45 @@ pub trait FileOperations {
     fn ensure_directory(&self, path: &str) -> Result<()>;
     fn copy_file(&self, from: &str, to: &str) -> Result<()>;
     fn remove_file(&self, path: &str) -> Result<()>;
+    fn remove_directory(&self, path: &str) -> Result<()>;
     fn file_exists(&self, path: &str) -> bool;
 }
 
 // Implementations
+mod apache;
+mod builtin_proxy;
+mod caddy;
+mod haproxy;
 mod mkcert;
 mod nginx;
+mod nginx_capabilities;
+mod nginx_layout;
+mod nginx_service;
+mod proxy;
 mod systemd;
+mod traefik;
 mod file_ops;
+mod htpasswd;
+mod transaction;
+pub mod access_log;
+pub mod error_pages;
+pub mod nginx_lint;
+pub mod nginx_parser;
+pub mod nginx_conflicts;
+pub mod templates;
 
+pub use apache::{ApacheConfig, ApacheLayout};
+pub use builtin_proxy::{proxy_pid, BuiltinProxyConfig};
+pub use caddy::CaddyConfig;
+pub use haproxy::HaproxyConfig;
 pub use mkcert::MkcertProvider;
 pub use nginx::NginxConfig;
+pub use nginx_capabilities::NginxCapabilities;
+pub use nginx_layout::{LayoutMode, NginxLayout, NginxPathSettings};
+pub use nginx_service::{running_pid, ReloadStrategy};
+pub use proxy::run as run_proxy;
 pub use systemd::SystemdService;
-pub use file_ops::FileOps;
\ No newline at end of file
+pub use traefik::{TraefikConfig, TraefikSettings};
+pub use file_ops::FileOps;
+pub use htpasswd::HtpasswdFile;
+pub use transaction::Transaction;
\ No newline at end of file
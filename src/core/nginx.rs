@@ -1,141 +1,792 @@
 use std::fs;
 use std::path::Path;
 
-use crate::config::Paths;
-use crate::core::WebServerConfig;
+use crate::config::{DomainConfig, Paths, RedirectTarget, ServerKind, SiteKind};
+use crate::core::nginx_lint::{self, LintContext, Severity};
+use crate::core::{
+    disable_renamed, enable_renamed, error_pages, fill_blocks, render_content, templates,
+    NginxCapabilities, ReloadStrategy, WebServerConfig,
+};
 use crate::error::{Error, Result};
-use crate::utils::{execute_command, log_info, log_verbose};
+use crate::utils::{execute_command, log_info, log_verbose, log_warning, parse_header};
 
-pub struct NginxConfig;
+/// MIME types worth compressing (text/html is always included by nginx)
+const COMPRESSIBLE_TYPES: &str = "text/plain text/css text/xml text/javascript application/json application/javascript application/xml application/rss+xml application/wasm image/svg+xml";
+
+pub struct NginxConfig {
+    capabilities: NginxCapabilities,
+}
 
 impl NginxConfig {
     pub fn new() -> Self {
-        NginxConfig
+        NginxConfig {
+            capabilities: NginxCapabilities::detect(),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn with_capabilities(capabilities: NginxCapabilities) -> Self {
+        NginxConfig { capabilities }
+    }
+
+    pub fn capabilities(&self) -> &NginxCapabilities {
+        &self.capabilities
+    }
+
+    /// `listen` lines for a port, adding the IPv6 listener when available
+    fn listen_lines(&self, port: u16, params: &str) -> String {
+        let params = if params.is_empty() {
+            String::new()
+        } else {
+            format!(" {}", params)
+        };
+
+        let mut lines = format!("    listen {}{};", port, params);
+        if self.capabilities.ipv6 {
+            lines.push_str(&format!("\n    listen [::]:{}{};", port, params));
+        }
+        lines
+    }
+
+    /// HTTPS `listen` lines plus `http2 on;` on nginx >= 1.25.1
+    fn https_listen_lines(&self, port: u16) -> String {
+        let caps = &self.capabilities;
+
+        if !caps.supports_http2() {
+            self.listen_lines(port, "ssl")
+        } else if caps.uses_http2_directive() {
+            format!("{}\n    http2 on;", self.listen_lines(port, "ssl"))
+        } else {
+            self.listen_lines(port, "ssl http2")
+        }
+    }
+
+    /// QUIC listener lines; only one server block per address may use `reuseport`
+    fn quic_listen_lines(&self, port: u16, reuseport: bool) -> String {
+        let params = if reuseport { "quic reuseport" } else { "quic" };
+        format!("\n{}", self.listen_lines(port, params))
+    }
+
+    /// Name of a per-domain shared memory zone. nginx takes any name up to
+    /// the `:size`, so the domain is used as is; mapping `.` and `-` to the
+    /// same character would give `a-b.test` and `a.b.test` one zone.
+    fn zone_name(prefix: &str, domain: &str) -> String {
+        format!("{}_{}", prefix, domain)
+    }
+
+    /// Security profile and custom headers, all server-level so every
+    /// location inherits them
+    fn header_directives(site: &DomainConfig) -> String {
+        let profile = site.headers.profile.headers();
+
+        let custom: Vec<(String, String)> = site
+            .headers
+            .custom
+            .iter()
+            .filter_map(|h| parse_header(h).ok())
+            .collect();
+
+        if profile.is_empty() && custom.is_empty() {
+            return String::new();
+        }
+
+        let mut out = String::from("\n\n    # Security Headers");
+        for (name, value) in profile {
+            out.push_str(&format!("\n    add_header {} \"{}\" always;", name, value));
+        }
+        if !custom.is_empty() {
+            out.push_str("\n\n    # Custom Headers");
+            for (name, value) in custom {
+                out.push_str(&format!(
+                    "\n    add_header {} \"{}\" always;",
+                    name,
+                    value.replace('\\', "\\\\").replace('"', "\\\"")
+                ));
+            }
+        }
+        out
+    }
+
+    /// CORS response headers. `$ls_cors_origin` stays empty for origins
+    /// that aren't allowed, and nginx skips headers with empty values.
+    fn cors_directives(site: &DomainConfig) -> String {
+        let origins = &site.headers.cors_origins;
+        if origins.is_empty() {
+            return String::new();
+        }
+
+        let mut out = String::from("\n\n    # CORS");
+        if origins.iter().any(|o| o == "*") {
+            out.push_str("\n    set $ls_cors_origin \"*\";");
+        } else {
+            let pattern = origins
+                .iter()
+                .map(|o| o.replace('.', "\\."))
+                .collect::<Vec<_>>()
+                .join("|");
+            out.push_str(&format!(
+                "\n    set $ls_cors_origin \"\";\n    if ($http_origin ~* \"^({})$\") {{\n        set $ls_cors_origin $http_origin;\n    }}",
+                pattern
+            ));
+        }
+        out.push_str(
+            "\n    set $ls_cors_preflight \"\";\n    if ($request_method = OPTIONS) {\n        set $ls_cors_preflight $http_access_control_request_method;\n    }",
+        );
+        out.push_str(&Self::cors_headers(site, "    "));
+        out
+    }
+
+    fn cors_headers(site: &DomainConfig, indent: &str) -> String {
+        let mut out = format!(
+            "\n{indent}add_header Access-Control-Allow-Origin $ls_cors_origin always;\n{indent}add_header Vary Origin always;",
+            indent = indent
+        );
+        // Credentials can't be combined with a wildcard origin
+        if !site.headers.cors_origins.iter().any(|o| o == "*") {
+            out.push_str(&format!(
+                "\n{}add_header Access-Control-Allow-Credentials \"true\" always;",
+                indent
+            ));
+        }
+        out
+    }
+
+    /// Answer CORS preflight requests at the edge. Headers set inside `if`
+    /// replace the server-level ones, so the CORS headers are repeated.
+    fn cors_preflight(site: &DomainConfig) -> String {
+        if site.headers.cors_origins.is_empty() {
+            return String::new();
+        }
+
+        format!(
+            r#"
+        # CORS preflight
+        if ($ls_cors_preflight) {{{headers}
+            add_header Access-Control-Allow-Methods "GET, POST, PUT, PATCH, DELETE, OPTIONS" always;
+            add_header Access-Control-Allow-Headers $http_access_control_request_headers always;
+            add_header Access-Control-Max-Age 86400 always;
+            return 204;
+        }}
+"#,
+            headers = Self::cors_headers(site, "            ")
+        )
+    }
+
+    /// Server-level allow/deny rules and rate limiting
+    fn access_directives(site: &DomainConfig) -> String {
+        let access = &site.access;
+        let mut out = String::new();
+
+        if !access.allow.is_empty() || !access.deny.is_empty() {
+            out.push_str("\n\n    # Access Control");
+            for source in &access.allow {
+                out.push_str(&format!("\n    allow {};", source));
+            }
+            for source in &access.deny {
+                out.push_str(&format!("\n    deny {};", source));
+            }
+        }
+
+        if access.rate_limit.is_some() {
+            let burst = access
+                .burst
+                .map(|b| format!(" burst={} nodelay", b))
+                .unwrap_or_default();
+            out.push_str(&format!(
+                "\n\n    # Rate Limiting (zone in {})\n    limit_req zone={}{};\n    limit_req_status 429;",
+                Paths::nginx_shared_include(),
+                Self::zone_name("ls_req", &site.domain),
+                burst
+            ));
+        }
+
+        out
+    }
+
+    fn compression_directives(site: &DomainConfig) -> String {
+        let mut out = String::new();
+
+        if site.gzip || site.brotli {
+            out.push_str("\n\n    # Compression");
+        }
+        if site.gzip {
+            out.push_str(&format!(
+                "\n    gzip on;\n    gzip_vary on;\n    gzip_proxied any;\n    gzip_comp_level 5;\n    gzip_min_length 256;\n    gzip_types {};",
+                COMPRESSIBLE_TYPES
+            ));
+        }
+        if site.brotli {
+            out.push_str(&format!(
+                "\n    brotli on;\n    brotli_comp_level 5;\n    brotli_min_length 256;\n    brotli_types {};",
+                COMPRESSIBLE_TYPES
+            ));
+        }
+
+        out
+    }
+
+    /// Proxy cache settings; the zone lives in the shared include
+    fn cache_directives(site: &DomainConfig) -> String {
+        match site.cache_ttl {
+            Some(ref ttl) => format!(
+                r#"
+
+    # Proxy Cache (zone in {include})
+    proxy_cache {zone};
+    proxy_cache_valid 200 301 302 {ttl};
+    proxy_cache_use_stale error timeout updating http_500 http_502 http_503 http_504;
+    proxy_cache_lock on;
+    add_header X-Cache-Status $upstream_cache_status always;"#,
+                include = Paths::nginx_shared_include(),
+                zone = Self::zone_name("ls_cache", &site.domain),
+                ttl = ttl
+            ),
+            None => String::new(),
+        }
+    }
+
+    /// Contents of the managed http-level include
+    fn shared_config(sites: &[&DomainConfig]) -> String {
+        let mut sites: Vec<_> = sites
+            .iter()
+            .filter(|s| s.kind == SiteKind::Http && s.server == ServerKind::Nginx)
+            .collect();
+        sites.sort_by(|a, b| a.domain.cmp(&b.domain));
+
+        let mut out = String::from("# Managed by localstacker - do not edit\n");
+
+        let rate_limited: Vec<_> = sites
+            .iter()
+            .filter_map(|s| s.access.rate_limit.as_ref().map(|rate| (s, rate)))
+            .collect();
+        if !rate_limited.is_empty() {
+            out.push_str("\n# Rate limiting zones\n");
+            for (site, rate) in rate_limited {
+                out.push_str(&format!(
+                    "limit_req_zone $binary_remote_addr zone={}:10m rate={};\n",
+                    Self::zone_name("ls_req", &site.domain),
+                    rate
+                ));
+            }
+        }
+
+        let cached: Vec<_> = sites.iter().filter(|s| s.cache_ttl.is_some()).collect();
+        if !cached.is_empty() {
+            out.push_str("\n# Proxy cache zones\n");
+            for site in cached {
+                out.push_str(&format!(
+                    "proxy_cache_path {} levels=1:2 keys_zone={}:10m max_size=1g inactive=60m use_temp_path=off;\n",
+                    Paths::nginx_cache_dir(&site.domain),
+                    Self::zone_name("ls_cache", &site.domain)
+                ));
+            }
+        }
+
+        out
+    }
+
+    /// TLS policy shared by every generated server block
+    fn ssl_snippet(&self) -> String {
+        self.ssl_policy("SSL")
+    }
+
+    /// The same policy for TCP sites, with a session cache zone of the stream module's own
+    fn stream_ssl_snippet(&self) -> String {
+        self.ssl_policy("STREAM_SSL")
+    }
+
+    fn ssl_policy(&self, session_zone: &str) -> String {
+        let caps = &self.capabilities;
+        format!(
+            r#"# Managed by localstacker - do not edit
+ssl_protocols {ssl_protocols};
+ssl_ciphers {ssl_ciphers};
+ssl_prefer_server_ciphers {prefer_server_ciphers};
+ssl_session_cache shared:{session_zone}:10m;
+ssl_session_timeout 10m;
+"#,
+            ssl_protocols = caps.ssl_protocols(),
+            ssl_ciphers = caps.ssl_ciphers(),
+            prefer_server_ciphers = caps.ssl_prefer_server_ciphers(),
+            session_zone = session_zone,
+        )
+    }
+
+    /// Proxy headers, websocket support, timeouts and buffering shared by
+    /// every proxying location
+    fn proxy_snippet() -> &'static str {
+        r#"# Managed by localstacker - do not edit
+
+# Proxy Headers
+proxy_set_header Host $host;
+proxy_set_header X-Real-IP $remote_addr;
+proxy_set_header X-Forwarded-For $proxy_add_x_forwarded_for;
+proxy_set_header X-Forwarded-Proto $scheme;
+proxy_set_header X-Forwarded-Host $host;
+proxy_set_header X-Forwarded-Port $server_port;
+
+# WebSocket Support
+proxy_http_version 1.1;
+proxy_set_header Upgrade $http_upgrade;
+proxy_set_header Connection "upgrade";
+
+# Timeouts
+proxy_connect_timeout 60s;
+proxy_send_timeout 60s;
+proxy_read_timeout 60s;
+
+# Buffering
+proxy_buffering on;
+proxy_buffer_size 4k;
+proxy_buffers 8 4k;
+proxy_busy_buffers_size 8k;
+"#
+    }
+
+    /// Body of a proxying `location` block
+    fn proxy_directives(site: &DomainConfig) -> String {
+        let early_data_header = if site.http3 {
+            "\n        proxy_set_header Early-Data $ssl_early_data;"
+        } else {
+            ""
+        };
+
+        format!(
+            "        proxy_pass http://127.0.0.1:{port};\n        include {snippet};{early_data_header}",
+            port = site.port,
+            snippet = Paths::nginx_proxy_snippet(),
+            early_data_header = early_data_header,
+        )
+    }
+
+    /// `error_page` rules and the internal location serving the static pages
+    fn error_page_directives(site: &DomainConfig) -> String {
+        let rules = if site.maintenance {
+            format!(
+                "    error_page 502 504 {loc}{down};\n    error_page 503 {loc}{maintenance};",
+                loc = error_pages::LOCATION,
+                down = error_pages::BACKEND_DOWN_PAGE,
+                maintenance = error_pages::MAINTENANCE_PAGE,
+            )
+        } else {
+            format!(
+                "    error_page 502 503 504 {}{};",
+                error_pages::LOCATION,
+                error_pages::BACKEND_DOWN_PAGE
+            )
+        };
+
+        format!(
+            "\n\n    # Error Pages\n{rules}\n    location ^~ {loc} {{\n        internal;\n        alias {dir}/;\n    }}",
+            rules = rules,
+            loc = error_pages::LOCATION,
+            dir = Paths::error_pages_dir(&site.domain),
+        )
+    }
+
+    fn auth_directives(site: &DomainConfig) -> String {
+        if !site.basic_auth {
+            return String::new();
+        }
+        format!(
+            "\n\n    # Basic Authentication\n    auth_basic \"Restricted\";\n    auth_basic_user_file {};",
+            Paths::htpasswd(ServerKind::Nginx, &site.domain)
+        )
     }
 
-    fn default_template(domain: &str, port: u16) -> String {
+    /// Server-level maintenance switch for templates, which may have any
+    /// number of locations. It runs again after the internal redirect to
+    /// the error page, so that location and /.well-known/ are let through.
+    fn maintenance_directives(site: &DomainConfig) -> String {
+        if !site.maintenance {
+            return String::new();
+        }
+        format!(
+            "\n\n    # Maintenance mode\n    if ($uri !~ \"^({}|/\\.well-known/)\") {{\n        return 503;\n    }}",
+            error_pages::LOCATION.replace('.', "\\.")
+        )
+    }
+
+    /// What the option placeholders of a custom template expand to, empty
+    /// for options the site doesn't use
+    fn template_blocks(site: &DomainConfig) -> Vec<(&'static str, String)> {
+        vec![
+            ("headers", format!("{}{}", Self::header_directives(site), Self::cors_directives(site))),
+            ("cors_preflight", Self::cors_preflight(site)),
+            ("access", Self::access_directives(site)),
+            ("auth", Self::auth_directives(site)),
+            ("compression", Self::compression_directives(site)),
+            ("cache", Self::cache_directives(site)),
+            ("error_pages", Self::error_page_directives(site)),
+            ("maintenance", Self::maintenance_directives(site)),
+        ]
+    }
+
+    /// Fill a `--template` (registry name or file path) for a site,
+    /// including the per-site option blocks
+    pub(crate) fn render_template(reference: &str, site: &DomainConfig) -> Result<String> {
+        let template = templates::resolve(reference)?;
+        let config = render_content(&template.content, site);
+        Ok(fill_blocks(&config, &Self::template_blocks(site)))
+    }
+
+    fn default_template(&self, site: &DomainConfig) -> String {
+        let domain = site.domain.as_str();
+        let port = site.port;
+        let http_port = site.effective_http_port();
+        let https_port = site.effective_https_port();
+
+        // Keep a non-standard HTTPS port in the redirect target
+        let redirect_port = match https_port {
+            443 => String::new(),
+            other => format!(":{}", other),
+        };
+
+        let (listen_quic, early_data) = if site.http3 {
+            (
+                self.quic_listen_lines(https_port, site.quic_reuseport),
+                format!(
+                    "\n\n    # HTTP/3 (QUIC)\n    ssl_early_data on;\n    add_header Alt-Svc 'h3=\":{}\"; ma=86400' always;",
+                    https_port
+                ),
+            )
+        } else {
+            (String::new(), String::new())
+        };
+
+        // Basic auth covers everything except /.well-known (ACME, etc.)
+        let well_known = if site.basic_auth {
+            format!(
+                "\n\n    location ^~ /.well-known/ {{\n        auth_basic off;\n{}\n    }}",
+                Self::proxy_directives(site)
+            )
+        } else {
+            String::new()
+        };
+
+        let location_body = if site.maintenance {
+            "\n        # Maintenance mode\n        return 503;".to_string()
+        } else {
+            format!("{}\n{}", Self::cors_preflight(site), Self::proxy_directives(site))
+        };
+
         format!(
             r#"# Generated by localstacker
 # Domain: {domain}
 # Backend: 127.0.0.1:{port}
 
 server {{
-    listen 80;
-    listen [::]:80;
+{listen_http}
     server_name {domain};
     
     # Redirect all HTTP to HTTPS
-    return 301 https://$server_name$request_uri;
+    return 301 https://$server_name{redirect_port}$request_uri;
 }}
 
 server {{
-    listen 443 ssl http2;
-    listen [::]:443 ssl http2;
+{listen_https}{listen_quic}
     server_name {domain};
 
     # SSL Configuration
-    ssl_certificate     /etc/nginx/ssl/{domain}.pem;
-    ssl_certificate_key /etc/nginx/ssl/{domain}-key.pem;
+    ssl_certificate     {ssl_cert};
+    ssl_certificate_key {ssl_key};
     
-    # SSL Security Settings
-    ssl_protocols TLSv1.2 TLSv1.3;
-    ssl_ciphers HIGH:!aNULL:!MD5;
-    ssl_prefer_server_ciphers on;
-    ssl_session_cache shared:SSL:10m;
-    ssl_session_timeout 10m;
-
-    # Security Headers
-    add_header Strict-Transport-Security "max-age=31536000; includeSubDomains" always;
-    add_header X-Frame-Options "SAMEORIGIN" always;
-    add_header X-Content-Type-Options "nosniff" always;
-    add_header X-XSS-Protection "1; mode=block" always;
+    include {ssl_snippet};{early_data}{headers}{cors}
 
     # Logging
-    access_log /var/log/nginx/{domain}_access.log;
-    error_log /var/log/nginx/{domain}_error.log;
+    access_log {log_dir}/{domain}_access.log;
+    error_log {log_dir}/{domain}_error.log;{access}{auth}{compression}{cache}{error_pages}
 
     # Proxy Configuration
-    location / {{
-        proxy_pass http://127.0.0.1:{port};
-        
-        # Proxy Headers
-        proxy_set_header Host $host;
-        proxy_set_header X-Real-IP $remote_addr;
-        proxy_set_header X-Forwarded-For $proxy_add_x_forwarded_for;
-        proxy_set_header X-Forwarded-Proto $scheme;
-        proxy_set_header X-Forwarded-Host $host;
-        proxy_set_header X-Forwarded-Port $server_port;
-        
-        # WebSocket Support
-        proxy_http_version 1.1;
-        proxy_set_header Upgrade $http_upgrade;
-        proxy_set_header Connection "upgrade";
-        
-        # Timeouts
-        proxy_connect_timeout 60s;
-        proxy_send_timeout 60s;
-        proxy_read_timeout 60s;
-        
-        # Buffering
-        proxy_buffering on;
-        proxy_buffer_size 4k;
-        proxy_buffers 8 4k;
-        proxy_busy_buffers_size 8k;
-    }}
+    location / {{{location_body}
+    }}{well_known}
 }}
 "#,
             domain = domain,
-            port = port
+            ssl_cert = Paths::ssl_cert(ServerKind::Nginx, domain),
+            ssl_key = Paths::ssl_key(ServerKind::Nginx, domain),
+            log_dir = Paths::nginx_log_dir(),
+            port = port,
+            redirect_port = redirect_port,
+            listen_http = self.listen_lines(http_port, ""),
+            listen_https = self.https_listen_lines(https_port),
+            listen_quic = listen_quic,
+            early_data = early_data,
+            headers = Self::header_directives(site),
+            cors = Self::cors_directives(site),
+            access = Self::access_directives(site),
+            compression = Self::compression_directives(site),
+            cache = Self::cache_directives(site),
+            error_pages = Self::error_page_directives(site),
+            auth = Self::auth_directives(site),
+            location_body = location_body,
+            well_known = well_known,
+            ssl_snippet = Paths::nginx_ssl_snippet(),
         )
     }
+
+    fn redirect_template(&self, site: &DomainConfig, target: &RedirectTarget) -> String {
+        let domain = site.domain.as_str();
+        let location = if target.preserve_path {
+            format!("{}$request_uri", target.to)
+        } else {
+            target.to.clone()
+        };
+
+        format!(
+            r#"# Generated by localstacker
+# Domain: {domain} (redirect)
+# Target: {to} ({code})
+
+server {{
+{listen_http}
+    server_name {domain};
+
+    return {code} {location};
+}}
+
+server {{
+{listen_https}
+    server_name {domain};
+
+    # SSL Configuration
+    ssl_certificate     {ssl_cert};
+    ssl_certificate_key {ssl_key};
+
+    include {ssl_snippet};
+
+    # Logging
+    access_log {log_dir}/{domain}_access.log;
+    error_log {log_dir}/{domain}_error.log;
+
+    return {code} {location};
+}}
+"#,
+            domain = domain,
+            ssl_cert = Paths::ssl_cert(ServerKind::Nginx, domain),
+            ssl_key = Paths::ssl_key(ServerKind::Nginx, domain),
+            log_dir = Paths::nginx_log_dir(),
+            to = target.to,
+            code = target.code,
+            location = location,
+            listen_http = self.listen_lines(site.effective_http_port(), ""),
+            listen_https = self.https_listen_lines(site.effective_https_port()),
+            ssl_snippet = Paths::nginx_ssl_snippet(),
+        )
+    }
+
+    fn stream_template(&self, domain: &str, listen_port: u16, port: u16) -> String {
+        format!(
+            r#"# Generated by localstacker
+# Domain: {domain} (TCP)
+# Listen: {listen_port} (TLS)
+# Backend: 127.0.0.1:{port}
+
+server {{
+{listen}
+
+    # SSL Configuration
+    ssl_certificate     {ssl_cert};
+    ssl_certificate_key {ssl_key};
+    include {ssl_snippet};
+
+    # Proxy Configuration
+    proxy_pass 127.0.0.1:{port};
+    proxy_connect_timeout 10s;
+    proxy_timeout 10m;
+}}
+"#,
+            domain = domain,
+            ssl_cert = Paths::ssl_cert(ServerKind::Nginx, domain),
+            ssl_key = Paths::ssl_key(ServerKind::Nginx, domain),
+            listen_port = listen_port,
+            port = port,
+            listen = self.listen_lines(listen_port, "ssl"),
+            ssl_snippet = Paths::nginx_stream_ssl_snippet(),
+        )
+    }
+
+    /// Catch structural mistakes in a custom template before it's written
+    /// and enabled, instead of leaving them to `nginx -t`
+    fn lint_template(template_path: &str, site: &DomainConfig, config: &str) -> Result<()> {
+        let context = match site.kind {
+            SiteKind::Tcp => LintContext::Stream,
+            SiteKind::Http | SiteKind::Redirect => LintContext::Http,
+        };
+        let findings = nginx_lint::lint(config, context, Some(&site.domain))
+            .map_err(|e| Error::Validation(format!("{}: {}", template_path, e)))?;
+
+        let mut errors = Vec::new();
+        for finding in findings {
+            match finding.severity {
+                Severity::Error => errors.push(finding.to_string()),
+                Severity::Warning => log_warning(&format!("{}: {}", template_path, finding)),
+            }
+        }
+        if !errors.is_empty() {
+            return Err(Error::Validation(format!(
+                "{} has errors:\n  {}",
+                template_path,
+                errors.join("\n  ")
+            )));
+        }
+        Ok(())
+    }
+
+    /// Make sure the managed `stream {}` block exists and is included
+    /// from the main nginx.conf. Stream servers must live outside `http {}`,
+    /// so they cannot go through sites-enabled.
+    fn ensure_stream_include(&self) -> Result<()> {
+        fs::create_dir_all(Paths::nginx_streams_enabled())
+            .map_err(|e| Error::Config(format!("Failed to create stream directory: {}", e)))?;
+
+        let include_path = Paths::nginx_stream_include();
+        if !Path::new(&include_path).exists() {
+            log_verbose(&format!("Creating {}", include_path));
+            let content = format!(
+                "# Managed by localstacker - do not edit\nstream {{\n    include {}/*;\n}}\n",
+                Paths::nginx_streams_enabled()
+            );
+            fs::write(&include_path, content)
+                .map_err(|e| Error::Config(format!("Failed to write stream include: {}", e)))?;
+        }
+
+        let main_config = Paths::nginx_main_config();
+        let contents = fs::read_to_string(main_config)
+            .map_err(|e| Error::Config(format!("Failed to read {}: {}", main_config, e)))?;
+        let directive = format!("include {};", include_path);
+
+        if !contents.contains(&directive) {
+            log_info(&format!("Adding stream include to {}...", main_config));
+            let mut updated = contents;
+            if !updated.ends_with('\n') {
+                updated.push('\n');
+            }
+            updated.push_str(&format!(
+                "\n# Added by localstacker for TCP (stream) sites\n{}\n",
+                directive
+            ));
+            fs::write(main_config, updated)
+                .map_err(|e| Error::Config(format!("Failed to update {}: {}", main_config, e)))?;
+        }
+
+        Ok(())
+    }
 }
 
 impl WebServerConfig for NginxConfig {
-    fn generate_config(&self, domain: &str, port: u16, template: Option<&str>) -> Result<String> {
+    fn generate_config(&self, site: &DomainConfig, template: Option<&str>) -> Result<String> {
+        let domain = site.domain.as_str();
+        let port = site.port;
         log_verbose(&format!("Generating nginx config for {}", domain));
 
         if let Some(template_path) = template {
-            // Load custom template
-            let template_content = fs::read_to_string(template_path)
-                .map_err(|e| Error::Config(format!("Failed to read template: {}", e)))?;
-
-            // Simple variable replacement
-            let config = template_content
-                .replace("{{domain}}", domain)
-                .replace("{{port}}", &port.to_string());
-
+            let missing = templates::missing_options(site, Some(&templates::resolve(template_path)?.content));
+            if !missing.is_empty() {
+                return Err(Error::Validation(format!(
+                    "{} has no placeholders for {}, see `localstacker template show proxy`",
+                    template_path,
+                    missing.join(", ")
+                )));
+            }
+            let config = Self::render_template(template_path, site)?;
+            Self::lint_template(template_path, site, &config)?;
             Ok(config)
         } else {
-            Ok(Self::default_template(domain, port))
+            match site.kind {
+                SiteKind::Http => {
+                    if site.http3 && !self.capabilities.supports_http3() {
+                        return Err(Error::Validation(format!(
+                            "nginx {} does not support HTTP/3",
+                            self.capabilities.version_string()
+                        )));
+                    }
+                    Ok(self.default_template(site))
+                }
+                SiteKind::Redirect => {
+                    let target = site.redirect.as_ref().ok_or_else(|| {
+                        Error::Config(format!("Redirect site {} has no target", domain))
+                    })?;
+                    Ok(self.redirect_template(site, target))
+                }
+                SiteKind::Tcp => {
+                    if !self.capabilities.supports_stream() {
+                        return Err(Error::Validation(format!(
+                            "nginx {} was built without the stream module",
+                            self.capabilities.version_string()
+                        )));
+                    }
+                    let listen_port = site.listen_port.ok_or_else(|| {
+                        Error::Config(format!("TCP site {} has no listen port", domain))
+                    })?;
+                    Ok(self.stream_template(domain, listen_port, port))
+                }
+            }
         }
     }
 
-    fn write_config(&self, domain: &str, config: &str) -> Result<()> {
-        log_info(&format!("Writing nginx configuration for {}...", domain));
+    fn write_shared_config(&self, sites: &[&DomainConfig]) -> Result<()> {
+        let path = Paths::nginx_shared_include();
+        log_verbose(&format!("Writing shared nginx config {}", path));
+
+        if let Some(parent) = Path::new(&path).parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| Error::Config(format!("Failed to create {}: {}", parent.display(), e)))?;
+        }
+
+        fs::write(&path, Self::shared_config(sites))
+            .map_err(|e| Error::Config(format!("Failed to write {}: {}", path, e)))?;
 
-        let config_path = Paths::nginx_config(domain);
-        
-        if Path::new(&config_path).exists() {
-            // replace existing config
-            fs::write(&config_path, config)
-                .map_err(|e| Error::Config(format!("Failed to write nginx config: {}", e)))?;   
-            // return Err(Error::Already(format!(
-            //     "Nginx config for {} already exists",
-            //     domain
-            // )));
+        let snippets_dir = Paths::nginx_snippets_dir();
+        fs::create_dir_all(&snippets_dir)
+            .map_err(|e| Error::Config(format!("Failed to create {}: {}", snippets_dir, e)))?;
+        for (path, contents) in [
+            (Paths::nginx_ssl_snippet(), self.ssl_snippet()),
+            (Paths::nginx_stream_ssl_snippet(), self.stream_ssl_snippet()),
+            (Paths::nginx_proxy_snippet(), Self::proxy_snippet().to_string()),
+        ] {
+            log_verbose(&format!("Writing shared nginx snippet {}", path));
+            fs::write(&path, contents)
+                .map_err(|e| Error::Config(format!("Failed to write {}: {}", path, e)))?;
         }
 
-        fs::write(&config_path, config)
+        Ok(())
+    }
+
+    fn write_config(&self, site: &DomainConfig, config: &str) -> Result<()> {
+        log_info(&format!("Writing nginx configuration for {}...", site.domain));
+
+        match site.kind {
+            SiteKind::Tcp => {
+                fs::create_dir_all(Paths::nginx_streams_available()).map_err(|e| {
+                    Error::Config(format!("Failed to create stream directory: {}", e))
+                })?;
+            }
+            SiteKind::Http => error_pages::write(site)?,
+            SiteKind::Redirect => {}
+        }
+
+        // Existing configs are replaced
+        fs::write(&site.config_path, config)
             .map_err(|e| Error::Config(format!("Failed to write nginx config: {}", e)))?;
 
         Ok(())
     }
 
-    fn enable_site(&self, domain: &str) -> Result<()> {
-        log_info(&format!("Enabling site {}...", domain));
+    fn enable_site(&self, site: &DomainConfig) -> Result<()> {
+        log_info(&format!("Enabling site {}...", site.domain));
+
+        if site.kind == SiteKind::Tcp {
+            self.ensure_stream_include()?;
+        }
+
+        let available = &site.config_path;
+        let enabled = site.enabled_link();
 
-        let available = Paths::nginx_config(domain);
-        let enabled = Paths::nginx_enabled(domain);
+        // conf.d layouts enable a site by dropping the `.disabled` suffix
+        if enabled == *available {
+            return enable_renamed(available);
+        }
 
         if Path::new(&enabled).exists() {
             log_verbose("Site already enabled");
@@ -143,16 +794,20 @@ impl WebServerConfig for NginxConfig {
         }
 
         #[cfg(unix)]
-        std::os::unix::fs::symlink(&available, &enabled)
+        std::os::unix::fs::symlink(available, &enabled)
             .map_err(|e| Error::Config(format!("Failed to enable site: {}", e)))?;
 
         Ok(())
     }
 
-    fn disable_site(&self, domain: &str) -> Result<()> {
-        log_info(&format!("Disabling site {}...", domain));
+    fn disable_site(&self, site: &DomainConfig) -> Result<()> {
+        log_info(&format!("Disabling site {}...", site.domain));
+
+        let enabled = site.enabled_link();
 
-        let enabled = Paths::nginx_enabled(domain);
+        if enabled == site.config_path {
+            return disable_renamed(&enabled);
+        }
 
         if !Path::new(&enabled).exists() {
             log_verbose("Site already disabled");
@@ -165,6 +820,41 @@ impl WebServerConfig for NginxConfig {
         Ok(())
     }
 
+    /// Options a custom template has no placeholders for
+    fn unsupported_features(&self, site: &DomainConfig) -> Vec<&'static str> {
+        match site.template {
+            Some(ref reference) => templates::resolve(reference)
+                .map(|template| templates::missing_options(site, Some(&template.content)))
+                .unwrap_or_default(),
+            None => Vec::new(),
+        }
+    }
+
+    fn site_paths(&self, site: &DomainConfig) -> Vec<String> {
+        let mut paths = vec![
+            site.config_path.clone(),
+            site.enabled_link(),
+        ];
+        if paths[0] == paths[1] {
+            paths[1] = Paths::disabled_config(&site.config_path);
+        }
+        if site.kind == SiteKind::Http {
+            paths.push(Paths::error_pages_dir(&site.domain));
+        }
+        paths
+    }
+
+    fn shared_paths(&self) -> Vec<String> {
+        vec![
+            Paths::nginx_shared_include().to_string(),
+            Paths::nginx_ssl_snippet(),
+            Paths::nginx_stream_ssl_snippet(),
+            Paths::nginx_proxy_snippet(),
+            Paths::nginx_stream_include().to_string(),
+            Paths::nginx_main_config().to_string(),
+        ]
+    }
+
     fn test_config(&self) -> Result<()> {
         log_info("Testing nginx configuration...");
         execute_command("nginx", &["-t"], "Test nginx config")?;
@@ -173,19 +863,255 @@ impl WebServerConfig for NginxConfig {
 
     fn reload(&self) -> Result<()> {
         log_info("Reloading nginx...");
-        execute_command("systemctl", &["reload", "nginx"], "Reload nginx")?;
-        Ok(())
+        ReloadStrategy::global().reload()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::HeaderProfile;
+
+    fn site(domain: &str, port: u16) -> DomainConfig {
+        DomainConfig {
+            domain: domain.to_string(),
+            port,
+            ..Default::default()
+        }
+    }
 
     #[test]
     fn test_default_template_contains_domain() {
-        let config = NginxConfig::default_template("test.local", 3000);
+        let nginx = NginxConfig::with_capabilities(NginxCapabilities::default());
+        let config = nginx.default_template(&site("test.local", 3000));
         assert!(config.contains("test.local"));
         assert!(config.contains("127.0.0.1:3000"));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_default_template_adapts_to_nginx_version() {
+        let modern = NginxConfig::with_capabilities(NginxCapabilities::parse(
+            "nginx version: nginx/1.25.3\nconfigure arguments: --with-http_v2_module",
+            true,
+        ));
+        let config = modern.default_template(&site("test.local", 3000));
+        assert!(config.contains("    listen 443 ssl;\n    listen [::]:443 ssl;\n    http2 on;"));
+
+        let old = NginxConfig::with_capabilities(NginxCapabilities::parse(
+            "nginx version: nginx/1.18.0\nconfigure arguments: --with-http_v2_module",
+            false,
+        ));
+        let config = old.default_template(&site("test.local", 3000));
+        assert!(config.contains("listen 443 ssl http2;"));
+        assert!(!config.contains("[::]"));
+    }
+
+    #[test]
+    fn test_custom_ports_carry_into_redirect() {
+        let nginx = NginxConfig::with_capabilities(NginxCapabilities::default());
+        let mut custom = site("app.test", 3000);
+        custom.http_port = Some(8080);
+        custom.https_port = Some(8443);
+
+        let config = nginx.default_template(&custom);
+        assert!(config.contains("listen 8080;"));
+        assert!(config.contains("listen 8443 ssl;"));
+        assert!(config.contains("return 301 https://$server_name:8443$request_uri;"));
+    }
+
+    #[test]
+    fn test_basic_auth_exempts_well_known() {
+        let nginx = NginxConfig::with_capabilities(NginxCapabilities::default());
+        let mut protected = site("admin.test", 9000);
+        protected.basic_auth = true;
+
+        let config = nginx.default_template(&protected);
+        assert!(config.contains("auth_basic_user_file /etc/nginx/htpasswd/admin.test;"));
+        assert!(config.contains("location ^~ /.well-known/ {\n        auth_basic off;"));
+        assert_eq!(config.matches("proxy_pass http://127.0.0.1:9000;").count(), 2);
+    }
+
+    #[test]
+    fn test_access_policy_and_rate_limit_zone() {
+        let nginx = NginxConfig::with_capabilities(NginxCapabilities::default());
+        let mut admin = site("admin.app.test", 9000);
+        admin.access.allow = vec!["10.0.0.0/8".to_string()];
+        admin.access.deny = vec!["all".to_string()];
+        admin.access.rate_limit = Some("10r/s".to_string());
+        admin.access.burst = Some(20);
+
+        let config = nginx.default_template(&admin);
+        assert!(config.contains("    allow 10.0.0.0/8;\n    deny all;"));
+        assert!(config.contains("limit_req zone=ls_req_admin.app.test burst=20 nodelay;"));
+
+        let shared = NginxConfig::shared_config(&[&admin, &site("open.test", 3000)]);
+        assert!(shared.contains(
+            "limit_req_zone $binary_remote_addr zone=ls_req_admin.app.test:10m rate=10r/s;"
+        ));
+        assert!(!shared.contains("open.test"));
+
+        // Domains that only differ in `.` and `-` get their own zones
+        let mut dashed = site("a-b.test", 3000);
+        let mut dotted = site("a.b.test", 3001);
+        dashed.cache_ttl = Some("10m".to_string());
+        dotted.cache_ttl = Some("10m".to_string());
+        let shared = NginxConfig::shared_config(&[&dashed, &dotted]);
+        assert!(shared.contains("keys_zone=ls_cache_a-b.test:10m"));
+        assert!(shared.contains("keys_zone=ls_cache_a.b.test:10m"));
+    }
+
+    #[test]
+    fn test_header_profiles_and_custom_headers() {
+        let nginx = NginxConfig::with_capabilities(NginxCapabilities::default());
+
+        let config = nginx.default_template(&site("app.test", 3000));
+        assert!(config.contains("X-Content-Type-Options"));
+        assert!(!config.contains("Strict-Transport-Security"));
+        assert!(!config.contains("X-XSS-Protection"));
+
+        let mut strict = site("app.test", 3000);
+        strict.headers.profile = HeaderProfile::Strict;
+        strict.headers.custom = vec!["X-Env: local \"dev\"".to_string()];
+        let config = nginx.default_template(&strict);
+        assert!(config.contains("add_header Strict-Transport-Security \"max-age=31536000\" always;"));
+        assert!(!config.contains("includeSubDomains"));
+        assert!(config.contains("add_header X-Env \"local \\\"dev\\\"\" always;"));
+
+        let mut bare = site("app.test", 3000);
+        bare.headers.profile = HeaderProfile::None;
+        assert!(!nginx.default_template(&bare).contains("add_header"));
+    }
+
+    #[test]
+    fn test_cors_preflight() {
+        let nginx = NginxConfig::with_capabilities(NginxCapabilities::default());
+        let mut api = site("api.test", 4000);
+        api.headers.cors_origins = vec![
+            "https://app.test".to_string(),
+            "https://admin.test:8443".to_string(),
+        ];
+
+        let config = nginx.default_template(&api);
+        assert!(config.contains(r#"if ($http_origin ~* "^(https://app\.test|https://admin\.test:8443)$")"#));
+        assert!(config.contains("if ($ls_cors_preflight) {"));
+        assert!(config.contains("return 204;"));
+        assert!(config.contains("Access-Control-Allow-Credentials"));
+    }
+
+    #[test]
+    fn test_compression_and_cache_zone() {
+        let nginx = NginxConfig::with_capabilities(NginxCapabilities::default());
+        let mut fast = site("app.test", 3000);
+        fast.gzip = true;
+        fast.cache_ttl = Some("10m".to_string());
+
+        let config = nginx.default_template(&fast);
+        assert!(config.contains("gzip on;"));
+        assert!(!config.contains("brotli"));
+        assert!(config.contains("proxy_cache ls_cache_app.test;"));
+        assert!(config.contains("proxy_cache_valid 200 301 302 10m;"));
+
+        let shared = NginxConfig::shared_config(&[&fast]);
+        assert!(shared.contains(
+            "proxy_cache_path /var/cache/nginx/localstacker/app.test levels=1:2 keys_zone=ls_cache_app.test:10m"
+        ));
+    }
+
+    #[test]
+    fn test_http3_listener_and_reuseport() {
+        let nginx = NginxConfig::with_capabilities(NginxCapabilities::parse(
+            "nginx version: nginx/1.25.3\nconfigure arguments: --with-http_v2_module --with-http_v3_module",
+            false,
+        ));
+
+        let mut holder = site("a.test", 3000);
+        holder.http3 = true;
+        holder.quic_reuseport = true;
+        let config = nginx.default_template(&holder);
+        assert!(config.contains("listen 443 quic reuseport;"));
+        assert!(config.contains("ssl_early_data on;"));
+        assert!(config.contains("Alt-Svc"));
+
+        let mut other = site("b.test", 3001);
+        other.http3 = true;
+        let config = nginx.default_template(&other);
+        assert!(config.contains("listen 443 quic;"));
+        assert!(!config.contains("reuseport"));
+    }
+
+    #[test]
+    fn test_maintenance_mode() {
+        let nginx = NginxConfig::with_capabilities(NginxCapabilities::default());
+        let mut app = site("app.test", 3000);
+
+        let config = nginx.default_template(&app);
+        assert!(config.contains("error_page 502 503 504 /.localstacker/502.html;"));
+        assert!(config.contains("alias /var/www/localstacker/app.test/;"));
+        assert!(config.contains("proxy_pass http://127.0.0.1:3000;"));
+
+        app.maintenance = true;
+        let config = nginx.default_template(&app);
+        assert!(config.contains("error_page 503 /.localstacker/maintenance.html;"));
+        assert!(config.contains("return 503;"));
+        assert!(!config.contains("proxy_pass"));
+    }
+
+    #[test]
+    fn test_redirect_template() {
+        let nginx = NginxConfig::with_capabilities(NginxCapabilities::default());
+        let mut alias = site("www.app.test", 0);
+        alias.kind = SiteKind::Redirect;
+        let target = RedirectTarget {
+            to: "https://app.test".to_string(),
+            code: 308,
+            preserve_path: true,
+        };
+
+        let config = nginx.redirect_template(&alias, &target);
+        assert_eq!(config.matches("return 308 https://app.test$request_uri;").count(), 2);
+        assert!(!config.contains("proxy_pass"));
+    }
+
+    #[test]
+    fn test_stream_template_proxies_raw_tcp() {
+        let nginx = NginxConfig::with_capabilities(NginxCapabilities::default());
+        let config = nginx.stream_template("redis.test", 6380, 6379);
+        assert!(config.contains("listen 6380 ssl;"));
+        assert!(config.contains("proxy_pass 127.0.0.1:6379;"));
+        assert!(!config.contains("location"));
+        assert!(config.contains("    include /etc/nginx/snippets/localstacker-stream-ssl.conf;"));
+        assert!(!config.contains("ssl_protocols"));
+        assert!(nginx.stream_ssl_snippet().contains("ssl_session_cache shared:STREAM_SSL:10m;"));
+    }
+
+    #[test]
+    fn test_sites_include_shared_snippets() {
+        let nginx = NginxConfig::with_capabilities(NginxCapabilities::default());
+        let config = nginx.default_template(&site("app.test", 3000));
+        assert!(config.contains("    include /etc/nginx/snippets/localstacker-ssl.conf;"));
+        assert!(config.contains(
+            "proxy_pass http://127.0.0.1:3000;\n        include /etc/nginx/snippets/localstacker-proxy.conf;"
+        ));
+        assert!(!config.contains("ssl_protocols"));
+        assert!(!config.contains("proxy_set_header"));
+
+        let ssl = nginx.ssl_snippet();
+        assert!(ssl.contains(&format!("ssl_protocols {};", nginx.capabilities().ssl_protocols())));
+        assert!(NginxConfig::proxy_snippet().contains("proxy_set_header Upgrade $http_upgrade;"));
+    }
+
+    #[test]
+    fn test_generated_configs_pass_lint() {
+        let nginx = NginxConfig::with_capabilities(NginxCapabilities::default());
+        let mut app = site("app.test", 3000);
+        app.basic_auth = true;
+        app.headers.cors_origins = vec!["https://web.test".to_string()];
+        app.access.rate_limit = Some("10r/s".to_string());
+        app.cache_ttl = Some("10m".to_string());
+        let config = nginx.default_template(&app);
+        assert_eq!(nginx_lint::lint(&config, LintContext::Http, Some("app.test")).unwrap(), vec![]);
+
+        let stream = nginx.stream_template("redis.test", 6380, 6379);
+        assert_eq!(nginx_lint::lint(&stream, LintContext::Stream, Some("redis.test")).unwrap(), vec![]);
+    }
+}
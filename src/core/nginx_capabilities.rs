@@ -0,0 +1,330 @@
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use crate::utils::log_verbose;
+
+/// Modern Mozilla "intermediate" cipher suite for TLSv1.2 (TLSv1.3 suites are fixed)
+const MODERN_CIPHERS: &str = "ECDHE-ECDSA-AES128-GCM-SHA256:ECDHE-RSA-AES128-GCM-SHA256:\
+ECDHE-ECDSA-AES256-GCM-SHA384:ECDHE-RSA-AES256-GCM-SHA384:\
+ECDHE-ECDSA-CHACHA20-POLY1305:ECDHE-RSA-CHACHA20-POLY1305";
+
+/// Cipher string for TLS libraries without TLSv1.3 support
+const FALLBACK_CIPHERS: &str = "HIGH:!aNULL:!MD5";
+
+/// What the installed nginx binary can do, parsed once from `nginx -V`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NginxCapabilities {
+    /// False when nginx could not be queried and defaults are assumed
+    pub detected: bool,
+    pub version: Option<(u32, u32, u32)>,
+    /// TLS library name and version, e.g. ("OpenSSL", (3, 0, 13))
+    pub tls_library: Option<(String, (u32, u32, u32))>,
+    /// Compiled-in modules, e.g. `http_v2_module`, `stream`, `ngx_brotli`
+    pub modules: Vec<String>,
+    /// Raw configure arguments
+    pub configure_args: Vec<String>,
+    /// Whether IPv6 listeners can be used on this host
+    pub ipv6: bool,
+}
+
+impl Default for NginxCapabilities {
+    /// Capabilities assumed when nginx can't be queried: a current mainline build
+    fn default() -> Self {
+        NginxCapabilities {
+            detected: false,
+            version: None,
+            tls_library: None,
+            modules: ["http_ssl_module", "http_v2_module", "stream", "stream_ssl_module"]
+                .iter()
+                .map(|m| m.to_string())
+                .collect(),
+            configure_args: Vec::new(),
+            ipv6: true,
+        }
+    }
+}
+
+impl NginxCapabilities {
+    /// Run `nginx -V` and parse its output
+    pub fn detect() -> Self {
+        let output = match Command::new("nginx").arg("-V").output() {
+            Ok(output) if output.status.success() => output,
+            _ => {
+                log_verbose("Could not run `nginx -V`, assuming default capabilities");
+                return Self::default();
+            }
+        };
+
+        // nginx prints its build information on stderr
+        let mut text = String::from_utf8_lossy(&output.stderr).to_string();
+        text.push_str(&String::from_utf8_lossy(&output.stdout));
+
+        let host_ipv6 = Path::new("/proc/net/if_inet6").exists();
+        let mut caps = Self::parse(&text, host_ipv6);
+
+        // Distro packages ship extra modules (e.g. brotli) as dynamic modules
+        // loaded from modules-enabled, which `nginx -V` doesn't list
+        let conf_dir = caps
+            .configure_arg("--conf-path")
+            .and_then(|path| Path::new(path).parent())
+            .unwrap_or_else(|| Path::new("/etc/nginx"))
+            .to_path_buf();
+        if let Ok(entries) = fs::read_dir(conf_dir.join("modules-enabled")) {
+            for entry in entries.flatten() {
+                if let Ok(contents) = fs::read_to_string(entry.path()) {
+                    caps.modules.extend(Self::parse_load_module(&contents));
+                }
+            }
+        }
+
+        log_verbose(&format!("Detected nginx capabilities: {:?}", caps));
+        caps
+    }
+
+    /// Parse `nginx -V` output
+    pub fn parse(output: &str, host_ipv6: bool) -> Self {
+        let mut caps = NginxCapabilities {
+            detected: true,
+            version: None,
+            tls_library: None,
+            modules: Vec::new(),
+            configure_args: Vec::new(),
+            ipv6: false,
+        };
+
+        for line in output.lines() {
+            let line = line.trim();
+
+            if let Some(rest) = line.strip_prefix("nginx version:") {
+                caps.version = rest
+                    .split_whitespace()
+                    .next()
+                    .and_then(|v| v.split('/').nth(1))
+                    .and_then(parse_version);
+            } else if let Some(rest) = line.strip_prefix("built with ") {
+                let mut parts = rest.split_whitespace();
+                if let (Some(name), Some(version)) = (parts.next(), parts.next()) {
+                    if let Some(version) = parse_version(version) {
+                        caps.tls_library = Some((name.to_string(), version));
+                    }
+                }
+            } else if let Some(rest) = line.strip_prefix("configure arguments:") {
+                caps.configure_args = split_configure_args(rest);
+            }
+        }
+
+        for arg in &caps.configure_args {
+            let (flag, value) = match arg.split_once('=') {
+                Some((flag, value)) => (flag, Some(value)),
+                None => (arg.as_str(), None),
+            };
+
+            if let Some(module) = flag.strip_prefix("--with-") {
+                // `--with-stream=dynamic` is a module, `--with-openssl=/path` is not
+                if value.is_none() || value == Some("dynamic") {
+                    caps.modules.push(module.to_string());
+                }
+            } else if flag == "--add-module" || flag == "--add-dynamic-module" {
+                if let Some(name) = value.and_then(|v| v.trim_end_matches('/').rsplit('/').next()) {
+                    caps.modules.push(name.to_string());
+                }
+            }
+        }
+
+        // IPv6 is built in since 1.11.5; older builds need --with-ipv6
+        let nginx_ipv6 = caps.version_at_least(1, 11, 5) || caps.has_module("ipv6");
+        caps.ipv6 = host_ipv6 && nginx_ipv6;
+
+        caps
+    }
+
+    /// Module names from `load_module modules/ngx_x_module.so;` lines
+    fn parse_load_module(contents: &str) -> Vec<String> {
+        contents
+            .lines()
+            .filter_map(|line| line.trim().strip_prefix("load_module"))
+            .filter_map(|path| {
+                let file = path.trim().trim_end_matches(';').rsplit('/').next()?;
+                Some(file.trim_end_matches(".so").to_string())
+            })
+            .collect()
+    }
+
+    pub fn version_string(&self) -> String {
+        match self.version {
+            Some((major, minor, patch)) => format!("{}.{}.{}", major, minor, patch),
+            None => "unknown".to_string(),
+        }
+    }
+
+    /// True if nginx is at least the given version. Unknown versions are
+    /// treated as current so generated configs use modern syntax.
+    pub fn version_at_least(&self, major: u32, minor: u32, patch: u32) -> bool {
+        match self.version {
+            Some(version) => version >= (major, minor, patch),
+            None => !self.detected,
+        }
+    }
+
+    pub fn has_module(&self, name: &str) -> bool {
+        self.modules.iter().any(|m| m == name)
+    }
+
+    /// Value of a `--key=value` configure argument
+    pub fn configure_arg(&self, key: &str) -> Option<&str> {
+        let prefix = format!("{}=", key);
+        self.configure_args
+            .iter()
+            .find_map(|arg| arg.strip_prefix(prefix.as_str()))
+    }
+
+    pub fn supports_http2(&self) -> bool {
+        self.has_module("http_v2_module")
+    }
+
+    /// `http2 on;` replaced the `listen ... http2` parameter in 1.25.1
+    pub fn uses_http2_directive(&self) -> bool {
+        self.version_at_least(1, 25, 1)
+    }
+
+    pub fn supports_http3(&self) -> bool {
+        self.has_module("http_v3_module") && self.version_at_least(1, 25, 0)
+    }
+
+    pub fn supports_brotli(&self) -> bool {
+        self.modules.iter().any(|m| m.contains("brotli"))
+    }
+
+    pub fn supports_stream(&self) -> bool {
+        self.has_module("stream")
+    }
+
+    pub fn supports_tls13(&self) -> bool {
+        if !self.version_at_least(1, 13, 0) {
+            return false;
+        }
+
+        match &self.tls_library {
+            Some((name, version)) => match name.as_str() {
+                "OpenSSL" => *version >= (1, 1, 1),
+                "LibreSSL" => *version >= (3, 2, 0),
+                _ => true,
+            },
+            None => true,
+        }
+    }
+
+    /// `ssl_protocols` value for this build
+    pub fn ssl_protocols(&self) -> &'static str {
+        if self.supports_tls13() {
+            "TLSv1.2 TLSv1.3"
+        } else {
+            "TLSv1.2"
+        }
+    }
+
+    /// `ssl_ciphers` value for this build
+    pub fn ssl_ciphers(&self) -> &'static str {
+        if self.supports_tls13() {
+            MODERN_CIPHERS
+        } else {
+            FALLBACK_CIPHERS
+        }
+    }
+
+    /// With TLSv1.3 clients choose well; only old builds should prefer server ciphers
+    pub fn ssl_prefer_server_ciphers(&self) -> &'static str {
+        if self.supports_tls13() {
+            "off"
+        } else {
+            "on"
+        }
+    }
+}
+
+fn parse_version(text: &str) -> Option<(u32, u32, u32)> {
+    // Strip suffixes such as "1.1.1w" or "3.0.13-fips"
+    let mut numbers = text.split('.').map(|part| {
+        part.chars()
+            .take_while(|c| c.is_ascii_digit())
+            .collect::<String>()
+            .parse::<u32>()
+            .ok()
+    });
+
+    let major = numbers.next()??;
+    let minor = numbers.next().flatten().unwrap_or(0);
+    let patch = numbers.next().flatten().unwrap_or(0);
+    Some((major, minor, patch))
+}
+
+/// Split configure arguments, honouring single and double quotes
+fn split_configure_args(text: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut quote: Option<char> = None;
+
+    for c in text.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => current.push(c),
+            (None, '\'') | (None, '"') => quote = Some(c),
+            (None, c) if c.is_whitespace() => {
+                if !current.is_empty() {
+                    args.push(std::mem::take(&mut current));
+                }
+            }
+            (None, c) => current.push(c),
+        }
+    }
+
+    if !current.is_empty() {
+        args.push(current);
+    }
+
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UBUNTU_OUTPUT: &str = "nginx version: nginx/1.24.0 (Ubuntu)
+built with OpenSSL 3.0.13 30 Jan 2024
+TLS SNI support enabled
+configure arguments: --with-cc-opt='-g -O2 -fstack-protector-strong' --prefix=/usr/share/nginx --conf-path=/etc/nginx/nginx.conf --with-http_ssl_module --with-http_v2_module --with-stream=dynamic --with-stream_ssl_module --add-dynamic-module=/build/nginx/debian/modules/http-brotli";
+
+    #[test]
+    fn test_parse_nginx_v_output() {
+        let caps = NginxCapabilities::parse(UBUNTU_OUTPUT, true);
+        assert_eq!(caps.version, Some((1, 24, 0)));
+        assert_eq!(caps.tls_library, Some(("OpenSSL".to_string(), (3, 0, 13))));
+        assert!(caps.supports_http2());
+        assert!(caps.supports_stream());
+        assert!(caps.has_module("http-brotli"));
+        assert!(!caps.supports_http3());
+        assert!(!caps.uses_http2_directive());
+        assert!(caps.supports_tls13());
+        assert!(caps.ipv6);
+        assert_eq!(caps.configure_arg("--conf-path"), Some("/etc/nginx/nginx.conf"));
+    }
+
+    #[test]
+    fn test_parse_load_module() {
+        let modules = NginxCapabilities::parse_load_module(
+            "load_module modules/ngx_http_brotli_filter_module.so;\n",
+        );
+        assert_eq!(modules, vec!["ngx_http_brotli_filter_module".to_string()]);
+    }
+
+    #[test]
+    fn test_old_openssl_falls_back_to_tls12() {
+        let output = "nginx version: nginx/1.14.2\nbuilt with OpenSSL 1.0.2k-fips  26 Jan 2017\n";
+        let caps = NginxCapabilities::parse(output, false);
+        assert!(!caps.supports_tls13());
+        assert_eq!(caps.ssl_protocols(), "TLSv1.2");
+        assert_eq!(caps.ssl_ciphers(), FALLBACK_CIPHERS);
+        assert!(!caps.ipv6);
+    }
+}
@@ -0,0 +1,283 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::core::nginx_parser::{self, Directive};
+use crate::utils::log_verbose;
+
+/// Includes deeper than this are assumed to be a loop
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// An http `server` block found while walking the nginx config
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerInfo {
+    pub file: String,
+    pub line: usize,
+    pub names: Vec<String>,
+    pub ports: Vec<u16>,
+    pub default_ports: Vec<u16>,
+}
+
+impl ServerInfo {
+    pub fn location(&self) -> String {
+        format!("{}:{}", self.file, self.line)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictKind {
+    /// The same server_name on the same port; nginx warns and picks one
+    DuplicateName,
+    /// More than one default server on a port; `nginx -t` fails
+    DuplicateDefault,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    pub kind: ConflictKind,
+    pub description: String,
+    pub servers: Vec<ServerInfo>,
+}
+
+/// Every http server block reachable from the main config through `include`
+pub fn load_servers(main_config: &str) -> Vec<ServerInfo> {
+    let prefix = Path::new(main_config)
+        .parent()
+        .unwrap_or_else(|| Path::new("/"))
+        .to_path_buf();
+
+    let mut servers = Vec::new();
+    if let Some(directives) = read_config(Path::new(main_config)) {
+        walk(&directives, main_config, &prefix, false, 0, &mut servers);
+    }
+    servers
+}
+
+fn read_config(path: &Path) -> Option<Vec<Directive>> {
+    let text = fs::read_to_string(path).ok()?;
+    match nginx_parser::parse(&text) {
+        Ok(directives) => Some(directives),
+        Err(e) => {
+            log_verbose(&format!("Skipping {}: {}", path.display(), e));
+            None
+        }
+    }
+}
+
+fn walk(
+    directives: &[Directive],
+    file: &str,
+    prefix: &Path,
+    in_stream: bool,
+    depth: usize,
+    servers: &mut Vec<ServerInfo>,
+) {
+    for directive in directives {
+        match directive.name.as_str() {
+            "include" if depth < MAX_INCLUDE_DEPTH => {
+                for path in directive.arg(0).map(|p| expand_include(prefix, p)).unwrap_or_default() {
+                    if let Some(included) = read_config(&path) {
+                        let name = path.to_string_lossy().to_string();
+                        walk(&included, &name, prefix, in_stream, depth + 1, servers);
+                    }
+                }
+            }
+            "stream" => walk(directive.children(), file, prefix, true, depth, servers),
+            // stream servers have no server_name and can't clash with http ones
+            "server" if in_stream => {}
+            "server" if directive.block.is_some() => {
+                let body = flatten(directive.children(), prefix, depth);
+                servers.push(server_info(file, directive.line, &body));
+            }
+            _ if directive.block.is_some() => {
+                walk(directive.children(), file, prefix, in_stream, depth, servers)
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Server body with `include`d snippets inlined
+fn flatten(directives: &[Directive], prefix: &Path, depth: usize) -> Vec<Directive> {
+    let mut flat = Vec::new();
+    for directive in directives {
+        if directive.name == "include" && depth < MAX_INCLUDE_DEPTH {
+            for path in directive.arg(0).map(|p| expand_include(prefix, p)).unwrap_or_default() {
+                if let Some(included) = read_config(&path) {
+                    flat.extend(flatten(&included, prefix, depth + 1));
+                }
+            }
+        } else {
+            flat.push(directive.clone());
+        }
+    }
+    flat
+}
+
+fn server_info(file: &str, line: usize, body: &[Directive]) -> ServerInfo {
+    let mut info = ServerInfo {
+        file: file.to_string(),
+        line,
+        names: Vec::new(),
+        ports: Vec::new(),
+        default_ports: Vec::new(),
+    };
+
+    for directive in body {
+        match directive.name.as_str() {
+            "server_name" => info.names.extend(directive.args.iter().cloned()),
+            "listen" => {
+                let port = match directive.arg(0).and_then(nginx_parser::listen_port) {
+                    Some(port) => port,
+                    None => continue,
+                };
+                if !info.ports.contains(&port) {
+                    info.ports.push(port);
+                }
+                let is_default = directive.args[1..]
+                    .iter()
+                    .any(|param| param == "default_server" || param == "default");
+                if is_default && !info.default_ports.contains(&port) {
+                    info.default_ports.push(port);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // A server without `listen` listens on port 80
+    if info.ports.is_empty() {
+        info.ports.push(80);
+    }
+
+    info
+}
+
+/// Resolve an include pattern; only the last path component may contain `*`
+fn expand_include(prefix: &Path, pattern: &str) -> Vec<PathBuf> {
+    let path = prefix.join(pattern);
+    let file_pattern = match path.file_name() {
+        Some(name) => name.to_string_lossy().to_string(),
+        None => return Vec::new(),
+    };
+
+    if !file_pattern.contains('*') {
+        return vec![path];
+    }
+
+    let dir = path.parent().unwrap_or(prefix);
+    let mut matches: Vec<PathBuf> = fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter(|entry| wildcard_match(&file_pattern, &entry.file_name().to_string_lossy()))
+                .map(|entry| entry.path())
+                .filter(|path| path.is_file())
+                .collect()
+        })
+        .unwrap_or_default();
+    matches.sort();
+    matches
+}
+
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let parts: Vec<&str> = pattern.split('*').collect();
+    let (first, last) = (parts[0], parts[parts.len() - 1]);
+
+    if !name.starts_with(first) || !name[first.len()..].ends_with(last) {
+        return false;
+    }
+
+    let mut rest = &name[first.len()..name.len() - last.len()];
+    for part in &parts[1..parts.len() - 1] {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+/// Duplicate `server_name`/port pairs and ports with more than one default server
+pub fn find_conflicts(servers: &[ServerInfo]) -> Vec<Conflict> {
+    let mut by_name: BTreeMap<(String, u16), Vec<&ServerInfo>> = BTreeMap::new();
+    let mut by_default: BTreeMap<u16, Vec<&ServerInfo>> = BTreeMap::new();
+
+    for server in servers {
+        for port in &server.ports {
+            for name in server.names.iter().filter(|name| !name.is_empty() && *name != "_") {
+                by_name.entry((name.to_lowercase(), *port)).or_default().push(server);
+            }
+        }
+        for port in &server.default_ports {
+            by_default.entry(*port).or_default().push(server);
+        }
+    }
+
+    let mut conflicts = Vec::new();
+
+    for ((name, port), found) in by_name {
+        if found.len() > 1 {
+            conflicts.push(Conflict {
+                kind: ConflictKind::DuplicateName,
+                description: format!("server_name {} on port {} is declared {} times", name, port, found.len()),
+                servers: found.into_iter().cloned().collect(),
+            });
+        }
+    }
+
+    for (port, found) in by_default {
+        if found.len() > 1 {
+            conflicts.push(Conflict {
+                kind: ConflictKind::DuplicateDefault,
+                description: format!("port {} has {} default servers", port, found.len()),
+                servers: found.into_iter().cloned().collect(),
+            });
+        }
+    }
+
+    conflicts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn server(file: &str, names: &[&str], ports: &[u16], default_ports: &[u16]) -> ServerInfo {
+        ServerInfo {
+            file: file.to_string(),
+            line: 1,
+            names: names.iter().map(|n| n.to_string()).collect(),
+            ports: ports.to_vec(),
+            default_ports: default_ports.to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_find_conflicts() {
+        let servers = vec![
+            server("/etc/nginx/sites-enabled/old", &["app.test"], &[443], &[443]),
+            server("/etc/nginx/sites-enabled/app.test", &["APP.test"], &[80, 443], &[]),
+            server("/etc/nginx/conf.d/default.conf", &["_"], &[80, 443], &[443]),
+        ];
+
+        let conflicts = find_conflicts(&servers);
+        assert_eq!(conflicts.len(), 2);
+        assert_eq!(conflicts[0].description, "server_name app.test on port 443 is declared 2 times");
+        assert_eq!(conflicts[1].description, "port 443 has 2 default servers");
+    }
+
+    #[test]
+    fn test_server_info_and_wildcards() {
+        let directives =
+            nginx_parser::parse("server {\n    listen [::]:8443 ssl default_server;\n    server_name a.test;\n}\n")
+                .unwrap();
+        let info = server_info("x", 1, directives[0].children());
+        assert_eq!(info.ports, vec![8443]);
+        assert_eq!(info.default_ports, vec![8443]);
+
+        assert!(wildcard_match("*.conf", "localstacker.conf"));
+        assert!(!wildcard_match("*.conf", "localstacker.conf.disabled"));
+        assert!(wildcard_match("*", "app.test"));
+    }
+}
@@ -0,0 +1,277 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use crate::config::Settings;
+use crate::core::nginx_parser::{self, Directive};
+use crate::core::{NginxCapabilities, ReloadStrategy};
+use crate::utils::log_verbose;
+
+static LAYOUT: OnceLock<NginxLayout> = OnceLock::new();
+
+/// How sites are enabled
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum LayoutMode {
+    /// Debian/Ubuntu: configs in sites-available, symlinked into sites-enabled
+    #[serde(rename = "sites-enabled")]
+    SitesEnabled,
+    /// RHEL/Fedora/Arch/Alpine: `*.conf` files in one directory, disabled by
+    /// renaming them to `*.conf.disabled`
+    #[serde(rename = "conf.d")]
+    ConfD,
+}
+
+/// Path overrides from the `[nginx]` table of the global config file
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct NginxPathSettings {
+    pub layout: Option<LayoutMode>,
+    /// Main nginx.conf
+    pub conf_path: Option<String>,
+    /// Where site configs are written (the conf.d directory in conf.d mode)
+    pub sites_available: Option<String>,
+    /// Where enabled sites are linked (unused in conf.d mode)
+    pub sites_enabled: Option<String>,
+    pub log_dir: Option<String>,
+    /// nginx's pid file, used to check it's running and for `reload = "pid-file"`
+    pub pid_file: Option<String>,
+    /// How to reload and start nginx; detected when unset
+    pub reload: Option<ReloadStrategy>,
+}
+
+/// Where this host's nginx keeps its configuration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NginxLayout {
+    pub mode: LayoutMode,
+    pub main_config: String,
+    /// Directory of the main config; localstacker's own files live here
+    pub conf_dir: String,
+    pub sites_available: String,
+    pub sites_enabled: String,
+    pub log_dir: String,
+    pub pid_file: String,
+}
+
+impl Default for NginxLayout {
+    /// The Debian/Ubuntu layout
+    fn default() -> Self {
+        NginxLayout {
+            mode: LayoutMode::SitesEnabled,
+            main_config: "/etc/nginx/nginx.conf".to_string(),
+            conf_dir: "/etc/nginx".to_string(),
+            sites_available: "/etc/nginx/sites-available".to_string(),
+            sites_enabled: "/etc/nginx/sites-enabled".to_string(),
+            log_dir: "/var/log/nginx".to_string(),
+            pid_file: "/run/nginx.pid".to_string(),
+        }
+    }
+}
+
+impl NginxLayout {
+    /// Layout of this host, detected once. Tests always get the default layout.
+    pub fn global() -> &'static NginxLayout {
+        LAYOUT.get_or_init(|| {
+            if cfg!(test) {
+                return NginxLayout::default();
+            }
+            let layout = Self::detect(&NginxCapabilities::detect(), &Settings::global().nginx);
+            log_verbose(&format!("Using nginx layout: {:?}", layout));
+            layout
+        })
+    }
+
+    /// Work out the layout from `nginx -V`, the main config's include
+    /// directives and what exists on disk; settings win over detection
+    pub fn detect(caps: &NginxCapabilities, settings: &NginxPathSettings) -> Self {
+        let main_config = settings
+            .conf_path
+            .clone()
+            .or_else(|| caps.configure_arg("--conf-path").map(str::to_string))
+            .or_else(|| {
+                caps.configure_arg("--prefix")
+                    .map(|prefix| format!("{}/conf/nginx.conf", prefix.trim_end_matches('/')))
+            })
+            .unwrap_or_else(|| NginxLayout::default().main_config);
+
+        let conf_dir = parent(&main_config);
+
+        let log_dir = settings
+            .log_dir
+            .clone()
+            .or_else(|| caps.configure_arg("--error-log-path").map(parent))
+            .unwrap_or_else(|| NginxLayout::default().log_dir);
+
+        let directives = fs::read_to_string(&main_config)
+            .ok()
+            .and_then(|text| nginx_parser::parse(&text).ok())
+            .unwrap_or_default();
+        let includes = include_patterns(&directives);
+
+        let pid_file = settings
+            .pid_file
+            .clone()
+            .or_else(|| {
+                directives
+                    .iter()
+                    .find(|directive| directive.name == "pid")
+                    .and_then(|directive| directive.arg(0))
+                    .map(str::to_string)
+            })
+            .or_else(|| caps.configure_arg("--pid-path").map(str::to_string))
+            .unwrap_or_else(|| NginxLayout::default().pid_file);
+
+        let mut layout = Self::from_includes(main_config, conf_dir, log_dir, &includes, settings);
+        layout.pid_file = pid_file;
+        layout
+    }
+
+    fn from_includes(
+        main_config: String,
+        conf_dir: String,
+        log_dir: String,
+        includes: &[String],
+        settings: &NginxPathSettings,
+    ) -> Self {
+        let absolute = |pattern: &str| {
+            if pattern.starts_with('/') {
+                pattern.to_string()
+            } else {
+                format!("{}/{}", conf_dir, pattern)
+            }
+        };
+
+        let sites_enabled_include = includes
+            .iter()
+            .find(|pattern| pattern.contains("sites-enabled"))
+            .map(|pattern| parent(&absolute(pattern)));
+        let conf_d_include = includes
+            .iter()
+            .find(|pattern| pattern.ends_with("/*.conf") && !pattern.contains("modules"))
+            .map(|pattern| parent(&absolute(pattern)));
+
+        let detected_mode = if sites_enabled_include.is_some() {
+            LayoutMode::SitesEnabled
+        } else if conf_d_include.is_some() {
+            LayoutMode::ConfD
+        } else if Path::new(&conf_dir).join("sites-enabled").is_dir() {
+            LayoutMode::SitesEnabled
+        } else {
+            LayoutMode::ConfD
+        };
+        let mode = settings.layout.unwrap_or(detected_mode);
+
+        let (sites_available, sites_enabled) = match mode {
+            LayoutMode::SitesEnabled => {
+                let enabled = settings.sites_enabled.clone().unwrap_or_else(|| {
+                    sites_enabled_include.unwrap_or_else(|| format!("{}/sites-enabled", conf_dir))
+                });
+                let available = settings
+                    .sites_available
+                    .clone()
+                    .unwrap_or_else(|| format!("{}/sites-available", parent(&enabled)));
+                (available, enabled)
+            }
+            LayoutMode::ConfD => {
+                // Enabled and available are the same directory
+                let dir = settings.sites_available.clone().unwrap_or_else(|| {
+                    conf_d_include.unwrap_or_else(|| format!("{}/conf.d", conf_dir))
+                });
+                (dir.clone(), dir)
+            }
+        };
+
+        NginxLayout {
+            mode,
+            main_config,
+            conf_dir,
+            sites_available,
+            sites_enabled,
+            log_dir,
+            pid_file: NginxLayout::default().pid_file,
+        }
+    }
+
+    /// Directory for the managed http-level include. Debian's conf.d is
+    /// included inside `http {}`; in conf.d mode it's the sites directory.
+    pub fn shared_dir(&self) -> String {
+        match self.mode {
+            LayoutMode::SitesEnabled => format!("{}/conf.d", self.conf_dir),
+            LayoutMode::ConfD => self.sites_available.clone(),
+        }
+    }
+}
+
+/// Include patterns reachable from the main config without following them
+fn include_patterns(directives: &[Directive]) -> Vec<String> {
+    let mut patterns = Vec::new();
+    for directive in directives {
+        if directive.name == "include" {
+            if let Some(pattern) = directive.arg(0) {
+                patterns.push(pattern.to_string());
+            }
+        }
+        // Only the http context matters for sites
+        if directive.name != "stream" && directive.name != "events" {
+            patterns.extend(include_patterns(directive.children()));
+        }
+    }
+    patterns
+}
+
+fn parent(path: &str) -> String {
+    Path::new(path)
+        .parent()
+        .map(|p| p.to_string_lossy().to_string())
+        .unwrap_or_else(|| "/".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layout(includes: &[&str], settings: &NginxPathSettings) -> NginxLayout {
+        let includes: Vec<String> = includes.iter().map(|i| i.to_string()).collect();
+        NginxLayout::from_includes(
+            "/etc/nginx/nginx.conf".to_string(),
+            "/etc/nginx".to_string(),
+            "/var/log/nginx".to_string(),
+            &includes,
+            settings,
+        )
+    }
+
+    #[test]
+    fn test_detect_debian_and_rhel_layouts() {
+        let debian = layout(
+            &["/etc/nginx/modules-enabled/*.conf", "/etc/nginx/conf.d/*.conf", "/etc/nginx/sites-enabled/*"],
+            &NginxPathSettings::default(),
+        );
+        assert_eq!(debian, NginxLayout::default());
+
+        let rhel = layout(
+            &["/usr/share/nginx/modules/*.conf", "/etc/nginx/conf.d/*.conf"],
+            &NginxPathSettings::default(),
+        );
+        assert_eq!(rhel.mode, LayoutMode::ConfD);
+        assert_eq!(rhel.sites_available, "/etc/nginx/conf.d");
+        assert_eq!(rhel.shared_dir(), "/etc/nginx/conf.d");
+
+        // Alpine uses a relative http.d include
+        let alpine = layout(&["http.d/*.conf"], &NginxPathSettings::default());
+        assert_eq!(alpine.sites_enabled, "/etc/nginx/http.d");
+    }
+
+    #[test]
+    fn test_settings_override_detection() {
+        let settings = NginxPathSettings {
+            layout: Some(LayoutMode::ConfD),
+            sites_available: Some("/opt/nginx/servers".to_string()),
+            ..Default::default()
+        };
+        let custom = layout(&["/etc/nginx/sites-enabled/*"], &settings);
+        assert_eq!(custom.mode, LayoutMode::ConfD);
+        assert_eq!(custom.sites_available, "/opt/nginx/servers");
+        assert_eq!(custom.sites_enabled, "/opt/nginx/servers");
+    }
+}
@@ -0,0 +1,380 @@
+use std::fmt;
+
+use crate::core::nginx_parser::{self, Directive};
+use crate::error::Result;
+
+/// Where a config file ends up being included
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LintContext {
+    /// A complete `nginx.conf`
+    Main,
+    /// A site included inside `http {}` (sites-enabled, conf.d)
+    Http,
+    /// A TCP site included inside `stream {}`
+    Stream,
+}
+
+impl LintContext {
+    /// `Main` for a file with top-level `http`/`events`/`stream` blocks,
+    /// otherwise a site in `http`
+    pub fn detect(text: &str) -> Self {
+        let main = nginx_parser::parse(text).is_ok_and(|directives| {
+            directives
+                .iter()
+                .any(|d| matches!(d.name.as_str(), "http" | "events" | "stream"))
+        });
+        if main {
+            LintContext::Main
+        } else {
+            LintContext::Http
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            LintContext::Main => "main",
+            LintContext::Http => "http",
+            LintContext::Stream => "stream",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub severity: Severity,
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+const ANY: &[&str] = &[
+    "main", "events", "http", "server", "location", "if", "limit_except", "upstream",
+    "stream", "stream_server", "stream_upstream",
+];
+const HSL: &[&str] = &["http", "server", "location"];
+const HSL_IF: &[&str] = &["http", "server", "location", "if"];
+const HS: &[&str] = &["http", "server"];
+const SSL: &[&str] = &["http", "server", "stream", "stream_server"];
+const STREAM_HSL: &[&str] = &["http", "server", "location", "stream", "stream_server"];
+const REWRITE: &[&str] = &["server", "location", "if"];
+const LOCATION_IF: &[&str] = &["location", "if"];
+const UPSTREAM: &[&str] = &["upstream", "stream_upstream"];
+
+/// Blocks whose bodies are data (`map` pairs, MIME types) rather than directives
+const DATA_BLOCKS: &[&str] = &["map", "types", "geo", "split_clients", "charset_map", "match"];
+
+/// Contexts a directive is allowed in, or `None` for directives we don't
+/// know (usually from third-party modules)
+fn allowed_contexts(name: &str) -> Option<&'static [&'static str]> {
+    let contexts: &[&str] = match name {
+        // Blocks
+        "events" | "http" | "stream" => &["main"],
+        "server" => &["http", "stream", "upstream", "stream_upstream"],
+        "location" => &["server", "location"],
+        "upstream" => &["http", "stream"],
+        "if" => &["server", "location"],
+        "limit_except" => &["location"],
+        "map" | "geo" | "split_clients" => &["http", "stream"],
+        "types" => HSL,
+
+        // Core
+        "include" => ANY,
+        "user" | "worker_processes" | "pid" | "load_module" | "worker_rlimit_nofile"
+        | "daemon" | "env" => &["main"],
+        "worker_connections" | "use" | "multi_accept" => &["events"],
+        "error_log" => &["main", "http", "server", "location", "stream", "stream_server"],
+        "access_log" => &["http", "server", "location", "if", "limit_except", "stream", "stream_server"],
+        "log_format" => &["http", "stream"],
+        "listen" => &["server", "stream_server"],
+        "server_name" => &["server"],
+        "root" => HSL_IF,
+        "alias" | "internal" => &["location"],
+        "index" | "autoindex" | "charset" | "default_type" | "etag" | "satisfy"
+        | "client_max_body_size" | "client_body_buffer_size" | "keepalive_timeout"
+        | "sendfile" | "tcp_nopush" | "tcp_nodelay" | "server_tokens" => HSL_IF,
+        "try_files" => &["server", "location"],
+        "error_page" => HSL_IF,
+        "large_client_header_buffers" | "underscores_in_headers" => HS,
+        "server_names_hash_bucket_size" | "server_names_hash_max_size"
+        | "types_hash_max_size" | "variables_hash_max_size" => &["http"],
+        "resolver" | "resolver_timeout" => STREAM_HSL,
+
+        // Rewrite module
+        "return" => &["server", "location", "if", "stream_server"],
+        "rewrite" | "break" => REWRITE,
+        "set" => &["server", "location", "if", "stream_server"],
+
+        // Headers
+        "add_header" | "add_trailer" | "expires" => HSL_IF,
+
+        // Access and auth
+        "allow" | "deny" => &["http", "server", "location", "limit_except", "stream", "stream_server"],
+        "auth_basic" | "auth_basic_user_file" => &["http", "server", "location", "limit_except"],
+        "auth_request" | "auth_request_set" => HSL,
+        "limit_req" | "limit_req_status" | "limit_req_log_level" => HSL,
+        "limit_req_zone" => &["http"],
+        "limit_conn" => STREAM_HSL,
+        "limit_conn_zone" => &["http", "stream"],
+
+        // Compression
+        "gzip" => HSL_IF,
+        "gzip_types" | "gzip_min_length" | "gzip_vary" | "gzip_proxied" | "gzip_comp_level"
+        | "gzip_static" | "brotli_types" | "brotli_comp_level" | "brotli_static" => HSL,
+        "brotli" => HSL_IF,
+
+        // TLS
+        "ssl_certificate" | "ssl_certificate_key" | "ssl_protocols" | "ssl_ciphers"
+        | "ssl_prefer_server_ciphers" | "ssl_session_cache" | "ssl_session_timeout"
+        | "ssl_session_tickets" | "ssl_dhparam" | "ssl_ecdh_curve" | "ssl_early_data"
+        | "ssl_trusted_certificate" | "ssl_verify_client" | "ssl_client_certificate" => SSL,
+        "ssl_stapling" | "ssl_stapling_verify" | "ssl" | "http2" | "http3" | "quic_retry"
+        | "quic_gso" => HS,
+        "ssl_preread" => &["stream", "stream_server"],
+
+        // Proxying
+        "proxy_pass" => &["location", "if", "limit_except", "stream_server"],
+        "proxy_set_header" | "proxy_http_version" | "proxy_read_timeout" | "proxy_send_timeout"
+        | "proxy_redirect" | "proxy_hide_header" | "proxy_pass_header" | "proxy_intercept_errors"
+        | "proxy_buffering" | "proxy_buffers" | "proxy_busy_buffers_size"
+        | "proxy_request_buffering" | "proxy_cache" | "proxy_cache_key" | "proxy_cache_valid"
+        | "proxy_cache_bypass" | "proxy_no_cache" | "proxy_cache_use_stale"
+        | "proxy_cache_lock" | "proxy_cache_methods" | "proxy_ignore_headers"
+        | "proxy_ssl_server_name" | "proxy_ssl_verify" | "proxy_next_upstream" => HSL,
+        "proxy_buffer_size" | "proxy_connect_timeout" | "proxy_ssl" | "proxy_ssl_name" => STREAM_HSL,
+        "proxy_timeout" | "proxy_protocol" => &["stream", "stream_server"],
+        "proxy_cache_path" => &["http"],
+        "fastcgi_pass" | "grpc_pass" | "uwsgi_pass" => LOCATION_IF,
+        "fastcgi_index" | "fastcgi_param" | "fastcgi_read_timeout" | "fastcgi_buffers"
+        | "fastcgi_buffer_size" | "fastcgi_intercept_errors" | "grpc_set_header"
+        | "grpc_read_timeout" | "grpc_send_timeout" | "uwsgi_param" => HSL,
+        "fastcgi_split_path_info" => &["location"],
+
+        // Upstreams
+        "keepalive" | "least_conn" | "ip_hash" | "hash" | "zone" | "random" => UPSTREAM,
+
+        _ => return None,
+    };
+    Some(contexts)
+}
+
+/// Structural checks on an nginx config before it's written: directive
+/// contexts, certificates for TLS listeners and, when `domain` is given,
+/// that every server block answers for it. Syntax errors (unbalanced
+/// braces, missing `;`) are returned as `Err`.
+pub fn lint(text: &str, context: LintContext, domain: Option<&str>) -> Result<Vec<Finding>> {
+    let directives = nginx_parser::parse(text)?;
+    let mut findings = Vec::new();
+
+    check_contexts(&directives, context.name(), &mut findings);
+
+    // Certificates set next to the server blocks are inherited
+    let inherited_certs = directives.iter().any(|d| d.name == "ssl_certificate");
+    let has_include = directives.iter().any(|d| d.name == "include");
+    for (server, stream) in servers(&directives, context) {
+        check_tls(server, inherited_certs, has_include, &mut findings);
+        // Stream servers route by port, not by name
+        if let (Some(domain), false) = (domain, stream) {
+            check_server_name(server, domain, &mut findings);
+        }
+    }
+
+    findings.sort_by_key(|finding| finding.line);
+    Ok(findings)
+}
+
+fn check_contexts(directives: &[Directive], context: &str, findings: &mut Vec<Finding>) {
+    for directive in directives {
+        match allowed_contexts(&directive.name) {
+            Some(allowed) if !allowed.contains(&context) => findings.push(Finding {
+                severity: Severity::Error,
+                line: directive.line,
+                message: format!(
+                    "\"{}\" is not allowed in the {} context",
+                    directive.name,
+                    context.replace("stream_", "stream ")
+                ),
+            }),
+            Some(_) => {}
+            None => findings.push(Finding {
+                severity: Severity::Warning,
+                line: directive.line,
+                message: format!("unknown directive \"{}\"", directive.name),
+            }),
+        }
+
+        if directive.block.is_some() && !DATA_BLOCKS.contains(&directive.name.as_str()) {
+            let inner = match (context, directive.name.as_str()) {
+                ("stream", "server") => "stream_server",
+                ("stream", "upstream") => "stream_upstream",
+                (_, name) => name,
+            };
+            check_contexts(directive.children(), inner, findings);
+        }
+    }
+}
+
+fn stream_servers(directives: &[Directive]) -> Vec<&Directive> {
+    directives
+        .iter()
+        .filter(|d| d.name == "server" && d.block.is_some())
+        .collect()
+}
+
+/// Server blocks of the file and whether they are stream servers
+fn servers(directives: &[Directive], context: LintContext) -> Vec<(&Directive, bool)> {
+    match context {
+        LintContext::Stream => stream_servers(directives).into_iter().map(|s| (s, true)).collect(),
+        LintContext::Http | LintContext::Main => {
+            let mut servers: Vec<_> = nginx_parser::server_blocks(directives)
+                .into_iter()
+                .map(|s| (s, false))
+                .collect();
+            for stream in directives.iter().filter(|d| d.name == "stream") {
+                servers.extend(stream_servers(stream.children()).into_iter().map(|s| (s, true)));
+            }
+            servers
+        }
+    }
+}
+
+fn check_tls(server: &Directive, inherited_certs: bool, has_include: bool, findings: &mut Vec<Finding>) {
+    let Some(listen) = server
+        .find("listen")
+        .find(|listen| listen.args.iter().any(|arg| arg == "ssl" || arg == "quic"))
+    else {
+        return;
+    };
+
+    let missing: Vec<&str> = ["ssl_certificate", "ssl_certificate_key"]
+        .into_iter()
+        .filter(|name| server.find(name).next().is_none())
+        .collect();
+    if missing.is_empty() || inherited_certs {
+        return;
+    }
+    let kind = listen.args.iter().find(|arg| *arg == "ssl" || *arg == "quic");
+
+    // An included snippet may well provide the certificate
+    let included = has_include || server.find("include").next().is_some();
+    findings.push(Finding {
+        severity: if included { Severity::Warning } else { Severity::Error },
+        line: listen.line,
+        message: format!(
+            "\"listen ... {}\" without {}{}",
+            kind.map_or("ssl", String::as_str),
+            missing.join(" or "),
+            if included { " (unless an include sets it)" } else { "" }
+        ),
+    });
+}
+
+fn check_server_name(server: &Directive, domain: &str, findings: &mut Vec<Finding>) {
+    let names: Vec<&str> = server
+        .find("server_name")
+        .flat_map(|d| d.args.iter().map(String::as_str))
+        .collect();
+    if names.iter().any(|name| server_name_matches(name, domain)) {
+        return;
+    }
+
+    findings.push(Finding {
+        severity: Severity::Error,
+        line: server.find("server_name").next().map_or(server.line, |d| d.line),
+        message: if names.is_empty() {
+            format!("server block has no server_name, expected {}", domain)
+        } else {
+            format!("server_name {} doesn't match {}", names.join(" "), domain)
+        },
+    });
+}
+
+/// nginx's name matching: exact, `*.example.com`, `.example.com`,
+/// `www.example.*`; regular expressions can't be checked and pass
+fn server_name_matches(name: &str, domain: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    let domain = domain.to_ascii_lowercase();
+
+    if name.starts_with('~') || name == domain {
+        return true;
+    }
+    if let Some(suffix) = name.strip_prefix('*') {
+        return domain.ends_with(suffix);
+    }
+    if let Some(base) = name.strip_prefix('.') {
+        return domain == base || domain.ends_with(&name);
+    }
+    if let Some(prefix) = name.strip_suffix('*') {
+        return domain.starts_with(prefix);
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn errors(findings: &[Finding]) -> Vec<String> {
+        findings
+            .iter()
+            .filter(|f| f.severity == Severity::Error)
+            .map(|f| f.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_lint_reports_context_tls_and_server_name() {
+        let text = r#"server {
+    listen 443 ssl;
+    server_name other.test;
+    proxy_pass http://127.0.0.1:3000;
+
+    location / {
+        listen 80;
+        more_set_headers "X-A: b";
+    }
+}
+"#;
+        let findings = lint(text, LintContext::Http, Some("app.test")).unwrap();
+        assert_eq!(
+            errors(&findings),
+            vec![
+                "line 2: \"listen ... ssl\" without ssl_certificate or ssl_certificate_key",
+                "line 3: server_name other.test doesn't match app.test",
+                "line 4: \"proxy_pass\" is not allowed in the server context",
+                "line 7: \"listen\" is not allowed in the location context",
+            ]
+        );
+        assert!(findings.iter().any(|f| f.severity == Severity::Warning
+            && f.message == "unknown directive \"more_set_headers\""));
+
+        assert!(lint("server {\n    listen 80;\n", LintContext::Http, None).is_err());
+    }
+
+    #[test]
+    fn test_server_name_patterns_and_stream_context() {
+        assert!(server_name_matches("*.app.test", "api.app.test"));
+        assert!(server_name_matches(".app.test", "app.test"));
+        assert!(server_name_matches("api.*", "api.app.test"));
+        assert!(server_name_matches("~^(?<sub>.+)\\.test$", "app.test"));
+        assert!(!server_name_matches("app.test", "www.app.test"));
+
+        let stream = "server {\n    listen 6380 ssl;\n    ssl_certificate a.pem;\n    ssl_certificate_key a-key.pem;\n    proxy_pass 127.0.0.1:6379;\n}\n";
+        assert!(lint(stream, LintContext::Stream, Some("redis.test")).unwrap().is_empty());
+        assert!(!errors(&lint(stream, LintContext::Http, None).unwrap()).is_empty());
+
+        let main = format!("events {{}}\nstream {{\n{}}}\n", stream);
+        assert_eq!(LintContext::detect(&main), LintContext::Main);
+        assert!(lint(&main, LintContext::Main, Some("redis.test")).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,271 @@
+use crate::error::{Error, Result};
+
+/// A single nginx directive, e.g. `listen 443 ssl;` or `server { ... }`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Directive {
+    pub name: String,
+    pub args: Vec<String>,
+    /// 1-based line the directive starts on
+    pub line: usize,
+    /// Contents of the `{ ... }` block, if the directive has one
+    pub block: Option<Vec<Directive>>,
+}
+
+impl Directive {
+    pub fn arg(&self, index: usize) -> Option<&str> {
+        self.args.get(index).map(String::as_str)
+    }
+
+    pub fn children(&self) -> &[Directive] {
+        self.block.as_deref().unwrap_or(&[])
+    }
+
+    /// Direct children with the given name
+    pub fn find<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Directive> {
+        self.children().iter().filter(move |d| d.name == name)
+    }
+
+    /// All descendants with the given name, depth first
+    pub fn find_all<'a>(&'a self, name: &str) -> Vec<&'a Directive> {
+        let mut found = Vec::new();
+        collect(self.children(), name, &mut found);
+        found
+    }
+}
+
+fn collect<'a>(directives: &'a [Directive], name: &str, found: &mut Vec<&'a Directive>) {
+    for directive in directives {
+        if directive.name == name {
+            found.push(directive);
+        }
+        collect(directive.children(), name, found);
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Token {
+    Word(String),
+    Semicolon,
+    Open,
+    Close,
+}
+
+/// Split nginx config text into (token, line) pairs
+fn tokenize(text: &str) -> Result<Vec<(Token, usize)>> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    let mut line = 1;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' => line += 1,
+            c if c.is_whitespace() => {}
+            '#' => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        line += 1;
+                        break;
+                    }
+                }
+            }
+            ';' => tokens.push((Token::Semicolon, line)),
+            '{' => tokens.push((Token::Open, line)),
+            '}' => tokens.push((Token::Close, line)),
+            '"' | '\'' => {
+                let start = line;
+                let mut word = String::new();
+                loop {
+                    match chars.next() {
+                        Some('\\') => {
+                            if let Some(escaped) = chars.next() {
+                                if escaped != c {
+                                    word.push('\\');
+                                }
+                                word.push(escaped);
+                            }
+                        }
+                        Some(q) if q == c => break,
+                        Some(other) => {
+                            if other == '\n' {
+                                line += 1;
+                            }
+                            word.push(other);
+                        }
+                        None => {
+                            return Err(Error::Config(format!(
+                                "line {}: unterminated quoted string",
+                                start
+                            )))
+                        }
+                    }
+                }
+                tokens.push((Token::Word(word), start));
+            }
+            _ => {
+                let mut word = String::from(c);
+                let mut in_variable = false;
+                while let Some(&next) = chars.peek() {
+                    // `${name}` is a variable, not a block
+                    if next == '{' && word.ends_with('$') {
+                        in_variable = true;
+                    } else if in_variable && next == '}' {
+                        in_variable = false;
+                    } else if !in_variable
+                        && (next.is_whitespace() || matches!(next, ';' | '{' | '}'))
+                    {
+                        break;
+                    }
+                    word.push(next);
+                    chars.next();
+                    if next == '\\' {
+                        if let Some(escaped) = chars.next() {
+                            word.push(escaped);
+                        }
+                    }
+                }
+                tokens.push((Token::Word(word), line));
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Parse nginx config text into a directive tree
+pub fn parse(text: &str) -> Result<Vec<Directive>> {
+    let tokens = tokenize(text)?;
+    let mut position = 0;
+    let directives = parse_block(&tokens, &mut position, None)?;
+    Ok(directives)
+}
+
+fn parse_block(
+    tokens: &[(Token, usize)],
+    position: &mut usize,
+    opened_at: Option<usize>,
+) -> Result<Vec<Directive>> {
+    let mut directives = Vec::new();
+
+    while *position < tokens.len() {
+        let (token, line) = &tokens[*position];
+        *position += 1;
+
+        let name = match token {
+            Token::Word(word) => word.clone(),
+            Token::Close if opened_at.is_some() => return Ok(directives),
+            Token::Close => return Err(Error::Config(format!("line {}: unexpected \"}}\"", line))),
+            Token::Semicolon => return Err(Error::Config(format!("line {}: unexpected \";\"", line))),
+            Token::Open => return Err(Error::Config(format!("line {}: unexpected \"{{\"", line))),
+        };
+
+        let mut args = Vec::new();
+        loop {
+            match tokens.get(*position) {
+                Some((Token::Word(word), _)) => {
+                    args.push(word.clone());
+                    *position += 1;
+                }
+                Some((Token::Semicolon, _)) => {
+                    *position += 1;
+                    directives.push(Directive { name, args, line: *line, block: None });
+                    break;
+                }
+                Some((Token::Open, _)) => {
+                    *position += 1;
+                    let block = parse_block(tokens, position, Some(*line))?;
+                    directives.push(Directive { name, args, line: *line, block: Some(block) });
+                    break;
+                }
+                Some((Token::Close, close_line)) => {
+                    return Err(Error::Config(format!(
+                        "line {}: directive \"{}\" is not terminated by \";\"",
+                        close_line, name
+                    )))
+                }
+                None => {
+                    return Err(Error::Config(format!(
+                        "line {}: unexpected end of file, expecting \";\" or \"}}\"",
+                        line
+                    )))
+                }
+            }
+        }
+    }
+
+    match opened_at {
+        Some(line) => Err(Error::Config(format!(
+            "line {}: block is never closed, expecting \"}}\"",
+            line
+        ))),
+        None => Ok(directives),
+    }
+}
+
+/// Port of a `listen` address: `443`, `*:80`, `[::]:443`, `127.0.0.1:8443`
+pub fn listen_port(address: &str) -> Option<u16> {
+    if address.starts_with("unix:") {
+        return None;
+    }
+    address.rsplit(':').next()?.parse().ok()
+}
+
+/// `server` blocks at the top level or inside an `http` block
+pub fn server_blocks(directives: &[Directive]) -> Vec<&Directive> {
+    let mut servers = Vec::new();
+    for directive in directives {
+        match directive.name.as_str() {
+            "server" if directive.block.is_some() => servers.push(directive),
+            "http" => servers.extend(server_blocks(directive.children())),
+            _ => {}
+        }
+    }
+    servers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SITE: &str = r#"# hand written
+server {
+    listen 443 ssl;
+    server_name app.test www.app.test;
+    ssl_certificate /etc/ssl/app.pem;
+
+    location / {
+        proxy_pass http://127.0.0.1:3000;
+        add_header X-Note "a; b";
+        set $x "${host}";
+    }
+}
+"#;
+
+    #[test]
+    fn test_parse_server_block() {
+        let directives = parse(SITE).unwrap();
+        let servers = server_blocks(&directives);
+        assert_eq!(servers.len(), 1);
+
+        let server = servers[0];
+        assert_eq!(server.line, 2);
+        let names = server.find("server_name").next().unwrap();
+        assert_eq!(names.args, vec!["app.test", "www.app.test"]);
+
+        let proxy = server.find_all("proxy_pass");
+        assert_eq!(proxy[0].arg(0), Some("http://127.0.0.1:3000"));
+        assert_eq!(proxy[0].line, 8);
+
+        let header = &server.find_all("add_header")[0];
+        assert_eq!(header.args, vec!["X-Note", "a; b"]);
+        assert_eq!(server.find_all("set")[0].args, vec!["$x", "${host}"]);
+    }
+
+    #[test]
+    fn test_parse_errors_name_the_line() {
+        let err = parse("server {\n    listen 80\n}\n").unwrap_err();
+        assert!(err.to_string().contains("line 3"));
+
+        let err = parse("server {\n    listen 80;\n").unwrap_err();
+        assert!(err.to_string().contains("line 1"));
+    }
+}
@@ -0,0 +1,181 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::OnceLock;
+
+use crate::config::Settings;
+use crate::core::NginxLayout;
+use crate::error::Result;
+use crate::utils::{command_exists, execute_command, is_dry_run, log_info, log_verbose};
+
+static STRATEGY: OnceLock<ReloadStrategy> = OnceLock::new();
+
+/// How nginx is reloaded and started on this host
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ReloadStrategy {
+    /// `systemctl reload nginx`
+    Systemctl,
+    /// SysV init script: `service nginx reload`
+    Service,
+    /// OpenRC: `rc-service nginx reload`
+    RcService,
+    /// `nginx -s reload`, for containers and WSL without an init system
+    Signal,
+    /// SIGHUP to the pid in nginx's pid file
+    PidFile,
+}
+
+impl fmt::Display for ReloadStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ReloadStrategy::Systemctl => "systemctl",
+            ReloadStrategy::Service => "service",
+            ReloadStrategy::RcService => "rc-service",
+            ReloadStrategy::Signal => "signal",
+            ReloadStrategy::PidFile => "pid-file",
+        };
+        f.write_str(name)
+    }
+}
+
+impl ReloadStrategy {
+    /// The configured strategy, or the detected one
+    pub fn global() -> ReloadStrategy {
+        *STRATEGY.get_or_init(|| {
+            let strategy = Settings::global().nginx.reload.unwrap_or_else(Self::detect);
+            log_verbose(&format!("Using nginx reload strategy: {}", strategy));
+            strategy
+        })
+    }
+
+    /// Whether the strategy comes from the settings file
+    pub fn is_configured() -> bool {
+        Settings::global().nginx.reload.is_some()
+    }
+
+    pub fn detect() -> Self {
+        Self::choose(
+            Path::new("/run/systemd/system").is_dir() && command_exists("systemctl"),
+            Path::new("/run/openrc").is_dir() && command_exists("rc-service"),
+            Path::new("/etc/init.d/nginx").exists() && command_exists("service"),
+        )
+    }
+
+    /// A booted init system wins; otherwise talk to nginx directly
+    fn choose(systemd: bool, openrc: bool, init_script: bool) -> Self {
+        if systemd {
+            ReloadStrategy::Systemctl
+        } else if openrc {
+            ReloadStrategy::RcService
+        } else if init_script {
+            ReloadStrategy::Service
+        } else {
+            ReloadStrategy::Signal
+        }
+    }
+
+    pub fn is_running(self) -> bool {
+        let status = |program: &str, args: &[&str]| {
+            Command::new(program)
+                .args(args)
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status()
+                .map(|status| status.success())
+                .unwrap_or(false)
+        };
+
+        match self {
+            ReloadStrategy::Systemctl => status("systemctl", &["is-active", "--quiet", "nginx"]),
+            ReloadStrategy::Service => status("service", &["nginx", "status"]),
+            ReloadStrategy::RcService => status("rc-service", &["nginx", "status"]),
+            ReloadStrategy::Signal | ReloadStrategy::PidFile => running_pid().is_some(),
+        }
+    }
+
+    /// Reload nginx, starting it if it isn't running
+    pub fn reload(self) -> Result<()> {
+        if !is_dry_run() && !self.is_running() {
+            log_info("nginx is not running, starting it...");
+            return self.start();
+        }
+
+        match self {
+            ReloadStrategy::Systemctl => {
+                execute_command("systemctl", &["reload", "nginx"], "Reload nginx")?
+            }
+            ReloadStrategy::Service => {
+                execute_command("service", &["nginx", "reload"], "Reload nginx")?
+            }
+            ReloadStrategy::RcService => {
+                execute_command("rc-service", &["nginx", "reload"], "Reload nginx")?
+            }
+            ReloadStrategy::Signal => execute_command("nginx", &["-s", "reload"], "Reload nginx")?,
+            ReloadStrategy::PidFile => {
+                // Only missing in dry-run mode, where nothing is sent
+                let pid = running_pid()
+                    .map(|pid| pid.to_string())
+                    .unwrap_or_else(|| "<pid>".to_string());
+                execute_command("kill", &["-HUP", &pid], "Reload nginx")?
+            }
+        };
+
+        Ok(())
+    }
+
+    pub fn start(self) -> Result<()> {
+        match self {
+            ReloadStrategy::Systemctl => {
+                execute_command("systemctl", &["start", "nginx"], "Start nginx")?
+            }
+            ReloadStrategy::Service => execute_command("service", &["nginx", "start"], "Start nginx")?,
+            ReloadStrategy::RcService => {
+                execute_command("rc-service", &["nginx", "start"], "Start nginx")?
+            }
+            // nginx daemonizes itself
+            ReloadStrategy::Signal | ReloadStrategy::PidFile => {
+                execute_command("nginx", &[], "Start nginx")?
+            }
+        };
+
+        Ok(())
+    }
+}
+
+/// Pid of the running nginx master, from its pid file
+pub fn running_pid() -> Option<u32> {
+    let contents = fs::read_to_string(&NginxLayout::global().pid_file).ok()?;
+    let pid = parse_pid(&contents)?;
+    Path::new(&format!("/proc/{}", pid)).exists().then_some(pid)
+}
+
+fn parse_pid(contents: &str) -> Option<u32> {
+    contents.trim().parse().ok().filter(|pid| *pid > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_choose_strategy() {
+        assert_eq!(ReloadStrategy::choose(true, false, true), ReloadStrategy::Systemctl);
+        assert_eq!(ReloadStrategy::choose(false, true, true), ReloadStrategy::RcService);
+        assert_eq!(ReloadStrategy::choose(false, false, true), ReloadStrategy::Service);
+        assert_eq!(ReloadStrategy::choose(false, false, false), ReloadStrategy::Signal);
+    }
+
+    #[test]
+    fn test_parse_strategy_and_pid() {
+        let settings: crate::core::NginxPathSettings = toml::from_str("reload = \"pid-file\"").unwrap();
+        assert_eq!(settings.reload, Some(ReloadStrategy::PidFile));
+        assert_eq!(ReloadStrategy::RcService.to_string(), "rc-service");
+
+        assert_eq!(parse_pid("1234\n"), Some(1234));
+        assert_eq!(parse_pid(""), None);
+        assert_eq!(parse_pid("0"), None);
+    }
+}
@@ -0,0 +1,578 @@
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use bytes::Bytes;
+use http_body_util::combinators::BoxBody;
+use http_body_util::{BodyExt, Full};
+use hyper::body::Incoming;
+use hyper::header::{self, HeaderMap, HeaderName, HeaderValue};
+use hyper::service::service_fn;
+use hyper::{Request, Response, StatusCode, Uri, Version};
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::client::legacy::Client;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto;
+use std::collections::{BTreeSet, HashMap};
+use std::convert::Infallible;
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+use tokio_rustls::rustls::server::{ClientHello, ResolvesServerCert};
+use tokio_rustls::rustls::sign::CertifiedKey;
+use tokio_rustls::rustls::{self, ServerConfig};
+use tokio_rustls::TlsAcceptor;
+
+use crate::config::{AccessPolicy, ConfigManager, DomainConfig, Paths, ServerKind, SiteKind};
+use crate::core::{error_pages, HtpasswdFile};
+use crate::error::{Error, Result};
+use crate::utils::{log_info, log_success, log_verbose, log_warning, parse_header};
+
+/// How often domains.json is checked for changes
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Connection-level headers that must not be forwarded
+const HOP_BY_HOP: &[&str] = &[
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+type Body = BoxBody<Bytes, hyper::Error>;
+
+/// Sites served by the built-in proxy and their certificates
+#[derive(Debug, Default)]
+pub struct Routes {
+    sites: HashMap<String, DomainConfig>,
+    certs: HashMap<String, Arc<CertifiedKey>>,
+}
+
+impl Routes {
+    /// Routes for every built-in proxy site; sites whose certificate
+    /// doesn't load are still routed but fail the TLS handshake
+    pub fn from_sites<'a>(sites: impl IntoIterator<Item = &'a DomainConfig>) -> Self {
+        let mut routes = Routes::default();
+        for site in sites {
+            if site.server != ServerKind::Builtin || site.kind == SiteKind::Tcp {
+                continue;
+            }
+            match load_certified_key(&site.ssl_cert_path, &site.ssl_key_path) {
+                Ok(key) => {
+                    routes.certs.insert(site.domain.clone(), Arc::new(key));
+                }
+                Err(e) => log_warning(&format!("No certificate for {}: {}", site.domain, e)),
+            }
+            routes.sites.insert(site.domain.clone(), site.clone());
+        }
+        routes
+    }
+
+    fn load() -> Result<Self> {
+        let config_manager = ConfigManager::new()?;
+        Ok(Self::from_sites(config_manager.list_domains()))
+    }
+
+    /// Ports to listen on: `(port, tls)`
+    fn listeners(&self) -> BTreeSet<(u16, bool)> {
+        let mut listeners = BTreeSet::new();
+        for site in self.sites.values() {
+            listeners.insert((site.effective_http_port(), false));
+            listeners.insert((site.effective_https_port(), true));
+        }
+        listeners
+    }
+
+    /// The site answering for a `Host` (or `:authority`) on this listener
+    fn site(&self, host: &str, port: u16, tls: bool) -> Option<&DomainConfig> {
+        let name = host.rsplit_once(':').map_or(host, |(name, _)| name).to_ascii_lowercase();
+        self.sites.get(&name).filter(|site| {
+            let expected = if tls { site.effective_https_port() } else { site.effective_http_port() };
+            expected == port
+        })
+    }
+}
+
+/// Load a PEM certificate chain and private key for rustls
+pub fn load_certified_key(cert_path: &str, key_path: &str) -> Result<CertifiedKey> {
+    let certs = rustls_pemfile::certs(&mut BufReader::new(File::open(cert_path)?))
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    if certs.is_empty() {
+        return Err(Error::Config(format!("No certificate found in {}", cert_path)));
+    }
+
+    let key = rustls_pemfile::private_key(&mut BufReader::new(File::open(key_path)?))?
+        .ok_or_else(|| Error::Config(format!("No private key found in {}", key_path)))?;
+    let signing_key = rustls::crypto::ring::sign::any_supported_type(&key)
+        .map_err(|e| Error::Config(format!("Unsupported private key in {}: {}", key_path, e)))?;
+
+    Ok(CertifiedKey::new(certs, signing_key))
+}
+
+type SharedRoutes = Arc<RwLock<Arc<Routes>>>;
+
+fn current(routes: &SharedRoutes) -> Arc<Routes> {
+    routes.read().map(|routes| routes.clone()).unwrap_or_default()
+}
+
+/// Picks the certificate by SNI from the current routes
+#[derive(Debug)]
+struct SniResolver(SharedRoutes);
+
+impl ResolvesServerCert for SniResolver {
+    fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        let name = client_hello.server_name()?.to_ascii_lowercase();
+        current(&self.0).certs.get(&name).cloned()
+    }
+}
+
+fn tls_acceptor(routes: SharedRoutes) -> Result<TlsAcceptor> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let mut config = ServerConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .map_err(|e| Error::Config(format!("Failed to set up TLS: {}", e)))?
+        .with_no_client_auth()
+        .with_cert_resolver(Arc::new(SniResolver(routes)));
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+struct Proxy {
+    routes: SharedRoutes,
+    client: Client<HttpConnector, Incoming>,
+}
+
+impl Proxy {
+    async fn handle(&self, req: Request<Incoming>, peer: SocketAddr, port: u16, tls: bool) -> Response<Body> {
+        let routes = current(&self.routes);
+        let site = match request_host(&req).and_then(|host| routes.site(&host, port, tls)) {
+            Some(site) => site.clone(),
+            None => return text(StatusCode::NOT_FOUND, "No site is configured for this host\n"),
+        };
+        let path = req.uri().path_and_query().map_or("/", |p| p.as_str()).to_string();
+        log_verbose(&format!("{} {} {}{}", peer.ip(), req.method(), site.domain, path));
+
+        if let Some(ref target) = site.redirect {
+            let location = if target.preserve_path {
+                format!("{}{}", target.to.trim_end_matches('/'), path)
+            } else {
+                target.to.clone()
+            };
+            return redirect(target.code, &location);
+        }
+        if !tls {
+            return redirect(301, &format!("{}{}", site.url(), path));
+        }
+
+        if !access_allowed(&site.access, peer.ip()) {
+            return text(StatusCode::FORBIDDEN, "Forbidden\n");
+        }
+
+        // ACME and other well-known paths stay reachable, as with nginx
+        let well_known = req.uri().path().starts_with("/.well-known/");
+        if site.basic_auth && !well_known && !authorized(&site, req.headers()) {
+            let mut response = text(StatusCode::UNAUTHORIZED, "Authentication required\n");
+            response.headers_mut().insert(
+                header::WWW_AUTHENTICATE,
+                HeaderValue::from_static("Basic realm=\"Restricted\""),
+            );
+            return response;
+        }
+
+        let mut response = if site.maintenance && !well_known {
+            html(StatusCode::SERVICE_UNAVAILABLE, error_pages::maintenance_page(&site))
+        } else {
+            self.forward(req, &site, peer).await
+        };
+        add_site_headers(&site, response.headers_mut());
+        response
+    }
+
+    /// Proxy to the backend, tunnelling upgraded (websocket) connections
+    async fn forward(&self, mut req: Request<Incoming>, site: &DomainConfig, peer: SocketAddr) -> Response<Body> {
+        let host = request_host(&req).unwrap_or_else(|| site.domain.clone());
+        let path = req.uri().path_and_query().map_or("/", |p| p.as_str()).to_string();
+        let upgrade = req.headers().contains_key(header::UPGRADE);
+
+        *req.uri_mut() = match format!("http://127.0.0.1:{}{}", site.port, path).parse::<Uri>() {
+            Ok(uri) => uri,
+            Err(_) => return text(StatusCode::BAD_REQUEST, "Bad request\n"),
+        };
+        // Backends speak HTTP/1.1 whatever the client negotiated
+        *req.version_mut() = Version::HTTP_11;
+
+        let headers = req.headers_mut();
+        strip_hop_by_hop(headers, upgrade);
+        if let Ok(value) = HeaderValue::from_str(&host) {
+            headers.insert(header::HOST, value.clone());
+            headers.insert("x-forwarded-host", value);
+        }
+        let client_ip = peer.ip().to_string();
+        let forwarded_for = match headers.get("x-forwarded-for").and_then(|v| v.to_str().ok()) {
+            Some(previous) => format!("{}, {}", previous, client_ip),
+            None => client_ip.clone(),
+        };
+        for (name, value) in [
+            ("x-forwarded-for", forwarded_for),
+            ("x-real-ip", client_ip),
+            ("x-forwarded-proto", "https".to_string()),
+        ] {
+            if let Ok(value) = HeaderValue::from_str(&value) {
+                headers.insert(name, value);
+            }
+        }
+
+        let client_upgrade = upgrade.then(|| hyper::upgrade::on(&mut req));
+        let mut response = match self.client.request(req).await {
+            Ok(response) => response,
+            Err(e) => {
+                log_verbose(&format!("Backend for {} unavailable: {}", site.domain, e));
+                return html(StatusCode::BAD_GATEWAY, error_pages::backend_down_page(site));
+            }
+        };
+
+        if response.status() == StatusCode::SWITCHING_PROTOCOLS {
+            if let Some(client_upgrade) = client_upgrade {
+                let backend_upgrade = hyper::upgrade::on(&mut response);
+                tokio::spawn(async move {
+                    match tokio::try_join!(client_upgrade, backend_upgrade) {
+                        Ok((client, backend)) => {
+                            let _ = tokio::io::copy_bidirectional(
+                                &mut TokioIo::new(client),
+                                &mut TokioIo::new(backend),
+                            )
+                            .await;
+                        }
+                        Err(e) => log_verbose(&format!("Upgrade failed: {}", e)),
+                    }
+                });
+            }
+        } else {
+            strip_hop_by_hop(response.headers_mut(), false);
+        }
+
+        response.map(|body| body.boxed())
+    }
+}
+
+fn request_host(req: &Request<Incoming>) -> Option<String> {
+    // HTTP/2 carries the host in `:authority`
+    req.uri()
+        .authority()
+        .map(|authority| authority.as_str().to_string())
+        .or_else(|| {
+            req.headers()
+                .get(header::HOST)
+                .and_then(|host| host.to_str().ok())
+                .map(str::to_string)
+        })
+}
+
+fn strip_hop_by_hop(headers: &mut HeaderMap, keep_upgrade: bool) {
+    // Headers listed in `Connection` are connection-level too
+    let listed: Vec<String> = headers
+        .get_all(header::CONNECTION)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|name| name.trim().to_ascii_lowercase())
+        .filter(|name| !name.is_empty())
+        .collect();
+
+    for name in HOP_BY_HOP.iter().map(|name| name.to_string()).chain(listed) {
+        if keep_upgrade && (name == "connection" || name == "upgrade") {
+            continue;
+        }
+        headers.remove(name.as_str());
+    }
+    if keep_upgrade {
+        headers.insert(header::CONNECTION, HeaderValue::from_static("upgrade"));
+    }
+}
+
+/// Whether the `AccessPolicy` lets `ip` through
+fn access_allowed(access: &AccessPolicy, ip: IpAddr) -> bool {
+    let matches = |sources: &[String]| {
+        sources
+            .iter()
+            .any(|source| source == "all" || cidr_contains(source, ip))
+    };
+    matches(&access.allow) || !matches(&access.deny)
+}
+
+fn cidr_contains(source: &str, ip: IpAddr) -> bool {
+    let (addr, prefix) = match source.split_once('/') {
+        Some((addr, prefix)) => (addr, prefix.parse::<u32>().ok()),
+        None => (source, None),
+    };
+    let Ok(network) = addr.parse::<IpAddr>() else {
+        return false;
+    };
+    // IPv4 clients on a dual-stack socket show up as ::ffff:a.b.c.d
+    let ip = match ip {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+        IpAddr::V4(_) => ip,
+    };
+
+    match (network, ip) {
+        (IpAddr::V4(network), IpAddr::V4(ip)) => {
+            let prefix = prefix.unwrap_or(32).min(32);
+            prefix == 0 || (u32::from(network) ^ u32::from(ip)) >> (32 - prefix) == 0
+        }
+        (IpAddr::V6(network), IpAddr::V6(ip)) => {
+            let prefix = prefix.unwrap_or(128).min(128);
+            prefix == 0 || (u128::from(network) ^ u128::from(ip)) >> (128 - prefix) == 0
+        }
+        _ => false,
+    }
+}
+
+fn authorized(site: &DomainConfig, headers: &HeaderMap) -> bool {
+    let credentials = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Basic "))
+        .and_then(|encoded| BASE64.decode(encoded.trim()).ok())
+        .and_then(|decoded| String::from_utf8(decoded).ok());
+    let Some((user, password)) = credentials.as_deref().and_then(|c| c.split_once(':')) else {
+        return false;
+    };
+
+    // Read on every request like nginx, so `auth add-user` needs no restart
+    HtpasswdFile::load(&Paths::htpasswd(site.server, &site.domain))
+        .map(|htpasswd| htpasswd.verify(user, password))
+        .unwrap_or(false)
+}
+
+fn add_site_headers(site: &DomainConfig, headers: &mut HeaderMap) {
+    let profile = site
+        .headers
+        .profile
+        .headers()
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()));
+    let custom = site.headers.custom.iter().filter_map(|h| parse_header(h).ok());
+
+    for (name, value) in profile.chain(custom) {
+        if let (Ok(name), Ok(value)) = (HeaderName::try_from(name), HeaderValue::try_from(value)) {
+            headers.insert(name, value);
+        }
+    }
+}
+
+fn full(body: impl Into<Bytes>) -> Body {
+    Full::new(body.into()).map_err(|never| match never {}).boxed()
+}
+
+fn respond(status: StatusCode, content_type: &'static str, body: impl Into<Bytes>) -> Response<Body> {
+    let mut response = Response::new(full(body));
+    *response.status_mut() = status;
+    response
+        .headers_mut()
+        .insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+    response
+}
+
+fn text(status: StatusCode, body: &'static str) -> Response<Body> {
+    respond(status, "text/plain; charset=utf-8", body)
+}
+
+fn html(status: StatusCode, body: String) -> Response<Body> {
+    let mut response = respond(status, "text/html; charset=utf-8", body);
+    response
+        .headers_mut()
+        .insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    response
+}
+
+fn redirect(code: u16, location: &str) -> Response<Body> {
+    let mut response = text(
+        StatusCode::from_u16(code).unwrap_or(StatusCode::MOVED_PERMANENTLY),
+        "Redirecting\n",
+    );
+    if let Ok(location) = HeaderValue::from_str(location) {
+        response.headers_mut().insert(header::LOCATION, location);
+    }
+    response
+}
+
+async fn serve_connection<I>(io: I, proxy: Arc<Proxy>, peer: SocketAddr, port: u16, tls: bool)
+where
+    I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let service = service_fn(move |req| {
+        let proxy = proxy.clone();
+        async move { Ok::<_, Infallible>(proxy.handle(req, peer, port, tls).await) }
+    });
+
+    if let Err(e) = auto::Builder::new(TokioExecutor::new())
+        .serve_connection_with_upgrades(TokioIo::new(io), service)
+        .await
+    {
+        log_verbose(&format!("Connection from {} ended: {}", peer, e));
+    }
+}
+
+async fn accept_loop(listener: TcpListener, port: u16, tls: Option<TlsAcceptor>, proxy: Arc<Proxy>) {
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                log_verbose(&format!("Accept failed on port {}: {}", port, e));
+                continue;
+            }
+        };
+        let proxy = proxy.clone();
+        let tls = tls.clone();
+
+        tokio::spawn(async move {
+            match tls {
+                Some(acceptor) => match acceptor.accept(stream).await {
+                    Ok(stream) => serve_connection(stream, proxy, peer, port, true).await,
+                    Err(e) => log_verbose(&format!("TLS handshake with {} failed: {}", peer, e)),
+                },
+                None => serve_connection(stream, proxy, peer, port, false).await,
+            }
+        });
+    }
+}
+
+/// Start listeners for new ports and stop the ones no site uses anymore
+async fn sync_listeners(
+    listeners: &mut HashMap<(u16, bool), JoinHandle<()>>,
+    proxy: &Arc<Proxy>,
+    acceptor: &TlsAcceptor,
+) {
+    let wanted = current(&proxy.routes).listeners();
+
+    listeners.retain(|key, task| {
+        let keep = wanted.contains(key);
+        if !keep {
+            log_info(&format!("Closing port {}", key.0));
+            task.abort();
+        }
+        keep
+    });
+
+    for (port, tls) in wanted {
+        if listeners.contains_key(&(port, tls)) {
+            continue;
+        }
+        match TcpListener::bind(("0.0.0.0", port)).await {
+            Ok(listener) => {
+                log_info(&format!("Listening on port {}{}", port, if tls { " (TLS)" } else { "" }));
+                let acceptor = tls.then(|| acceptor.clone());
+                let task = tokio::spawn(accept_loop(listener, port, acceptor, proxy.clone()));
+                listeners.insert((port, tls), task);
+            }
+            Err(e) => log_warning(&format!("Can't listen on port {}: {}", port, e)),
+        }
+    }
+}
+
+fn modified_time() -> Option<SystemTime> {
+    fs::metadata(ConfigManager::config_path()).and_then(|m| m.modified()).ok()
+}
+
+/// Serve every built-in proxy site until interrupted, picking up changes
+/// to domains.json as they happen
+pub async fn run() -> Result<()> {
+    let routes: SharedRoutes = Arc::new(RwLock::new(Arc::new(Routes::load()?)));
+    let acceptor = tls_acceptor(routes.clone())?;
+    let proxy = Arc::new(Proxy {
+        routes: routes.clone(),
+        client: Client::builder(TokioExecutor::new()).build_http(),
+    });
+
+    let mut listeners = HashMap::new();
+    sync_listeners(&mut listeners, &proxy, &acceptor).await;
+    log_success(&format!("Serving {} site(s)", current(&routes).sites.len()));
+
+    let mut terminate = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+    let mut interval = tokio::time::interval(POLL_INTERVAL);
+    let mut last_modified = modified_time();
+
+    loop {
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => break,
+            _ = terminate.recv() => break,
+            _ = interval.tick() => {
+                let modified = modified_time();
+                if modified == last_modified {
+                    continue;
+                }
+                // A half-written file fails to parse; try again on the next tick
+                match Routes::load() {
+                    Ok(new_routes) => {
+                        last_modified = modified;
+                        log_info(&format!("domains.json changed, serving {} site(s)", new_routes.sites.len()));
+                        if let Ok(mut shared) = routes.write() {
+                            *shared = Arc::new(new_routes);
+                        }
+                        sync_listeners(&mut listeners, &proxy, &acceptor).await;
+                    }
+                    Err(e) => log_verbose(&format!("Not reloading yet: {}", e)),
+                }
+            }
+        }
+    }
+
+    log_info("Shutting down");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_access_rules() {
+        let ip = |addr: &str| addr.parse::<IpAddr>().unwrap();
+        let policy = AccessPolicy {
+            allow: vec!["10.0.0.0/8".to_string(), "2001:db8::/32".to_string()],
+            deny: vec!["all".to_string()],
+            ..Default::default()
+        };
+        assert!(access_allowed(&policy, ip("10.1.2.3")));
+        assert!(access_allowed(&policy, ip("::ffff:10.1.2.3")));
+        assert!(access_allowed(&policy, ip("2001:db8::1")));
+        assert!(!access_allowed(&policy, ip("192.168.1.1")));
+
+        let deny_one = AccessPolicy {
+            deny: vec!["192.168.1.1".to_string()],
+            ..Default::default()
+        };
+        assert!(!access_allowed(&deny_one, ip("192.168.1.1")));
+        assert!(access_allowed(&deny_one, ip("192.168.1.2")));
+    }
+
+    #[test]
+    fn test_routes_match_host_and_port() {
+        let mut app = DomainConfig {
+            domain: "app.test".to_string(),
+            port: 3000,
+            server: ServerKind::Builtin,
+            https_port: Some(8443),
+            ..Default::default()
+        };
+        let nginx = DomainConfig {
+            domain: "nginx.test".to_string(),
+            ..Default::default()
+        };
+        let routes = Routes::from_sites([&app, &nginx]);
+        assert_eq!(routes.listeners(), BTreeSet::from([(80, false), (8443, true)]));
+        assert!(routes.site("App.Test:8443", 8443, true).is_some());
+        assert!(routes.site("app.test", 443, true).is_none());
+        assert!(routes.site("app.test", 80, false).is_some());
+        assert!(routes.site("nginx.test", 443, true).is_none());
+
+        app.kind = SiteKind::Tcp;
+        assert!(Routes::from_sites([&app]).sites.is_empty());
+    }
+}
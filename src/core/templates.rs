@@ -0,0 +1,517 @@
+use std::fs;
+use std::path::Path;
+
+use crate::config::{DomainConfig, HeaderProfile, Paths};
+use crate::error::{Error, Result};
+
+/// Where a template comes from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TemplateSource {
+    Builtin,
+    /// A file in `Paths::templates_dir`
+    User(String),
+    /// A file passed by path to `--template`
+    File(String),
+}
+
+/// An nginx site template. Its leading `# key: value` comments carry the
+/// metadata; `{{placeholders}}` are filled by `render_template`, and the
+/// per-site option blocks by `NginxConfig::render_template`.
+#[derive(Debug, Clone)]
+pub struct Template {
+    pub name: String,
+    pub version: u32,
+    pub description: String,
+    pub source: TemplateSource,
+    pub content: String,
+}
+
+impl Template {
+    fn new(name: &str, source: TemplateSource, content: String) -> Self {
+        let mut version = 1;
+        let mut description = String::new();
+        for line in content.lines().map(str::trim).take_while(|l| l.starts_with('#')) {
+            let Some((key, value)) = line.trim_start_matches('#').split_once(':') else {
+                continue;
+            };
+            match key.trim() {
+                "version" => version = value.trim().parse().unwrap_or(1),
+                "description" => description = value.trim().to_string(),
+                _ => {}
+            }
+        }
+
+        Template {
+            name: name.to_string(),
+            version,
+            description,
+            source,
+            content,
+        }
+    }
+
+    /// What `DomainConfig::template` records: the name, or the file path
+    pub fn reference(&self) -> &str {
+        match self.source {
+            TemplateSource::File(ref path) => path,
+            TemplateSource::Builtin | TemplateSource::User(_) => &self.name,
+        }
+    }
+}
+
+const PROXY: &str = r#"# description: HTTPS reverse proxy to a local port
+# version: 2
+server {
+    listen {{http_port}};
+    server_name {{domain}};
+    return 301 https://$host{{https_port_suffix}}$request_uri;
+}
+
+server {
+    listen {{https_port}} ssl http2;
+    server_name {{domain}};
+
+    ssl_certificate     {{ssl_cert}};
+    ssl_certificate_key {{ssl_key}};
+    include {{ssl_snippet}};
+
+    access_log {{log_dir}}/{{domain}}_access.log;
+    error_log {{log_dir}}/{{domain}}_error.log;
+
+    {{headers}}
+    {{access}}
+    {{auth}}
+    {{compression}}
+    {{cache}}
+    {{error_pages}}
+    {{maintenance}}
+
+    location / {
+        {{cors_preflight}}
+        proxy_pass http://127.0.0.1:{{port}};
+        include {{proxy_snippet}};
+    }
+}
+"#;
+
+const SPA: &str = r#"# description: Single-page app from /var/www/<domain> with /api proxied to the port
+# version: 2
+server {
+    listen {{http_port}};
+    server_name {{domain}};
+    return 301 https://$host{{https_port_suffix}}$request_uri;
+}
+
+server {
+    listen {{https_port}} ssl http2;
+    server_name {{domain}};
+
+    ssl_certificate     {{ssl_cert}};
+    ssl_certificate_key {{ssl_key}};
+    include {{ssl_snippet}};
+
+    access_log {{log_dir}}/{{domain}}_access.log;
+    error_log {{log_dir}}/{{domain}}_error.log;
+
+    {{headers}}
+    {{access}}
+    {{auth}}
+    {{compression}}
+    {{cache}}
+    {{error_pages}}
+    {{maintenance}}
+
+    root /var/www/{{domain}};
+    index index.html;
+
+    location / {
+        try_files $uri $uri/ /index.html;
+    }
+
+    location /assets/ {
+        expires 30d;
+        add_header Cache-Control "public, immutable";
+    }
+
+    location /api/ {
+        {{cors_preflight}}
+        proxy_pass http://127.0.0.1:{{port}};
+        include {{proxy_snippet}};
+    }
+}
+"#;
+
+const PHP_FPM: &str = r#"# description: PHP app from /var/www/<domain>/public through php-fpm listening on the port
+# version: 2
+server {
+    listen {{http_port}};
+    server_name {{domain}};
+    return 301 https://$host{{https_port_suffix}}$request_uri;
+}
+
+server {
+    listen {{https_port}} ssl http2;
+    server_name {{domain}};
+
+    ssl_certificate     {{ssl_cert}};
+    ssl_certificate_key {{ssl_key}};
+    include {{ssl_snippet}};
+
+    access_log {{log_dir}}/{{domain}}_access.log;
+    error_log {{log_dir}}/{{domain}}_error.log;
+
+    {{headers}}
+    {{access}}
+    {{auth}}
+    {{compression}}
+    {{error_pages}}
+    {{maintenance}}
+
+    root /var/www/{{domain}}/public;
+    index index.php index.html;
+    client_max_body_size 64m;
+
+    location / {
+        {{cors_preflight}}
+        try_files $uri $uri/ /index.php?$query_string;
+    }
+
+    location ~ \.php$ {
+        try_files $uri =404;
+        fastcgi_split_path_info ^(.+\.php)(/.+)$;
+        fastcgi_pass 127.0.0.1:{{port}};
+        fastcgi_index index.php;
+        include fastcgi_params;
+        fastcgi_param SCRIPT_FILENAME $realpath_root$fastcgi_script_name;
+        fastcgi_param HTTPS on;
+    }
+
+    location ~ /\.(?!well-known) {
+        deny all;
+    }
+}
+"#;
+
+const WEBSOCKET_ONLY: &str = r#"# description: Long-lived websocket connections only, plain requests get 426
+# version: 2
+server {
+    listen {{http_port}};
+    server_name {{domain}};
+    return 301 https://$host{{https_port_suffix}}$request_uri;
+}
+
+server {
+    listen {{https_port}} ssl http2;
+    server_name {{domain}};
+
+    ssl_certificate     {{ssl_cert}};
+    ssl_certificate_key {{ssl_key}};
+    include {{ssl_snippet}};
+
+    access_log {{log_dir}}/{{domain}}_access.log;
+    error_log {{log_dir}}/{{domain}}_error.log;
+
+    {{headers}}
+    {{access}}
+    {{auth}}
+    {{error_pages}}
+    {{maintenance}}
+
+    location / {
+        if ($http_upgrade != "websocket") {
+            return 426;
+        }
+
+        proxy_pass http://127.0.0.1:{{port}};
+        proxy_http_version 1.1;
+        proxy_set_header Host $host;
+        proxy_set_header X-Real-IP $remote_addr;
+        proxy_set_header X-Forwarded-For $proxy_add_x_forwarded_for;
+        proxy_set_header X-Forwarded-Proto $scheme;
+        proxy_set_header Upgrade $http_upgrade;
+        proxy_set_header Connection "upgrade";
+        proxy_buffering off;
+        proxy_read_timeout 1h;
+        proxy_send_timeout 1h;
+    }
+}
+"#;
+
+const GRPC: &str = r#"# description: gRPC over HTTP/2 to a plaintext gRPC server on the port
+# version: 2
+server {
+    listen {{https_port}} ssl http2;
+    server_name {{domain}};
+
+    ssl_certificate     {{ssl_cert}};
+    ssl_certificate_key {{ssl_key}};
+    include {{ssl_snippet}};
+
+    access_log {{log_dir}}/{{domain}}_access.log;
+    error_log {{log_dir}}/{{domain}}_error.log;
+
+    {{headers}}
+    {{access}}
+    {{auth}}
+    {{maintenance}}
+
+    client_max_body_size 0;
+
+    location / {
+        grpc_pass grpc://127.0.0.1:{{port}};
+        grpc_set_header X-Real-IP $remote_addr;
+        grpc_read_timeout 1h;
+        grpc_send_timeout 1h;
+    }
+}
+"#;
+
+const BUILTINS: &[(&str, &str)] = &[
+    ("proxy", PROXY),
+    ("spa", SPA),
+    ("php-fpm", PHP_FPM),
+    ("websocket-only", WEBSOCKET_ONLY),
+    ("grpc", GRPC),
+];
+
+/// Site options a template has to make room for, with the `{{placeholders}}`
+/// that render them. Only nginx templates have these placeholders.
+pub fn requested_options(site: &DomainConfig) -> Vec<(&'static str, &'static [&'static str])> {
+    let mut options: Vec<(&'static str, &'static [&'static str])> = Vec::new();
+    // The relaxed default profile is a nicety, templates may go without it
+    if !site.headers.custom.is_empty() || site.headers.profile == HeaderProfile::Strict {
+        options.push(("response headers", &["headers"]));
+    }
+    if !site.headers.cors_origins.is_empty() {
+        options.push(("CORS", &["headers", "cors_preflight"]));
+    }
+    if !site.access.allow.is_empty() || !site.access.deny.is_empty() {
+        options.push(("access rules", &["access"]));
+    }
+    if site.access.rate_limit.is_some() {
+        options.push(("rate limiting", &["access"]));
+    }
+    if site.basic_auth {
+        options.push(("basic auth", &["auth"]));
+    }
+    if site.gzip || site.brotli {
+        options.push(("compression", &["compression"]));
+    }
+    if site.cache_ttl.is_some() {
+        options.push(("proxy caching", &["cache"]));
+    }
+    if site.maintenance {
+        options.push(("maintenance mode", &["maintenance"]));
+    }
+    options
+}
+
+/// Options of a `--template` site that its template can't render: those
+/// whose placeholders are missing from `content`, or all of them for
+/// templates without placeholders (`None`, other servers' templates)
+pub fn missing_options(site: &DomainConfig, content: Option<&str>) -> Vec<&'static str> {
+    if site.template.is_none() {
+        return Vec::new();
+    }
+    requested_options(site)
+        .into_iter()
+        .filter(|(_, placeholders)| {
+            let content = content.unwrap_or_default();
+            placeholders.iter().any(|name| !content.contains(&format!("{{{{{}}}}}", name)))
+        })
+        .map(|(feature, _)| feature)
+        .collect()
+}
+
+/// For servers whose templates are used as written: every option requested
+/// for a `--template` site is one they can't add
+pub fn add_missing_options(unsupported: &mut Vec<&'static str>, site: &DomainConfig) {
+    for feature in missing_options(site, None) {
+        if !unsupported.contains(&feature) {
+            unsupported.push(feature);
+        }
+    }
+}
+
+fn builtin(name: &str) -> Option<Template> {
+    BUILTINS
+        .iter()
+        .find(|(builtin, _)| *builtin == name)
+        .map(|(name, content)| Template::new(name, TemplateSource::Builtin, content.to_string()))
+}
+
+pub fn is_builtin(name: &str) -> bool {
+    BUILTINS.iter().any(|(builtin, _)| *builtin == name)
+}
+
+/// Template names are file names in the templates directory
+pub fn validate_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_');
+    if !valid {
+        return Err(Error::Validation(format!(
+            "Invalid template name '{}': use lowercase letters, digits, '-' and '_'",
+            name
+        )));
+    }
+    Ok(())
+}
+
+pub fn user_template_path(name: &str) -> String {
+    format!("{}/{}.conf", Paths::templates_dir(), name)
+}
+
+fn load_file(name: &str, source: TemplateSource, path: &str) -> Result<Template> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| Error::Config(format!("Failed to read template {}: {}", path, e)))?;
+    Ok(Template::new(name, source, content))
+}
+
+/// Built-in templates followed by the user's, sorted by name
+pub fn list() -> Result<Vec<Template>> {
+    let mut templates: Vec<Template> = BUILTINS.iter().filter_map(|(name, _)| builtin(name)).collect();
+
+    let mut user = Vec::new();
+    if let Ok(entries) = fs::read_dir(Paths::templates_dir()) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Some(name) = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .and_then(|n| n.strip_suffix(".conf"))
+            else {
+                continue;
+            };
+            // Built-in names can't be shadowed
+            if is_builtin(name) || validate_name(name).is_err() {
+                continue;
+            }
+            let path = path.to_string_lossy().to_string();
+            user.push(load_file(name, TemplateSource::User(path.clone()), &path)?);
+        }
+    }
+    user.sort_by(|a, b| a.name.cmp(&b.name));
+    templates.extend(user);
+
+    Ok(templates)
+}
+
+/// Look up a template by registry name
+pub fn find(name: &str) -> Result<Template> {
+    if let Some(template) = builtin(name) {
+        return Ok(template);
+    }
+
+    let path = user_template_path(name);
+    if validate_name(name).is_ok() && Path::new(&path).is_file() {
+        return load_file(name, TemplateSource::User(path.clone()), &path);
+    }
+
+    Err(Error::NotFound(format!(
+        "Template '{}' not found, see `localstacker template list`",
+        name
+    )))
+}
+
+/// Resolve `--template`: an existing file wins, otherwise a registry name
+pub fn resolve(reference: &str) -> Result<Template> {
+    let path = Path::new(reference);
+    if path.is_file() {
+        let path = fs::canonicalize(path)
+            .map(|p| p.to_string_lossy().to_string())
+            .unwrap_or_else(|_| reference.to_string());
+        let name = Path::new(&path)
+            .file_stem()
+            .map_or_else(|| path.clone(), |stem| stem.to_string_lossy().to_string());
+        return load_file(&name, TemplateSource::File(path.clone()), &path);
+    }
+
+    if reference.contains('/') {
+        return Err(Error::NotFound(format!("Template file '{}' not found", reference)));
+    }
+    find(reference)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{AccessPolicy, HeaderPolicy};
+    use crate::core::nginx_lint::{self, LintContext};
+    use crate::core::NginxConfig;
+
+    #[test]
+    fn test_builtins_have_metadata_and_lint_clean() {
+        let plain = DomainConfig {
+            domain: "app.test".to_string(),
+            port: 3000,
+            ssl_cert_path: "/etc/nginx/ssl/app.test.pem".to_string(),
+            ssl_key_path: "/etc/nginx/ssl/app.test-key.pem".to_string(),
+            ..Default::default()
+        };
+        let everything = DomainConfig {
+            basic_auth: true,
+            access: AccessPolicy {
+                allow: vec!["10.0.0.0/8".to_string()],
+                deny: vec!["all".to_string()],
+                rate_limit: Some("10r/s".to_string()),
+                burst: Some(20),
+            },
+            headers: HeaderPolicy {
+                profile: HeaderProfile::Strict,
+                custom: vec!["X-Env: dev".to_string()],
+                cors_origins: vec!["https://ui.test".to_string()],
+            },
+            gzip: true,
+            cache_ttl: Some("10m".to_string()),
+            maintenance: true,
+            ..plain.clone()
+        };
+
+        for (name, _) in BUILTINS {
+            let template = find(name).unwrap();
+            assert_eq!(template.version, 2);
+            assert!(!template.description.is_empty(), "{} has no description", name);
+            assert_eq!(template.reference(), *name);
+
+            for site in [&plain, &everything] {
+                let site = DomainConfig { template: Some(name.to_string()), ..site.clone() };
+                let config = NginxConfig::render_template(name, &site).unwrap();
+                assert!(!config.contains("{{"), "{} has unfilled placeholders", name);
+                let findings = nginx_lint::lint(&config, LintContext::Http, Some("app.test")).unwrap();
+                assert_eq!(findings, vec![], "{} doesn't lint clean", name);
+            }
+        }
+
+        // Options a template leaves out are reported instead of dropped
+        let proxy = DomainConfig { template: Some("proxy".to_string()), ..everything.clone() };
+        assert_eq!(missing_options(&proxy, Some(&find("proxy").unwrap().content)), Vec::<&str>::new());
+        let grpc = DomainConfig { template: Some("grpc".to_string()), ..everything.clone() };
+        assert_eq!(
+            missing_options(&grpc, Some(&find("grpc").unwrap().content)),
+            ["CORS", "compression", "proxy caching"]
+        );
+        assert!(missing_options(&proxy, None).contains(&"basic auth"));
+        assert!(missing_options(&everything, None).is_empty());
+    }
+
+    #[test]
+    fn test_resolve_prefers_files_and_rejects_bad_names() {
+        let dir = std::env::temp_dir().join(format!("localstacker-templates-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("custom.conf");
+        fs::write(&file, "# version: 3\n# description: mine\nserver {}\n").unwrap();
+
+        let template = resolve(file.to_str().unwrap()).unwrap();
+        assert_eq!(template.name, "custom");
+        assert_eq!(template.version, 3);
+        assert_eq!(template.description, "mine");
+        assert!(matches!(template.source, TemplateSource::File(_)));
+        assert_eq!(resolve("grpc").unwrap().source, TemplateSource::Builtin);
+        assert!(resolve("./missing/x.conf").is_err());
+        assert!(validate_name("My Template").is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,702 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::config::{DomainConfig, Paths, RedirectTarget, ServerKind, Settings, SiteKind};
+use crate::core::{disable_renamed, enable_renamed, render_template, templates, WebServerConfig};
+use crate::error::{Error, Result};
+use crate::utils::{content_hash, log_info, log_verbose, parse_header};
+
+/// First line of every generated file; `test_config` only checks these
+const GENERATED_MARKER: &str = "# Generated by localstacker";
+
+/// Settings from the `[traefik]` table of the global config file
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct TraefikSettings {
+    /// Directory watched by Traefik's file provider
+    pub directory: String,
+    /// Entry point for plain HTTP
+    pub http_entry_point: String,
+    /// Entry point for HTTPS
+    pub https_entry_point: String,
+}
+
+impl Default for TraefikSettings {
+    fn default() -> Self {
+        TraefikSettings {
+            directory: "/etc/traefik/dynamic".to_string(),
+            http_entry_point: "web".to_string(),
+            https_entry_point: "websecure".to_string(),
+        }
+    }
+}
+
+/// The subset of Traefik's dynamic configuration localstacker writes.
+/// Unknown keys are rejected so typos in hand-edited files are caught.
+#[derive(Debug, Serialize, Deserialize, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+struct DynamicConfig {
+    http: HttpConfig,
+    #[serde(default, skip_serializing_if = "TlsConfig::is_empty")]
+    tls: TlsConfig,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+struct HttpConfig {
+    routers: BTreeMap<String, Router>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    services: BTreeMap<String, Service>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    middlewares: BTreeMap<String, Middleware>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct Router {
+    rule: String,
+    entry_points: Vec<String>,
+    service: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    middlewares: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tls: Option<RouterTls>,
+}
+
+/// `tls: {}` - certificates come from the `tls` section
+#[derive(Debug, Serialize, Deserialize, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+struct RouterTls {}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct Service {
+    load_balancer: LoadBalancer,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+struct LoadBalancer {
+    servers: Vec<ServerUrl>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+struct ServerUrl {
+    url: String,
+}
+
+/// Exactly one of the fields is set
+#[derive(Debug, Serialize, Deserialize, Default, PartialEq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct Middleware {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    redirect_scheme: Option<RedirectScheme>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    redirect_regex: Option<RedirectRegex>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    headers: Option<Headers>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ip_allow_list: Option<IpAllowList>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    basic_auth: Option<BasicAuth>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    rate_limit: Option<RateLimit>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    compress: Option<Compress>,
+}
+
+impl Middleware {
+    fn kinds(&self) -> usize {
+        [
+            self.redirect_scheme.is_some(),
+            self.redirect_regex.is_some(),
+            self.headers.is_some(),
+            self.ip_allow_list.is_some(),
+            self.basic_auth.is_some(),
+            self.rate_limit.is_some(),
+            self.compress.is_some(),
+        ]
+        .iter()
+        .filter(|set| **set)
+        .count()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+struct RedirectScheme {
+    scheme: String,
+    permanent: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    port: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+struct RedirectRegex {
+    regex: String,
+    replacement: String,
+    permanent: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, PartialEq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct Headers {
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    custom_response_headers: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    access_control_allow_origin_list: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    access_control_allow_methods: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    access_control_allow_headers: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    access_control_allow_credentials: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    access_control_max_age: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    add_vary_header: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct IpAllowList {
+    source_range: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct BasicAuth {
+    users_file: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+struct RateLimit {
+    average: u32,
+    period: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    burst: Option<u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+struct Compress {
+    encodings: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+struct TlsConfig {
+    #[serde(default)]
+    certificates: Vec<Certificate>,
+}
+
+impl TlsConfig {
+    fn is_empty(&self) -> bool {
+        self.certificates.is_empty()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct Certificate {
+    cert_file: String,
+    key_file: String,
+}
+
+/// Traefik file-provider backend: one dynamic configuration YAML per site
+/// in the watched directory. Traefik hot-reloads it, so there is nothing
+/// to reload; disabled sites are renamed to `*.yml.disabled`.
+pub struct TraefikConfig;
+
+impl TraefikConfig {
+    pub fn new() -> Self {
+        TraefikConfig
+    }
+
+    /// Router, service and middleware names: `app-test-` plus a short hash
+    /// of the domain for app.test. Names are global across the watched
+    /// directory and a-b.test and a.b.test read the same without the hash.
+    fn name(site: &DomainConfig) -> String {
+        format!("{}-{}", site.domain.replace('.', "-"), &content_hash(&site.domain)[..8])
+    }
+
+    fn router(site: &DomainConfig, entry_point: &str, service: &str, middlewares: Vec<String>) -> Router {
+        Router {
+            rule: format!("Host(`{}`)", site.domain),
+            entry_points: vec![entry_point.to_string()],
+            service: service.to_string(),
+            middlewares,
+            tls: None,
+        }
+    }
+
+    fn headers(site: &DomainConfig) -> Option<Headers> {
+        let mut headers = Headers::default();
+        for (name, value) in site.headers.profile.headers() {
+            headers.custom_response_headers.insert(name.to_string(), value.to_string());
+        }
+        for (name, value) in site.headers.custom.iter().filter_map(|h| parse_header(h).ok()) {
+            headers.custom_response_headers.insert(name, value);
+        }
+
+        let origins = &site.headers.cors_origins;
+        if !origins.is_empty() {
+            headers.access_control_allow_origin_list = origins.clone();
+            headers.access_control_allow_methods = ["GET", "POST", "PUT", "PATCH", "DELETE", "OPTIONS"]
+                .iter()
+                .map(|method| method.to_string())
+                .collect();
+            headers.access_control_allow_headers = vec!["*".to_string()];
+            headers.access_control_max_age = Some(86400);
+            headers.add_vary_header = Some(true);
+            // Credentials can't be combined with a wildcard origin
+            if !origins.iter().any(|origin| origin == "*") {
+                headers.access_control_allow_credentials = Some(true);
+            }
+        }
+
+        (headers != Headers::default()).then_some(headers)
+    }
+
+    /// `10r/s` is an average of 10 per second
+    fn rate_limit(rate: &str, burst: Option<u32>) -> Option<RateLimit> {
+        let (count, period) = match rate.strip_suffix("r/s") {
+            Some(count) => (count, "1s"),
+            None => (rate.strip_suffix("r/m")?, "1m"),
+        };
+        Some(RateLimit {
+            average: count.parse().ok()?,
+            period: period.to_string(),
+            burst,
+        })
+    }
+
+    /// Middlewares of the HTTPS router, in the order they apply
+    fn site_middlewares(site: &DomainConfig) -> Vec<(String, Middleware)> {
+        let name = Self::name(site);
+        let mut middlewares = Vec::new();
+
+        if !site.access.allow.is_empty() {
+            middlewares.push((
+                format!("{}-allow", name),
+                Middleware {
+                    ip_allow_list: Some(IpAllowList { source_range: site.access.allow.clone() }),
+                    ..Default::default()
+                },
+            ));
+        }
+        if let Some(rate) = &site.access.rate_limit {
+            if let Some(rate_limit) = Self::rate_limit(rate, site.access.burst) {
+                middlewares.push((
+                    format!("{}-ratelimit", name),
+                    Middleware { rate_limit: Some(rate_limit), ..Default::default() },
+                ));
+            }
+        }
+        if site.basic_auth {
+            middlewares.push((
+                format!("{}-auth", name),
+                Middleware {
+                    basic_auth: Some(BasicAuth {
+                        users_file: Paths::htpasswd(ServerKind::Traefik, &site.domain),
+                    }),
+                    ..Default::default()
+                },
+            ));
+        }
+        if let Some(headers) = Self::headers(site) {
+            middlewares.push((
+                format!("{}-headers", name),
+                Middleware { headers: Some(headers), ..Default::default() },
+            ));
+        }
+
+        let mut encodings = Vec::new();
+        if site.brotli {
+            encodings.push("br".to_string());
+        }
+        if site.gzip {
+            encodings.push("gzip".to_string());
+        }
+        if !encodings.is_empty() {
+            middlewares.push((
+                format!("{}-compress", name),
+                Middleware { compress: Some(Compress { encodings }), ..Default::default() },
+            ));
+        }
+
+        middlewares
+    }
+
+    fn default_config(site: &DomainConfig) -> DynamicConfig {
+        let settings = &Settings::global().traefik;
+        let name = Self::name(site);
+        let mut config = DynamicConfig::default();
+
+        let https_port = site.effective_https_port();
+        let redirect = format!("{}-https-redirect", name);
+        config.http.middlewares.insert(
+            redirect.clone(),
+            Middleware {
+                redirect_scheme: Some(RedirectScheme {
+                    scheme: "https".to_string(),
+                    permanent: true,
+                    port: (https_port != 443).then(|| https_port.to_string()),
+                }),
+                ..Default::default()
+            },
+        );
+        config.http.routers.insert(
+            format!("{}-http", name),
+            Self::router(site, &settings.http_entry_point, &name, vec![redirect]),
+        );
+
+        let middlewares = Self::site_middlewares(site);
+        let mut router = Self::router(
+            site,
+            &settings.https_entry_point,
+            &name,
+            middlewares.iter().map(|(name, _)| name.clone()).collect(),
+        );
+        router.tls = Some(RouterTls {});
+        config.http.routers.insert(name.clone(), router);
+        config.http.middlewares.extend(middlewares);
+
+        config.http.services.insert(
+            name,
+            Service {
+                load_balancer: LoadBalancer {
+                    servers: vec![ServerUrl { url: format!("http://127.0.0.1:{}", site.port) }],
+                },
+            },
+        );
+
+        config
+    }
+
+    fn redirect_config(site: &DomainConfig, target: &RedirectTarget) -> DynamicConfig {
+        let settings = &Settings::global().traefik;
+        let name = Self::name(site);
+        let mut config = DynamicConfig::default();
+
+        let middleware = format!("{}-redirect", name);
+        let replacement = if target.preserve_path {
+            format!("{}${{1}}", target.to.trim_end_matches('/'))
+        } else {
+            target.to.clone()
+        };
+        config.http.middlewares.insert(
+            middleware.clone(),
+            Middleware {
+                redirect_regex: Some(RedirectRegex {
+                    regex: "^https?://[^/]+(.*)".to_string(),
+                    replacement,
+                    permanent: matches!(target.code, 301 | 308),
+                }),
+                ..Default::default()
+            },
+        );
+
+        // Both entry points redirect straight to the target
+        let service = "noop@internal";
+        config.http.routers.insert(
+            format!("{}-http", name),
+            Self::router(site, &settings.http_entry_point, service, vec![middleware.clone()]),
+        );
+        let mut router = Self::router(site, &settings.https_entry_point, service, vec![middleware]);
+        router.tls = Some(RouterTls {});
+        config.http.routers.insert(name, router);
+
+        config
+    }
+
+    fn render(site: &DomainConfig, config: &DynamicConfig) -> Result<String> {
+        let yaml = serde_norway::to_string(config)
+            .map_err(|e| Error::Config(format!("Failed to serialize Traefik config: {}", e)))?;
+        Ok(Self::marked(site, &yaml))
+    }
+
+    /// `test_config` only checks files carrying the marker
+    fn marked(site: &DomainConfig, yaml: &str) -> String {
+        format!("{} for {}\n{}", GENERATED_MARKER, site.domain, yaml)
+    }
+
+    /// Check a file against the schema and that every router's service and
+    /// middlewares are defined (or come from another provider, `name@provider`)
+    fn validate(contents: &str) -> std::result::Result<DynamicConfig, String> {
+        let config: DynamicConfig = serde_norway::from_str(contents).map_err(|e| e.to_string())?;
+
+        for (name, router) in &config.http.routers {
+            if router.rule.trim().is_empty() {
+                return Err(format!("router {} has an empty rule", name));
+            }
+            if router.entry_points.is_empty() {
+                return Err(format!("router {} has no entry points", name));
+            }
+            if !router.service.contains('@') && !config.http.services.contains_key(&router.service) {
+                return Err(format!("router {} uses undefined service {}", name, router.service));
+            }
+            for middleware in &router.middlewares {
+                if !middleware.contains('@') && !config.http.middlewares.contains_key(middleware) {
+                    return Err(format!("router {} uses undefined middleware {}", name, middleware));
+                }
+            }
+        }
+        for (name, service) in &config.http.services {
+            if service.load_balancer.servers.is_empty() {
+                return Err(format!("service {} has no servers", name));
+            }
+        }
+        for (name, middleware) in &config.http.middlewares {
+            if middleware.kinds() != 1 {
+                return Err(format!("middleware {} must have exactly one type", name));
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+impl WebServerConfig for TraefikConfig {
+    fn generate_config(&self, site: &DomainConfig, template: Option<&str>) -> Result<String> {
+        log_verbose(&format!("Generating Traefik config for {}", site.domain));
+
+        let unsupported = self.unsupported_features(site);
+        if !unsupported.is_empty() {
+            return Err(Error::Validation(format!(
+                "Traefik sites don't support {}; use --server nginx",
+                unsupported.join(", ")
+            )));
+        }
+
+        if let Some(template_path) = template {
+            return Ok(Self::marked(site, &render_template(template_path, site)?));
+        }
+
+        let mut config = match site.kind {
+            SiteKind::Redirect => {
+                let target = site.redirect.as_ref().ok_or_else(|| {
+                    Error::Config(format!("Redirect site {} has no target", site.domain))
+                })?;
+                Self::redirect_config(site, target)
+            }
+            _ => Self::default_config(site),
+        };
+        config.tls.certificates.push(Certificate {
+            cert_file: site.ssl_cert_path.clone(),
+            key_file: site.ssl_key_path.clone(),
+        });
+
+        Self::render(site, &config)
+    }
+
+    /// Options with no file-provider equivalent. Ports and HTTP/3 belong to
+    /// Traefik's static entry point configuration.
+    fn unsupported_features(&self, site: &DomainConfig) -> Vec<&'static str> {
+        let mut unsupported = Vec::new();
+        if site.kind == SiteKind::Tcp {
+            unsupported.push("TCP sites");
+        }
+        if site.http3 {
+            unsupported.push("HTTP/3");
+        }
+        if site.http_port.is_some() || site.https_port.is_some() {
+            unsupported.push("per-domain ports");
+        }
+        // Only an allow list in front of `--deny all` maps to ipAllowList
+        let access = &site.access;
+        let allow_list = !access.allow.is_empty() && access.deny == ["all"];
+        if (!access.allow.is_empty() || !access.deny.is_empty()) && !allow_list {
+            unsupported.push("access rules other than --allow with --deny all");
+        }
+        if site.cache_ttl.is_some() {
+            unsupported.push("proxy caching");
+        }
+        if site.maintenance {
+            unsupported.push("maintenance mode");
+        }
+        templates::add_missing_options(&mut unsupported, site);
+        unsupported
+    }
+
+    fn write_config(&self, site: &DomainConfig, config: &str) -> Result<()> {
+        log_info(&format!("Writing Traefik configuration for {}...", site.domain));
+
+        fs::create_dir_all(Paths::traefik_dynamic_dir())
+            .map_err(|e| Error::Config(format!("Failed to create Traefik directory: {}", e)))?;
+
+        fs::write(&site.config_path, config)
+            .map_err(|e| Error::Config(format!("Failed to write Traefik config: {}", e)))?;
+
+        Ok(())
+    }
+
+    fn enable_site(&self, site: &DomainConfig) -> Result<()> {
+        log_info(&format!("Enabling site {}...", site.domain));
+        enable_renamed(&site.config_path)
+    }
+
+    /// Traefik only watches `*.yml`, `*.yaml` and `*.toml`
+    fn disable_site(&self, site: &DomainConfig) -> Result<()> {
+        log_info(&format!("Disabling site {}...", site.domain));
+        disable_renamed(&site.config_path)
+    }
+
+    fn site_paths(&self, site: &DomainConfig) -> Vec<String> {
+        vec![
+            site.config_path.clone(),
+            Paths::disabled_config(&site.config_path),
+        ]
+    }
+
+    /// Validate every generated file in the watched directory, since
+    /// Traefik skips broken files without telling anyone
+    fn test_config(&self) -> Result<()> {
+        let dir = Paths::traefik_dynamic_dir();
+        log_info(&format!("Validating Traefik configuration in {}...", dir));
+
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(_) => return Ok(()),
+        };
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("yml") {
+                continue;
+            }
+            let contents = fs::read_to_string(&path)?;
+            if !contents.starts_with(GENERATED_MARKER) {
+                continue;
+            }
+
+            let config = Self::validate(&contents).map_err(|e| {
+                Error::Config(format!("Invalid Traefik config {}: {}", path.display(), e))
+            })?;
+            for certificate in &config.tls.certificates {
+                for file in [&certificate.cert_file, &certificate.key_file] {
+                    if !Path::new(file).exists() {
+                        return Err(Error::Config(format!(
+                            "Invalid Traefik config {}: {} does not exist",
+                            path.display(),
+                            file
+                        )));
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Traefik watches the directory and picks up changes by itself
+    fn reload(&self) -> Result<()> {
+        log_verbose("Traefik reloads its file provider automatically");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AccessPolicy;
+
+    fn site(domain: &str, port: u16) -> DomainConfig {
+        DomainConfig {
+            domain: domain.to_string(),
+            port,
+            server: ServerKind::Traefik,
+            ssl_cert_path: Paths::ssl_cert(ServerKind::Traefik, domain),
+            ssl_key_path: Paths::ssl_key(ServerKind::Traefik, domain),
+            config_path: Paths::site_config(ServerKind::Traefik, SiteKind::Http, domain),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_default_config_validates() {
+        let mut app = site("app.test", 3000);
+        app.basic_auth = true;
+        app.gzip = true;
+        app.headers.cors_origins = vec!["https://ui.test".to_string()];
+        app.access = AccessPolicy {
+            allow: vec!["10.0.0.0/8".to_string()],
+            deny: vec!["all".to_string()],
+            rate_limit: Some("300r/m".to_string()),
+            burst: Some(20),
+        };
+
+        let yaml = TraefikConfig::new().generate_config(&app, None).unwrap();
+        assert!(yaml.starts_with("# Generated by localstacker for app.test\n"));
+        assert_eq!(app.config_path, "/etc/traefik/dynamic/app.test.yml");
+
+        let config = TraefikConfig::validate(&yaml).unwrap();
+        let name = TraefikConfig::name(&app);
+        let router = &config.http.routers[&name];
+        assert_eq!(router.rule, "Host(`app.test`)");
+        assert_eq!(router.entry_points, ["websecure"]);
+        let middlewares: Vec<String> = ["allow", "ratelimit", "auth", "headers", "compress"]
+            .iter()
+            .map(|kind| format!("{}-{}", name, kind))
+            .collect();
+        assert_eq!(router.middlewares, middlewares);
+        assert_eq!(
+            config.http.routers[&format!("{}-http", name)].middlewares,
+            [format!("{}-https-redirect", name)]
+        );
+        assert_eq!(
+            config.http.services[&name].load_balancer.servers[0].url,
+            "http://127.0.0.1:3000"
+        );
+        let rate_limit = config.http.middlewares[&format!("{}-ratelimit", name)].rate_limit.as_ref().unwrap();
+        assert_eq!((rate_limit.average, rate_limit.period.as_str()), (300, "1m"));
+        assert_eq!(config.tls.certificates[0].cert_file, "/etc/traefik/ssl/app.test.pem");
+
+        assert!(name.starts_with("app-test-"));
+        assert_ne!(TraefikConfig::name(&site("a-b.test", 0)), TraefikConfig::name(&site("a.b-test", 0)));
+
+        app.access.deny = vec!["192.168.0.0/16".to_string()];
+        assert!(TraefikConfig::new().generate_config(&app, None).is_err());
+    }
+
+    #[test]
+    fn test_validate_rejects_broken_files() {
+        let mut old = site("old.test", 0);
+        old.kind = SiteKind::Redirect;
+        old.redirect = Some(RedirectTarget {
+            to: "https://new.test".to_string(),
+            code: 302,
+            preserve_path: true,
+        });
+        let yaml = TraefikConfig::new().generate_config(&old, None).unwrap();
+        let config = TraefikConfig::validate(&yaml).unwrap();
+        let middleware = format!("{}-redirect", TraefikConfig::name(&old));
+        let redirect = config.http.middlewares[&middleware].redirect_regex.as_ref().unwrap();
+        assert_eq!(redirect.replacement, "https://new.test${1}");
+        assert!(!redirect.permanent);
+
+        let typo = yaml.replace("entryPoints", "entrypoints");
+        assert!(TraefikConfig::validate(&typo).unwrap_err().contains("entrypoints"));
+
+        let dangling = "http:\n  routers:\n    app:\n      rule: Host(`app.test`)\n      entryPoints: [websecure]\n      service: missing\n";
+        assert!(TraefikConfig::validate(dangling).unwrap_err().contains("undefined service missing"));
+    }
+
+    #[test]
+    fn test_templates_are_checked() {
+        let path = std::env::temp_dir().join(format!("localstacker-traefik-{}.yml", std::process::id()));
+        fs::write(&path, "http:\n  routers:\n    {{domain}}:\n      rule: Host(`{{domain}}`)\n").unwrap();
+
+        let app = site("app.test", 3000);
+        let yaml = TraefikConfig::new().generate_config(&app, path.to_str()).unwrap();
+        fs::remove_file(&path).unwrap();
+        // Carries the marker, so `test_config` validates it like a generated file
+        assert!(yaml.starts_with(GENERATED_MARKER));
+        assert!(TraefikConfig::validate(&yaml).is_err());
+    }
+}
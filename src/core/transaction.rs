@@ -0,0 +1,169 @@
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use crate::error::{Error, Result};
+use crate::utils::{log_info, log_success, log_verbose, log_warning};
+
+/// What a path looked like before the transaction touched it
+enum Snapshot {
+    Missing,
+    File { contents: Vec<u8>, mode: u32 },
+    Symlink(PathBuf),
+    Directory(Vec<(String, Snapshot)>),
+}
+
+/// Backs up the files a command is about to change so they can be put back
+/// exactly as they were when applying the change fails (e.g. `nginx -t`)
+#[derive(Default)]
+pub struct Transaction {
+    snapshots: Vec<(String, Snapshot)>,
+}
+
+impl Transaction {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Remember the current state of a file, symlink or directory.
+    /// Paths that are already tracked keep their first snapshot.
+    pub fn track(&mut self, path: &str) -> Result<()> {
+        if self.snapshots.iter().any(|(p, _)| p == path) {
+            return Ok(());
+        }
+
+        log_verbose(&format!("Backing up {}", path));
+        let snapshot = snapshot(Path::new(path))?;
+        self.snapshots.push((path.to_string(), snapshot));
+        Ok(())
+    }
+
+    pub fn track_all<I, S>(&mut self, paths: I) -> Result<()>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        for path in paths {
+            self.track(path.as_ref())?;
+        }
+        Ok(())
+    }
+
+    /// Run `apply`; if it fails, restore every tracked path and return its error
+    pub fn run<F>(self, apply: F) -> Result<()>
+    where
+        F: FnOnce() -> Result<()>,
+    {
+        let error = match apply() {
+            Ok(()) => return Ok(()),
+            Err(e) => e,
+        };
+
+        log_warning("Applying the change failed, rolling back...");
+        match self.rollback() {
+            Ok(()) => log_success("Previous configuration restored"),
+            Err(rollback_error) => log_warning(&format!("Rollback incomplete: {}", rollback_error)),
+        }
+
+        Err(error)
+    }
+
+    /// Put every tracked path back, most recent first
+    pub fn rollback(self) -> Result<()> {
+        for (path, snapshot) in self.snapshots.iter().rev() {
+            log_info(&format!("Restoring {}", path));
+            restore(Path::new(path), snapshot)
+                .map_err(|e| Error::Config(format!("Failed to restore {}: {}", path, e)))?;
+        }
+        Ok(())
+    }
+}
+
+fn snapshot(path: &Path) -> std::io::Result<Snapshot> {
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Snapshot::Missing),
+        Err(e) => return Err(e),
+    };
+
+    if metadata.file_type().is_symlink() {
+        return Ok(Snapshot::Symlink(fs::read_link(path)?));
+    }
+
+    if metadata.is_dir() {
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(path)? {
+            let entry = entry?;
+            entries.push((
+                entry.file_name().to_string_lossy().to_string(),
+                snapshot(&entry.path())?,
+            ));
+        }
+        return Ok(Snapshot::Directory(entries));
+    }
+
+    Ok(Snapshot::File {
+        contents: fs::read(path)?,
+        mode: metadata.permissions().mode(),
+    })
+}
+
+fn restore(path: &Path, snapshot: &Snapshot) -> std::io::Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(path)?,
+        Ok(_) => fs::remove_file(path)?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+
+    match snapshot {
+        Snapshot::Missing => {}
+        Snapshot::File { contents, mode } => {
+            fs::write(path, contents)?;
+            fs::set_permissions(path, fs::Permissions::from_mode(*mode))?;
+        }
+        Snapshot::Symlink(target) => std::os::unix::fs::symlink(target, path)?,
+        Snapshot::Directory(entries) => {
+            fs::create_dir_all(path)?;
+            for (name, entry) in entries {
+                restore(&path.join(name), entry)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rollback_restores_previous_state() {
+        let dir = std::env::temp_dir().join(format!("localstacker-tx-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let config = dir.join("app.test");
+        let link = dir.join("app.test.enabled");
+        let pages = dir.join("pages");
+        fs::write(&config, "old").unwrap();
+
+        let mut tx = Transaction::new();
+        tx.track_all([&config, &link, &pages].map(|p| p.to_string_lossy().to_string()))
+            .unwrap();
+
+        let result = tx.run(|| {
+            fs::write(&config, "new")?;
+            std::os::unix::fs::symlink(&config, &link)?;
+            fs::create_dir_all(&pages)?;
+            fs::write(pages.join("502.html"), "down")?;
+            Err(Error::Command("Test nginx config failed: boom".to_string()))
+        });
+
+        assert!(result.unwrap_err().to_string().contains("boom"));
+        assert_eq!(fs::read_to_string(&config).unwrap(), "old");
+        assert!(fs::symlink_metadata(&link).is_err());
+        assert!(!pages.exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
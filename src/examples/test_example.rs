@@ -117,7 +117,7 @@ mod config_tests {
             service: None,
             ssl_cert_path: "/etc/nginx/ssl/test.local.pem".to_string(),
             ssl_key_path: "/etc/nginx/ssl/test.local-key.pem".to_string(),
-            nginx_config_path: "/etc/nginx/sites-available/test.local".to_string(),
+            config_path: "/etc/nginx/sites-available/test.local".to_string(),
             created_at: "2025-01-31".to_string(),
             enabled: true,
         };
@@ -136,7 +136,7 @@ mod config_tests {
             service: None,
             ssl_cert_path: "/etc/nginx/ssl/test.local.pem".to_string(),
             ssl_key_path: "/etc/nginx/ssl/test.local-key.pem".to_string(),
-            nginx_config_path: "/etc/nginx/sites-available/test.local".to_string(),
+            config_path: "/etc/nginx/sites-available/test.local".to_string(),
             created_at: "2025-01-31".to_string(),
             enabled: true,
         };
@@ -7,7 +7,7 @@ mod core;
 mod error;
 mod utils;
 
-use commands::{setup, list, remove, status};
+use commands::{adopt, auth, logs, maintenance, redirect, regenerate, serve, setup, list, remove, status, template};
 
 #[derive(Parser)]
 #[command(
@@ -33,26 +33,35 @@ struct Cli {
 #[derive(Subcommand)]
 enum Commands {
     /// Setup SSL for a new domain
-    Setup {
-        /// Domain name to setup
-        #[arg(long)]
-        domain: String,
+    Setup(Box<setup::SetupArgs>),
 
-        /// Backend port to proxy to
-        #[arg(long)]
-        port: u16,
+    /// Redirect a domain to another domain or URL
+    Redirect(redirect::RedirectArgs),
 
-        /// Systemd service name (optional)
+    /// Bring hand-written nginx sites under management without rewriting them
+    Adopt {
+        /// Config file (path or name in sites-available)
+        #[arg(required_unless_present = "all", conflicts_with = "all")]
+        file: Option<String>,
+
+        /// Adopt every site in sites-available
         #[arg(long)]
-        service: Option<String>,
+        all: bool,
+    },
 
-        /// Skip confirmation prompts
-        #[arg(short = 'y', long)]
-        yes: bool,
+    /// Rewrite managed site configs after a template or TLS policy change
+    Regenerate {
+        /// Domain to regenerate
+        #[arg(required_unless_present = "all", conflicts_with = "all")]
+        domain: Option<String>,
 
-        /// Custom nginx config template path
+        /// Regenerate every managed site
         #[arg(long)]
-        template: Option<String>,
+        all: bool,
+
+        /// Overwrite configs that were edited by hand
+        #[arg(long)]
+        force: bool,
     },
 
     /// List all managed SSL configurations
@@ -74,6 +83,10 @@ enum Commands {
         /// Also remove certificates
         #[arg(long)]
         remove_certs: bool,
+
+        /// Overwrite hand-edited configs of other sites that need rewriting
+        #[arg(long)]
+        force: bool,
     },
 
     /// Check status of SSL setup
@@ -82,6 +95,30 @@ enum Commands {
         domain: Option<String>,
     },
 
+    /// Show a domain's access or error log
+    Logs(logs::LogsArgs),
+
+    /// Manage HTTP basic auth users for a domain
+    Auth {
+        #[command(subcommand)]
+        command: auth::AuthCommand,
+    },
+
+    /// Turn maintenance mode on or off for a domain
+    Maintenance {
+        #[command(subcommand)]
+        command: maintenance::MaintenanceCommand,
+    },
+
+    /// Work with nginx config templates
+    Template {
+        #[command(subcommand)]
+        command: template::TemplateCommand,
+    },
+
+    /// Run the built-in TLS reverse proxy for `--server builtin` sites
+    Serve,
+
     /// Install mkcert if not present
     InstallMkcert {
         /// Force reinstall
@@ -104,14 +141,14 @@ fn main() {
         println!();
     }
 
-    let result = match cli.command {
-        Commands::Setup {
-            domain,
-            port,
-            service,
-            yes,
-            template,
-        } => setup::run(domain, port, service, yes, template),
+    let result = config::Settings::init().and_then(|_| match cli.command {
+        Commands::Setup(args) => setup::run(*args),
+
+        Commands::Redirect(args) => redirect::run(args),
+
+        Commands::Adopt { file, all } => adopt::run(file, all),
+
+        Commands::Regenerate { domain, all, force } => regenerate::run(domain, all, force),
 
         Commands::List { detailed } => list::run(detailed),
 
@@ -119,12 +156,23 @@ fn main() {
             domain,
             yes,
             remove_certs,
-        } => remove::run(domain, yes, remove_certs),
+            force,
+        } => remove::run(domain, yes, remove_certs, force),
 
         Commands::Status { domain } => status::run(domain),
 
+        Commands::Logs(args) => logs::run(args),
+
+        Commands::Auth { command } => auth::run(command),
+
+        Commands::Maintenance { command } => maintenance::run(command),
+
+        Commands::Template { command } => template::run(command),
+
+        Commands::Serve => serve::run(),
+
         Commands::InstallMkcert { force } => commands::install_mkcert::run(if force { Some(String::new()) } else { None }),
-    };
+    });
 
     if let Err(e) = result {
         eprintln!("{} {}", "✗".red().bold(), e.to_string().red());
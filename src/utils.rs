@@ -169,6 +169,158 @@ pub fn validate_domain(domain: &str) -> Result<()> {
     Ok(())
 }
 
+pub fn validate_username(user: &str) -> Result<()> {
+    if user.is_empty() {
+        return Err(Error::Validation("Username cannot be empty".to_string()));
+    }
+
+    if user.contains(':') || user.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        return Err(Error::Validation(
+            "Username cannot contain ':' or whitespace".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Validate an nginx `allow`/`deny` source: `all`, an IP address or a CIDR range
+pub fn validate_access_source(source: &str) -> Result<()> {
+    if source == "all" {
+        return Ok(());
+    }
+
+    let (address, prefix) = match source.split_once('/') {
+        Some((address, prefix)) => (address, Some(prefix)),
+        None => (source, None),
+    };
+
+    let ip: std::net::IpAddr = address.parse().map_err(|_| {
+        Error::Validation(format!("Invalid address '{}' (expected IP, CIDR or 'all')", source))
+    })?;
+
+    if let Some(prefix) = prefix {
+        let max = if ip.is_ipv4() { 32 } else { 128 };
+        match prefix.parse::<u8>() {
+            Ok(bits) if bits <= max => {}
+            _ => {
+                return Err(Error::Validation(format!(
+                    "Invalid prefix length in '{}'",
+                    source
+                )))
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Validate an nginx request rate such as `10r/s` or `300r/m`
+pub fn validate_rate(rate: &str) -> Result<()> {
+    let valid = rate
+        .strip_suffix("r/s")
+        .or_else(|| rate.strip_suffix("r/m"))
+        .is_some_and(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()) && n != "0");
+
+    if !valid {
+        return Err(Error::Validation(format!(
+            "Invalid rate '{}' (expected e.g. 10r/s or 300r/m)",
+            rate
+        )));
+    }
+
+    Ok(())
+}
+
+/// Split and validate a `Name: value` response header
+pub fn parse_header(header: &str) -> Result<(String, String)> {
+    let (name, value) = header.split_once(':').ok_or_else(|| {
+        Error::Validation(format!("Invalid header '{}' (expected 'Name: value')", header))
+    })?;
+    let (name, value) = (name.trim(), value.trim());
+
+    let valid_name = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c));
+    if !valid_name {
+        return Err(Error::Validation(format!("Invalid header name '{}'", name)));
+    }
+
+    if value.chars().any(|c| c.is_control()) {
+        return Err(Error::Validation(format!(
+            "Header value for '{}' contains control characters",
+            name
+        )));
+    }
+
+    Ok((name.to_string(), value.to_string()))
+}
+
+/// Validate a CORS origin such as `https://app.test:8443`, or `*`
+pub fn validate_origin(origin: &str) -> Result<()> {
+    if origin == "*" {
+        return Ok(());
+    }
+
+    let host_port = origin
+        .strip_prefix("https://")
+        .or_else(|| origin.strip_prefix("http://"))
+        .ok_or_else(|| {
+            Error::Validation(format!("Origin '{}' must start with http:// or https://", origin))
+        })?;
+
+    let (host, port) = match host_port.split_once(':') {
+        Some((host, port)) => (host, Some(port)),
+        None => (host_port, None),
+    };
+
+    validate_domain(host)
+        .map_err(|_| Error::Validation(format!("Invalid host in origin '{}'", origin)))?;
+
+    if let Some(port) = port {
+        port.parse::<u16>()
+            .map_err(|_| Error::Validation(format!("Invalid port in origin '{}'", origin)))?;
+    }
+
+    Ok(())
+}
+
+/// Validate an nginx time value such as `30s`, `10m` or `1h`
+pub fn validate_duration(duration: &str) -> Result<()> {
+    let digits = duration.trim_end_matches(['s', 'm', 'h', 'd', 'w']);
+    let unit_len = duration.len() - digits.len();
+
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) || unit_len > 1 {
+        return Err(Error::Validation(format!(
+            "Invalid duration '{}' (expected e.g. 30s, 10m or 1h)",
+            duration
+        )));
+    }
+
+    Ok(())
+}
+
+/// Seconds in a duration accepted by `validate_duration`
+pub fn duration_secs(duration: &str) -> Result<u64> {
+    validate_duration(duration)?;
+    let (digits, unit) = duration.split_at(duration.len() - 1);
+    let (digits, unit) = match unit {
+        "s" | "m" | "h" | "d" | "w" => (digits, unit),
+        _ => (duration, "s"),
+    };
+    let multiplier = match unit {
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        "w" => 604800,
+        _ => 1,
+    };
+    let value: u64 = digits
+        .parse()
+        .map_err(|_| Error::Validation(format!("Invalid duration '{}'", duration)))?;
+    Ok(value.saturating_mul(multiplier))
+}
+
 pub fn validate_port(port: u16) -> Result<()> {
     if port == 0 {
         return Err(Error::Validation("Port cannot be 0".to_string()));
@@ -195,6 +347,37 @@ pub fn get_timestamp() -> String {
     chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string()
 }
 
+/// Hex MD5 of generated config contents, used to spot hand edits
+pub fn content_hash(contents: &str) -> String {
+    use md5::{Digest, Md5};
+
+    Md5::digest(contents.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Print a colored unified diff from `old` to `new`
+pub fn print_diff(old_label: &str, new_label: &str, old: &str, new: &str) {
+    let diff = similar::TextDiff::from_lines(old, new);
+
+    println!("{}", format!("--- {}", old_label).red().bold());
+    println!("{}", format!("+++ {}", new_label).green().bold());
+
+    for hunk in diff.unified_diff().context_radius(3).iter_hunks() {
+        println!("{}", hunk.header().to_string().cyan());
+        for change in hunk.iter_changes() {
+            let line = change.to_string_lossy();
+            let line = line.trim_end_matches('\n');
+            match change.tag() {
+                similar::ChangeTag::Delete => println!("{}", format!("-{}", line).red()),
+                similar::ChangeTag::Insert => println!("{}", format!("+{}", line).green()),
+                similar::ChangeTag::Equal => println!(" {}", line),
+            }
+        }
+    }
+}
+
 pub mod chrono {
     use std::time::{SystemTime, UNIX_EPOCH};
 